@@ -0,0 +1,8 @@
+SELECT
+  f.time,
+  f.expectedKwh AS `Expected Energy Production`,
+  f.cloudCover AS `Forecast Cloud Cover`
+FROM solar.production_forecast_hourly AS f
+WHERE 
+  f.issueTime = ( SELECT MAX(issueTime) FROM solar.production_forecast_hourly )
+ORDER BY f.time;
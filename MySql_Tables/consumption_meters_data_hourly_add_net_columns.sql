@@ -0,0 +1,5 @@
+-- Adds consumption meter net counter columns to existing consumption_meters_data_hourly table.  Required for retention
+-- downsampling after the net counter (used by energy reconciliation) was added to hourly rollups.
+ALTER TABLE solar.consumption_meters_data_hourly
+  ADD COLUMN net_ltea_3phsum_kwh DOUBLE AFTER neg_ltea_3phsum_kwh_delta,
+  ADD COLUMN net_ltea_3phsum_kwh_delta DOUBLE AFTER net_ltea_3phsum_kwh;
@@ -0,0 +1,16 @@
+-- Lifetime energy counter resets and implausible jumps found at PVS6 ingest (pvs6 max_inverter_kw / max_meter_kw).
+-- counter is the *_ltea_3phsum_kwh column of the device's table.  event_time is the data_time of the reading after the step,
+-- previous_time the reading before it.  delta_kwh = kwh - previous_kwh is the step energy calculations remove from readings
+-- at or after event_time.  Delete a row to count its step as energy again.
+CREATE TABLE IF NOT EXISTS solar.counter_events (
+  serial VARCHAR(64) NOT NULL,
+  counter VARCHAR(32) NOT NULL,
+  event_time DATETIME NOT NULL,
+  event_type VARCHAR(8) NOT NULL,
+  previous_time DATETIME NOT NULL,
+  previous_kwh DOUBLE NOT NULL,
+  kwh DOUBLE NOT NULL,
+  delta_kwh DOUBLE NOT NULL,
+  PRIMARY KEY ( serial, counter, event_time ),
+  INDEX ( counter, event_time )
+);
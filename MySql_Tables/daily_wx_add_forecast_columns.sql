@@ -0,0 +1,7 @@
+-- Adds daily forecast fields to existing daily_wx table.  Required for daily_wx uploads after multi-day forecast ingestion was added.
+ALTER TABLE solar.daily_wx
+  ADD COLUMN summary VARCHAR(255) AFTER time,
+  ADD COLUMN icon VARCHAR(64) AFTER summary,
+  ADD COLUMN precipProbability FLOAT AFTER precipAccumulation,
+  ADD COLUMN cloudCover FLOAT,
+  ADD COLUMN uvIndex FLOAT;
@@ -0,0 +1,20 @@
+-- Daily Pirate Weather forecast history.  One row per forecast day (time) per forecast retrieval (issueTime)
+-- so changes in each day's forecast over successive retrievals are kept.
+CREATE TABLE IF NOT EXISTS solar.daily_wx_forecast (
+  latitude DOUBLE NOT NULL,
+  longitude DOUBLE NOT NULL,
+  issueTime DATETIME NOT NULL,
+  time DATETIME NOT NULL,
+  summary VARCHAR(255),
+  icon VARCHAR(64),
+  sunriseTime DATETIME,
+  sunsetTime DATETIME,
+  precipAccumulation FLOAT,
+  precipProbability FLOAT,
+  temperatureMin FLOAT,
+  temperatureMax FLOAT,
+  cloudCover FLOAT,
+  uvIndex FLOAT,
+  PRIMARY KEY ( latitude, longitude, issueTime, time ),
+  INDEX idx_daily_wx_forecast_time ( time )
+);
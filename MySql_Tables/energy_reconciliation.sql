@@ -0,0 +1,19 @@
+-- Energy reconciliation (reconciliation section) per local day.  Energy is from lifetime counter readings at each local midnight.
+-- inverters is the number of inverters with readings for the day.  production_difference_kwh = inverter_kwh - production_kwh.
+-- consumption_difference_kwh = import_kwh - export_kwh - net_kwh (consumption meter pos / neg / net counters).
+-- *_flagged is true when the difference is beyond tolerance, NULL when either side has no readings.
+CREATE TABLE IF NOT EXISTS solar.energy_reconciliation (
+  date DATE NOT NULL,
+  inverters INT NOT NULL,
+  inverter_kwh DOUBLE,
+  production_kwh DOUBLE,
+  production_difference_kwh DOUBLE,
+  production_flagged BOOLEAN,
+  import_kwh DOUBLE,
+  export_kwh DOUBLE,
+  net_kwh DOUBLE,
+  consumption_difference_kwh DOUBLE,
+  consumption_flagged BOOLEAN,
+  calc_time DATETIME NOT NULL,
+  PRIMARY KEY ( date )
+);
@@ -0,0 +1,68 @@
+-- Hourly rollups written by retention (retention section of config.yml) before raw PVS6 rows are deleted.
+-- data_time is the start of the hour.  Readings exactly on the hour belong to the hour before (same as the raw energy deltas).
+-- Reading columns are the hourly average.  Lifetime energy counter columns are the last reading in the hour, <counter>_time
+-- is that reading's data_time and <counter>_delta is the energy (kWh) produced / used in the hour.  For net counters
+-- (net_ltea_3phsum_kwh) the delta is the signed change in the hour, negative while exporting.
+
+CREATE TABLE IF NOT EXISTS solar.production_meters_data_hourly (
+  serial VARCHAR(64) NOT NULL,
+  data_time DATETIME NOT NULL,
+  samples INT UNSIGNED NOT NULL,
+  freq_hz DOUBLE,
+  i_a DOUBLE,
+  p_3phsum_kw DOUBLE,
+  q_3phsum_kvar DOUBLE,
+  s_3phsum_kva DOUBLE,
+  tot_pf_rto DOUBLE,
+  v12_v DOUBLE,
+  net_ltea_3phsum_kwh DOUBLE,
+  net_ltea_3phsum_kwh_time DATETIME,
+  net_ltea_3phsum_kwh_delta DOUBLE,
+  PRIMARY KEY ( serial, data_time )
+);
+
+CREATE TABLE IF NOT EXISTS solar.consumption_meters_data_hourly (
+  serial VARCHAR(64) NOT NULL,
+  data_time DATETIME NOT NULL,
+  samples INT UNSIGNED NOT NULL,
+  freq_hz DOUBLE,
+  i1_a DOUBLE,
+  i2_a DOUBLE,
+  p_3phsum_kw DOUBLE,
+  p1_kw DOUBLE,
+  p2_kw DOUBLE,
+  q_3phsum_kvar DOUBLE,
+  s_3phsum_kva DOUBLE,
+  tot_pf_rto DOUBLE,
+  v12_v DOUBLE,
+  v1n_v DOUBLE,
+  v2n_v DOUBLE,
+  pos_ltea_3phsum_kwh DOUBLE,
+  pos_ltea_3phsum_kwh_time DATETIME,
+  pos_ltea_3phsum_kwh_delta DOUBLE,
+  neg_ltea_3phsum_kwh DOUBLE,
+  neg_ltea_3phsum_kwh_time DATETIME,
+  neg_ltea_3phsum_kwh_delta DOUBLE,
+  net_ltea_3phsum_kwh DOUBLE,
+  net_ltea_3phsum_kwh_time DATETIME,
+  net_ltea_3phsum_kwh_delta DOUBLE,
+  PRIMARY KEY ( serial, data_time )
+);
+
+CREATE TABLE IF NOT EXISTS solar.inverters_data_hourly (
+  serial VARCHAR(64) NOT NULL,
+  data_time DATETIME NOT NULL,
+  samples INT UNSIGNED NOT NULL,
+  freq_hz DOUBLE,
+  i_3phsum_a DOUBLE,
+  i_mppt1_a DOUBLE,
+  p_3phsum_kw DOUBLE,
+  p_mppt1_kw DOUBLE,
+  t_htsnk_degc DOUBLE,
+  v_mppt1_v DOUBLE,
+  vln_3phavg_v DOUBLE,
+  ltea_3phsum_kwh DOUBLE,
+  ltea_3phsum_kwh_time DATETIME,
+  ltea_3phsum_kwh_delta DOUBLE,
+  PRIMARY KEY ( serial, data_time )
+);
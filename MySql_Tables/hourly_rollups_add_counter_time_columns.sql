@@ -0,0 +1,12 @@
+-- Adds the time of the last counter reading in each hour to existing hourly rollup tables.  Required for retention
+-- downsampling after counter columns changed from the highest to the last reading in the hour.  Run after
+-- consumption_meters_data_hourly_add_net_columns.sql.  Rows downsampled before this have no time and aren't used as the reading
+-- before a window, so the first hour downsampled after upgrading has no energy.
+ALTER TABLE solar.production_meters_data_hourly
+  ADD COLUMN net_ltea_3phsum_kwh_time DATETIME AFTER net_ltea_3phsum_kwh;
+ALTER TABLE solar.consumption_meters_data_hourly
+  ADD COLUMN pos_ltea_3phsum_kwh_time DATETIME AFTER pos_ltea_3phsum_kwh,
+  ADD COLUMN neg_ltea_3phsum_kwh_time DATETIME AFTER neg_ltea_3phsum_kwh,
+  ADD COLUMN net_ltea_3phsum_kwh_time DATETIME AFTER net_ltea_3phsum_kwh;
+ALTER TABLE solar.inverters_data_hourly
+  ADD COLUMN ltea_3phsum_kwh_time DATETIME AFTER ltea_3phsum_kwh;
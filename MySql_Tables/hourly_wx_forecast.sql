@@ -0,0 +1,29 @@
+-- Hourly Pirate Weather forecast.  One row per forecast hour (time) per forecast retrieval (issueTime)
+-- so successive forecasts for the same hour can be compared against actual production.
+CREATE TABLE IF NOT EXISTS solar.hourly_wx_forecast (
+  latitude DOUBLE NOT NULL,
+  longitude DOUBLE NOT NULL,
+  issueTime DATETIME NOT NULL,
+  time DATETIME NOT NULL,
+  summary VARCHAR(255),
+  icon VARCHAR(64),
+  precipIntensity FLOAT,
+  precipProbability FLOAT,
+  precipAccumulation FLOAT,
+  precipType VARCHAR(32),
+  temperature FLOAT,
+  apparentTemperature FLOAT,
+  dewPoint FLOAT,
+  humidity FLOAT,
+  pressure FLOAT,
+  windSpeed FLOAT,
+  windGust FLOAT,
+  windBearing FLOAT,
+  cloudCover FLOAT,
+  uvIndex FLOAT,
+  visibility FLOAT,
+  ozone FLOAT,
+  smoke FLOAT,
+  PRIMARY KEY ( latitude, longitude, issueTime, time ),
+  INDEX idx_hourly_wx_forecast_time ( time )
+);
@@ -0,0 +1,12 @@
+-- Minutely Pirate Weather precipitation forecast (next hour).  One row per forecast minute (time) per forecast retrieval (issueTime).
+CREATE TABLE IF NOT EXISTS solar.minutely_wx_forecast (
+  latitude DOUBLE NOT NULL,
+  longitude DOUBLE NOT NULL,
+  issueTime DATETIME NOT NULL,
+  time DATETIME NOT NULL,
+  precipIntensity FLOAT,
+  precipProbability FLOAT,
+  precipIntensityError FLOAT,
+  precipType VARCHAR(32),
+  PRIMARY KEY ( latitude, longitude, issueTime, time )
+);
@@ -0,0 +1,35 @@
+-- Solar production forecast tables.  Written by the production forecast (forecast section of config.yml).
+-- issueTime is the time the forecast was run.  Use the latest issueTime for current forecast.
+
+-- Fitted model parameters for each forecast run.
+CREATE TABLE IF NOT EXISTS solar.production_forecast_model (
+  fitTime DATETIME NOT NULL,
+  k DOUBLE NOT NULL,
+  cloudFactor DOUBLE NOT NULL,
+  samples INT UNSIGNED NOT NULL,
+  rmseKwh DOUBLE NOT NULL,
+  PRIMARY KEY ( fitTime )
+);
+
+-- Expected production per hour.  time is start of hour.  source is the cloud cover forecast used ('hourly' or 'daily').
+CREATE TABLE IF NOT EXISTS solar.production_forecast_hourly (
+  issueTime DATETIME NOT NULL,
+  time DATETIME NOT NULL,
+  clearSkyKwhM2 DOUBLE NOT NULL,
+  cloudCover DOUBLE NOT NULL,
+  expectedKwh DOUBLE NOT NULL,
+  source VARCHAR(8) NOT NULL,
+  PRIMARY KEY ( issueTime, time ),
+  INDEX idx_production_forecast_hourly_time ( time )
+);
+
+-- Expected production per local day (forecast timezone).
+CREATE TABLE IF NOT EXISTS solar.production_forecast_daily (
+  issueTime DATETIME NOT NULL,
+  date DATE NOT NULL,
+  expectedKwh DOUBLE NOT NULL,
+  hoursFromHourly INT UNSIGNED NOT NULL,
+  hoursFromDaily INT UNSIGNED NOT NULL,
+  PRIMARY KEY ( issueTime, date ),
+  INDEX idx_production_forecast_daily_date ( date )
+);
@@ -0,0 +1,16 @@
+-- Expected clear-sky output and performance ratio for each production meter reading.  Same serial / data_time as production_meters_data.
+-- performance_ratio is NULL when expected output is under 5% of capacity (sun near or below horizon).
+-- measured_ghi_w_m2 / measured_performance_ratio are from the personal weather station (pws section), NULL without one.
+CREATE TABLE IF NOT EXISTS solar.production_performance (
+  serial VARCHAR(64) NOT NULL,
+  data_time DATETIME NOT NULL,
+  sun_zenith_deg DOUBLE NOT NULL,
+  sun_azimuth_deg DOUBLE NOT NULL,
+  clear_sky_ghi_w_m2 DOUBLE NOT NULL,
+  poa_irradiance_w_m2 DOUBLE NOT NULL,
+  expected_p_kw DOUBLE NOT NULL,
+  performance_ratio DOUBLE,
+  measured_ghi_w_m2 DOUBLE,
+  measured_performance_ratio DOUBLE,
+  PRIMARY KEY ( serial, data_time )
+);
@@ -0,0 +1,5 @@
+-- Adds personal weather station columns to existing production_performance table.  Required for production_performance
+-- uploads after personal weather station ingestion was added.
+ALTER TABLE solar.production_performance
+  ADD COLUMN measured_ghi_w_m2 DOUBLE AFTER performance_ratio,
+  ADD COLUMN measured_performance_ratio DOUBLE AFTER measured_ghi_w_m2;
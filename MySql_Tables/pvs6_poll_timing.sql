@@ -0,0 +1,14 @@
+-- Timing of each PVS6 poll.  Used to check get_device_offset lands PVS6 data_time just after the interval boundary.
+-- scheduled_time is the interval boundary + offset_ms.  data_lag_ms is data_time - ( scheduled_time - offset_ms ), the lag from
+-- the interval boundary (data_time has 1 second resolution).  data_time is NULL if the PVS6 didn't respond.
+CREATE TABLE IF NOT EXISTS solar.pvs6_poll_timing (
+  scheduled_time DATETIME(3) NOT NULL,
+  request_time DATETIME(3) NOT NULL,
+  response_time DATETIME(3) NOT NULL,
+  data_time DATETIME,
+  latency_ms INT NOT NULL,
+  data_lag_ms INT,
+  offset_ms INT,
+  success BOOLEAN NOT NULL,
+  PRIMARY KEY ( scheduled_time )
+);
@@ -0,0 +1,17 @@
+-- Personal weather station readings (pws section), averaged for each production meter reading.  Same data_time as
+-- production_meters_data.  samples is the number of station readings averaged, from first_reading_time to last_reading_time.
+-- Units are W/m², °C and m/s.  humidity is a fraction 0 to 1, wind_bearing degrees.
+CREATE TABLE IF NOT EXISTS solar.pws_data (
+  data_time DATETIME NOT NULL,
+  samples INT NOT NULL,
+  first_reading_time DATETIME NOT NULL,
+  last_reading_time DATETIME NOT NULL,
+  solar_radiation_w_m2 DOUBLE,
+  temperature_c DOUBLE,
+  humidity DOUBLE,
+  wind_speed_m_s DOUBLE,
+  wind_gust_m_s DOUBLE,
+  wind_bearing DOUBLE,
+  uv_index DOUBLE,
+  PRIMARY KEY ( data_time )
+);
@@ -0,0 +1,14 @@
+-- PVS6 meter and inverter values outside their plausibility range (plausibility section of config.yml, action "quarantine").
+-- device is production_meter, consumption_meter or inverter and field the column of the device's table, which is stored as
+-- NULL for that reading.  value is NULL for values that weren't finite numbers.  min_value / max_value is the range it failed.
+CREATE TABLE IF NOT EXISTS solar.quarantined_readings (
+  serial VARCHAR(64) NOT NULL,
+  data_time DATETIME NOT NULL,
+  device VARCHAR(32) NOT NULL,
+  field VARCHAR(32) NOT NULL,
+  value DOUBLE NULL,
+  min_value DOUBLE NULL,
+  max_value DOUBLE NULL,
+  PRIMARY KEY ( serial, data_time, field ),
+  INDEX ( device, field, data_time )
+);
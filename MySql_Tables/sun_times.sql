@@ -0,0 +1,10 @@
+-- Sunrise, solar noon and sunset calculated locally from site lat / long.  One row per day (date at the site).
+CREATE TABLE IF NOT EXISTS solar.sun_times (
+  latitude DOUBLE NOT NULL,
+  longitude DOUBLE NOT NULL,
+  date DATE NOT NULL,
+  sunriseTime DATETIME,
+  solarNoonTime DATETIME NOT NULL,
+  sunsetTime DATETIME,
+  PRIMARY KEY ( latitude, longitude, date )
+);
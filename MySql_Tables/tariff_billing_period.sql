@@ -0,0 +1,34 @@
+-- Time-of-use tariff (tariff section) totals per billing period, period_start to period_end (exclusive, local dates).
+-- energy_cost / energy_credit are after netting.  fixed_charges are the monthly charge and daily charge for days so far.
+-- net_cost = energy_cost - energy_credit + fixed_charges.  no_solar_cost includes fixed_charges.  savings = no_solar_cost - net_cost.
+-- complete is false until period_end has passed.  Periods start on meter read dates (read_dates), otherwise on billing_day.
+-- meter_*_kwh are from the lifetime counters at period_start and period_end, as the utility reads the meter (NULL without
+-- readings).  true_up_* are totals for the true-up year starting true_up_start, up to period_end.  Rows overlapping the
+-- recalculated days that no longer match read_dates / billing_day are deleted by the tariff job.
+CREATE TABLE IF NOT EXISTS solar.tariff_billing_period (
+  period_start DATE NOT NULL,
+  period_end DATE NOT NULL,
+  days INT NOT NULL,
+  complete BOOLEAN NOT NULL,
+  import_kwh DOUBLE NOT NULL,
+  export_kwh DOUBLE NOT NULL,
+  production_kwh DOUBLE NOT NULL,
+  load_kwh DOUBLE NOT NULL,
+  energy_cost DOUBLE NOT NULL,
+  energy_credit DOUBLE NOT NULL,
+  fixed_charges DOUBLE NOT NULL,
+  net_cost DOUBLE NOT NULL,
+  no_solar_cost DOUBLE NOT NULL,
+  savings DOUBLE NOT NULL,
+  true_up_start DATE NOT NULL,
+  meter_import_kwh DOUBLE,
+  meter_export_kwh DOUBLE,
+  meter_net_kwh DOUBLE,
+  true_up_import_kwh DOUBLE NOT NULL,
+  true_up_export_kwh DOUBLE NOT NULL,
+  true_up_net_kwh DOUBLE NOT NULL,
+  true_up_net_cost DOUBLE NOT NULL,
+  true_up_savings DOUBLE NOT NULL,
+  calc_time DATETIME NOT NULL,
+  PRIMARY KEY ( period_start )
+);
@@ -0,0 +1,12 @@
+-- Adds meter read and true-up columns to existing tariff_billing_period table.  Required for tariff_billing_period uploads
+-- after billing cycle / true-up reports were added.  Existing rows get true_up_start 1970-01-01 until recalculated.
+ALTER TABLE solar.tariff_billing_period
+  ADD COLUMN true_up_start DATE NOT NULL DEFAULT '1970-01-01' AFTER savings,
+  ADD COLUMN meter_import_kwh DOUBLE AFTER true_up_start,
+  ADD COLUMN meter_export_kwh DOUBLE AFTER meter_import_kwh,
+  ADD COLUMN meter_net_kwh DOUBLE AFTER meter_export_kwh,
+  ADD COLUMN true_up_import_kwh DOUBLE NOT NULL DEFAULT 0 AFTER meter_net_kwh,
+  ADD COLUMN true_up_export_kwh DOUBLE NOT NULL DEFAULT 0 AFTER true_up_import_kwh,
+  ADD COLUMN true_up_net_kwh DOUBLE NOT NULL DEFAULT 0 AFTER true_up_export_kwh,
+  ADD COLUMN true_up_net_cost DOUBLE NOT NULL DEFAULT 0 AFTER true_up_net_kwh,
+  ADD COLUMN true_up_savings DOUBLE NOT NULL DEFAULT 0 AFTER true_up_net_cost;
@@ -0,0 +1,19 @@
+-- Time-of-use tariff (tariff section) cost of each hour.  time is the start of the hour (UTC).  Energy in kWh, prices and costs
+-- in the tariff's currency.  load_kwh is home use (import - export + production).  no_solar_cost is load_kwh at the import price
+-- and savings is no_solar_cost - ( cost - credit ).  Recalculated (replaced) by each tariff run.
+CREATE TABLE IF NOT EXISTS solar.tariff_interval (
+  time DATETIME NOT NULL,
+  season VARCHAR(64) NOT NULL,
+  period VARCHAR(64) NOT NULL,
+  import_kwh DOUBLE NOT NULL,
+  export_kwh DOUBLE NOT NULL,
+  production_kwh DOUBLE NOT NULL,
+  load_kwh DOUBLE NOT NULL,
+  import_price DOUBLE NOT NULL,
+  export_price DOUBLE NOT NULL,
+  cost DOUBLE NOT NULL,
+  credit DOUBLE NOT NULL,
+  no_solar_cost DOUBLE NOT NULL,
+  savings DOUBLE NOT NULL,
+  PRIMARY KEY ( time )
+);
@@ -0,0 +1,16 @@
+-- Pirate Weather alerts.  Deduplicated by alertId (alert uri, or title/time/regions if alert has no uri).
+CREATE TABLE IF NOT EXISTS solar.wx_alerts (
+  alertId VARCHAR(512) NOT NULL,
+  latitude DOUBLE NOT NULL,
+  longitude DOUBLE NOT NULL,
+  title VARCHAR(255) NOT NULL,
+  severity VARCHAR(32),
+  regions TEXT,
+  time DATETIME NOT NULL,
+  expires DATETIME,
+  description TEXT,
+  uri VARCHAR(512),
+  firstSeen DATETIME NOT NULL,
+  PRIMARY KEY ( alertId ),
+  INDEX idx_wx_alerts_time ( time )
+);
@@ -0,0 +1,408 @@
+/*
+Optional read-only HTTP API (api section of config.yml).  All responses are JSON.
+    GET /status                                 latest PVS6 devices response and current weather
+    GET /inverters                              latest reading of each inverter
+    GET /inverters/{serial}/history?from&to     inverter readings from the solar db.  Default is the last day
+    GET /energy/daily?from&to                   production, grid import and grid export kWh per local day.  Default is the last 7 days
+    GET /healthz                                last successful PVS6 poll and weather fetch, solar db connectivity, job
+                                                schedule status, task restarts and counts of out of range PVS6 values
+                                                (plausibility).  503 status if not healthy.  There is no spool depth:
+                                                failed db writes are logged and dropped, not spooled to disk
+    GET /stream                                 Server-Sent Events.  "pvs6" event (same as /status without weather) for every
+                                                PVS6 response and "weather" event for every weather update, as they are received
+from / to are dates or date times in the api timezone (or RFC 3339).  to is exclusive.
+Latest data comes from an in-memory snapshot updated by the collectors, history from the solar db.
+When stopped (shutdown or config reload) the api stops accepting connections and stream responses end, so open streams
+don't hold up the exit or restart.
+Stream clients each read from a bounded broadcast queue.  A client that falls behind skips the oldest events and is sent a
+"lagged" event with the number skipped, so a slow client never holds up the collectors or other clients.
+*/
+
+// USE STATEMENTS
+    use std::{ collections::BTreeMap, convert::Infallible, net::SocketAddr, sync::{ Arc, RwLock } };
+    use axum::{ Json, Router, extract::{ Path, Query, State }, http::StatusCode, routing::get,
+        response::{ IntoResponse, Response, sse::{ Event, KeepAlive, Sse } } };
+    use chrono::{ DateTime, DurationRound, TimeDelta, Utc };
+    use chrono_tz::Tz;
+    use log::{ debug, error, info };
+    use serde::Deserialize;
+    use serde_json::json;
+    use tokio::sync::broadcast;
+    use tokio_stream::{ Stream, StreamExt, wrappers::{ BroadcastStream, WatchStream, errors::BroadcastStreamRecvError } };
+
+    use crate::{ CurrentWx, Inverter, Pvs6DevicesResponse, energy, export::{ self, ExportError, ExportRecord }, scheduler::Scheduler,
+        shutdown::Shutdown, supervisor::TaskSupervisor, validation::ConfigError };
+
+// CONSTANTS
+    // counter readings further apart than this are not used for energy
+    const MAX_ENERGY_GAP: TimeDelta = TimeDelta::hours(1);
+    // events queued per stream client before the oldest are skipped
+    const STREAM_CAPACITY: usize = 16;
+    // a job whose next run is this far in the past has stopped (task panicked or hung)
+    const JOB_OVERDUE_GRACE: TimeDelta = TimeDelta::minutes(5);
+    // longest wait for the solar db health check
+    const HEALTH_DB_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+#[derive(Debug, Deserialize, Clone )]
+pub struct ApiConf {
+    // address and port to listen on.  Use 0.0.0.0 to listen on all interfaces
+    #[serde( default = "default_api_bind" )]
+    pub bind: String,
+    // timezone of from / to and of daily energy days
+    #[serde( default = "default_api_timezone" )]
+    pub timezone: String,
+    // longest from / to range allowed for history requests
+    #[serde( default = "default_api_max_history_days" )]
+    pub max_history_days: i64,
+    // /healthz is unhealthy if the last successful PVS6 poll / weather fetch is older than this (minutes)
+    #[serde( with = "crate::integer_to_chrono_time_delta_minutes", default = "default_api_health_max_pvs6_age" )]
+    pub health_max_pvs6_age: TimeDelta,
+    #[serde( with = "crate::integer_to_chrono_time_delta_minutes", default = "default_api_health_max_wx_age" )]
+    pub health_max_wx_age: TimeDelta,
+}
+fn default_api_bind() -> String {
+    "127.0.0.1:8080".to_string()
+}
+fn default_api_timezone() -> String {
+    "UTC".to_string()
+}
+fn default_api_max_history_days() -> i64 {
+    31
+}
+fn default_api_health_max_pvs6_age() -> TimeDelta {
+    TimeDelta::minutes(60)
+}
+fn default_api_health_max_wx_age() -> TimeDelta {
+    TimeDelta::minutes(60)
+}
+
+// Latest data from the collectors, shared with the api.  Updates are also broadcast to stream clients as ( event name, json ).
+#[derive(Clone, Debug)]
+pub struct Latest {
+    data: Arc<RwLock<LatestData>>,
+    events: broadcast::Sender<(&'static str, Arc<String>)>,
+}
+impl Default for Latest {
+    fn default() -> Self {
+        Self { data: Arc::default(), events: broadcast::channel(STREAM_CAPACITY).0 }
+    }
+}
+#[derive(Clone, Debug, Default)]
+struct LatestData {
+    pvs6_time: Option<DateTime<Utc>>,
+    pvs6: Option<Pvs6DevicesResponse>,
+    wx_time: Option<DateTime<Utc>>,
+    wx: Option<CurrentWx>,
+    // out of range PVS6 values (plausibility) since startup, per "device.field"
+    out_of_range: BTreeMap<String, u64>,
+}
+impl Latest {
+    pub fn set_pvs6(&self, pvs6: &Pvs6DevicesResponse) {
+        let now = Utc::now();
+        if let Ok(mut data) = self.data.write() {
+            data.pvs6_time = Some(now);
+            data.pvs6 = Some( pvs6.clone() );
+        }
+        self.broadcast( "pvs6", || pvs6_json(Some(pvs6), Some(now)) );
+    }
+    pub fn set_wx(&self, wx: &CurrentWx) {
+        if let Ok(mut data) = self.data.write() {
+            data.wx_time = Some( Utc::now() );
+            data.wx = Some( wx.clone() );
+        }
+        self.broadcast( "weather", || wx.to_json() );
+    }
+    pub fn add_out_of_range(&self, counts: BTreeMap<String, u64>) {
+        if let Ok(mut data) = self.data.write() {
+            for (field, count) in counts {
+                *data.out_of_range.entry(field).or_insert(0) += count;
+            }
+        }
+    }
+    fn broadcast<F: FnOnce() -> serde_json::Value>(&self, event: &'static str, to_json: F) {
+        // json is only built when a stream client is connected
+        if self.events.receiver_count() > 0 {
+            let _ = self.events.send( (event, Arc::new( to_json().to_string() )) );
+        }
+    }
+    fn get(&self) -> LatestData {
+        match self.data.read() {
+            Ok(data) => data.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ApiState {
+    solar_pool: Option<sqlx::Pool<sqlx::MySql>>,
+    latest: Latest,
+    scheduler: Scheduler,
+    supervisor: TaskSupervisor,
+    stop: Shutdown,
+    tz: Tz,
+    max_history: TimeDelta,
+    health_max_pvs6_age: TimeDelta,
+    health_max_wx_age: TimeDelta,
+}
+
+#[derive(Debug, Deserialize)]
+struct RangeParams {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Debug)]
+enum ApiError {
+    BadRequest(String),
+    NoDatabase,
+    Sql(sqlx::Error),
+}
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            ApiError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            ApiError::NoDatabase => (StatusCode::SERVICE_UNAVAILABLE, "Solar database not available".to_string()),
+            ApiError::Sql(sql_eff) => {
+                error!("API database error: {}", sql_eff);
+                (StatusCode::INTERNAL_SERVER_ERROR, "Database error".to_string())
+            },
+        };
+        ( status, Json( json!({ "error": message }) ) ).into_response()
+    }
+}
+impl From<ExportError> for ApiError {
+    fn from(export_eff: ExportError) -> Self {
+        match export_eff {
+            ExportError::Sql(sql_eff) => ApiError::Sql(sql_eff),
+            other => ApiError::BadRequest( other.to_string() ),
+        }
+    }
+}
+
+// FUNCTIONS
+
+pub async fn serve_api(api_conf: ApiConf, solar_pool: Option<sqlx::Pool<sqlx::MySql>>, latest: Latest, scheduler: Scheduler,
+    supervisor: TaskSupervisor, stop: Shutdown) {
+    // bind and timezone verified at startup by verify_api_conf
+    let state = ApiState {
+        solar_pool,
+        latest,
+        scheduler,
+        supervisor,
+        stop: stop.clone(),
+        tz: api_conf.timezone.parse().unwrap_or(Tz::UTC),
+        max_history: TimeDelta::days(api_conf.max_history_days),
+        health_max_pvs6_age: api_conf.health_max_pvs6_age,
+        health_max_wx_age: api_conf.health_max_wx_age,
+    };
+    let router = Router::new()
+        .route("/healthz", get(get_health))
+        .route("/status", get(get_status))
+        .route("/inverters", get(get_inverters))
+        .route("/inverters/{serial}/history", get(get_inverter_history))
+        .route("/energy/daily", get(get_daily_energy))
+        .route("/stream", get(get_stream))
+        .with_state(state);
+
+    let listener = match tokio::net::TcpListener::bind(&api_conf.bind).await {
+        Ok(listener) => listener,
+        Err(bind_eff) => {
+            error!("API couldn't listen on {}. Error: {}", api_conf.bind, bind_eff);
+            return
+        },
+    };
+    info!("API listening on {}", api_conf.bind);
+    let serve = axum::serve(listener, router).with_graceful_shutdown( async move { stop.wait().await } );
+    if let Err(serve_eff) = serve.await {
+        error!("API server stopped. Error: {}", serve_eff);
+    }
+}
+
+async fn get_status(State(state): State<ApiState>) -> Json<serde_json::Value> {
+    let latest = state.latest.get();
+    let mut status = pvs6_json(latest.pvs6.as_ref(), latest.pvs6_time);
+    status["weather"] = json!( latest.wx.as_ref().map( |wx| wx.to_json() ) );
+    Json(status)
+}
+
+async fn get_health(State(state): State<ApiState>) -> (StatusCode, Json<serde_json::Value>) {
+    // healthy when PVS6 and weather data are recent, solar db answers, no job has stopped running and no task is degraded
+    // (crashing repeatedly)
+    // collectors that aren't configured (have no job) are ok
+    let now = Utc::now();
+    let latest = state.latest.get();
+    let job_statuses = state.scheduler.job_statuses();
+    let pvs6_configured = job_statuses.contains_key("pvs6");
+    let wx_configured = job_statuses.contains_key("weather");
+    let pvs6_ok = !pvs6_configured || latest.pvs6_time.is_some_and( |time| now - time <= state.health_max_pvs6_age );
+    let wx_ok = !wx_configured || latest.wx_time.is_some_and( |time| now - time <= state.health_max_wx_age );
+    let db_ok = match &state.solar_pool {
+        Some(sql_pool) => matches!( tokio::time::timeout( HEALTH_DB_TIMEOUT, sqlx::query("SELECT 1").execute(sql_pool) ).await, Ok(Ok(_)) ),
+        None => false,
+    };
+
+    let mut jobs_ok = true;
+    let jobs: serde_json::Map<String, serde_json::Value> = job_statuses.into_iter().map( |(name, status)| {
+        let overdue = status.next_run.is_some_and( |next_run| now - next_run > JOB_OVERDUE_GRACE );
+        jobs_ok &= !overdue;
+        (name, json!({
+            "schedule": status.schedule,
+            "last_run": status.last_run,
+            "next_run": status.next_run,
+            "missed_ticks": status.missed_ticks,
+            "overdue": overdue,
+        }))
+    }).collect();
+
+    let mut tasks_ok = true;
+    let tasks: serde_json::Map<String, serde_json::Value> = state.supervisor.task_statuses().into_iter().map( |(name, status)| {
+        let degraded = status.degraded(now);
+        tasks_ok &= !degraded;
+        (name, json!({
+            "started": status.started,
+            "restarts": status.restarts,
+            "last_failure": status.last_failure,
+            "last_error": status.last_error,
+            "degraded": degraded,
+        }))
+    }).collect();
+
+    let healthy = pvs6_ok && wx_ok && db_ok && jobs_ok && tasks_ok;
+    let status = if healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    ( status, Json( json!({
+        "healthy": healthy,
+        "pvs6": { "ok": pvs6_ok, "configured": pvs6_configured, "last_success": latest.pvs6_time, "out_of_range": latest.out_of_range },
+        "weather": { "ok": wx_ok, "configured": wx_configured, "last_success": latest.wx_time },
+        "database": { "ok": db_ok },
+        "jobs": jobs,
+        "tasks": tasks,
+    })))
+}
+
+fn pvs6_json(pvs6: Option<&Pvs6DevicesResponse>, updated: Option<DateTime<Utc>>) -> serde_json::Value {
+    // devices that weren't in the response (empty serial) are null
+    json!({
+        "updated": updated,
+        "supervisor": pvs6.filter( |pvs6| !pvs6.supervisor.serial.is_empty() ).map( |pvs6| pvs6.supervisor.to_json() ),
+        "production_meter": pvs6.filter( |pvs6| !pvs6.prod_meter.serial.is_empty() ).map( |pvs6| pvs6.prod_meter.to_json() ),
+        "consumption_meter": pvs6.filter( |pvs6| !pvs6.cons_meter.serial.is_empty() ).map( |pvs6| pvs6.cons_meter.to_json() ),
+        "inverters": pvs6.map( |pvs6| inverters_json(&pvs6.inverters) ).unwrap_or_default(),
+    })
+}
+
+async fn get_stream(State(state): State<ApiState>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    // current data first, so a new client doesn't wait for the next poll, then every update as it arrives
+    let receiver = state.latest.events.subscribe();
+    let latest = state.latest.get();
+    let mut initial = vec![ Event::default().event("pvs6").data( pvs6_json(latest.pvs6.as_ref(), latest.pvs6_time).to_string() ) ];
+    if let Some(wx) = &latest.wx {
+        initial.push( Event::default().event("weather").data( wx.to_json().to_string() ) );
+    }
+
+    let updates = BroadcastStream::new(receiver).map( |received| match received {
+        Ok((event, data)) => Ok( Event::default().event(event).data(data.as_str()) ),
+        Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+            debug!("API stream client fell behind.  {} events skipped.", skipped);
+            Ok( Event::default().event("lagged").data( json!({ "skipped": skipped }).to_string() ) )
+        },
+    });
+    // ends the stream once the api is stopped
+    let stop = WatchStream::new( state.stop.subscribe() ).filter( |triggered| *triggered ).map( |_| None );
+    let stream = tokio_stream::iter(initial).map(Ok).chain(updates).map(Some)
+        .merge(stop)
+        .take_while( |event| event.is_some() )
+        .filter_map( |event| event );
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn get_inverters(State(state): State<ApiState>) -> Json<serde_json::Value> {
+    let latest = state.latest.get();
+    Json( json!({
+        "updated": latest.pvs6_time,
+        "inverters": latest.pvs6.as_ref().map( |pvs6| inverters_json(&pvs6.inverters) ).unwrap_or_default(),
+    }))
+}
+
+fn inverters_json(inverters: &[Inverter]) -> Vec<serde_json::Value> {
+    inverters.iter().map( |inverter| inverter.to_json() ).collect()
+}
+
+async fn get_inverter_history(State(state): State<ApiState>, Path(serial): Path<String>, Query(range): Query<RangeParams>)
+    -> Result<Json<serde_json::Value>, ApiError> {
+    let sql_pool = state.solar_pool.as_ref().ok_or(ApiError::NoDatabase)?;
+    let (from, to) = parse_range(&state, &range, TimeDelta::days(1))?;
+    let table = export::read_table::<Inverter>(sql_pool, std::slice::from_ref(&serial), from, to).await?;
+    Ok( Json( json!({
+        "serial": serial,
+        "from": from,
+        "to": to,
+        "readings": table.json_rows(),
+    })))
+}
+
+async fn get_daily_energy(State(state): State<ApiState>, Query(range): Query<RangeParams>) -> Result<Json<serde_json::Value>, ApiError> {
+    // Consumption meter positive counter is energy imported from the grid and negative counter is energy exported to the grid.
+    let sql_pool = state.solar_pool.as_ref().ok_or(ApiError::NoDatabase)?;
+    let (from, to) = parse_range(&state, &range, TimeDelta::days(7))?;
+    // reading before from is needed for the first hour
+    let read_from = from - MAX_ENERGY_GAP;
+
+    let mut days = std::collections::BTreeMap::new();
+    for (name, table, counter) in [
+        ("production_kwh", "production_meters_data", "net_ltea_3phsum_kwh"),
+        ("grid_import_kwh", "consumption_meters_data", "pos_ltea_3phsum_kwh"),
+        ("grid_export_kwh", "consumption_meters_data", "neg_ltea_3phsum_kwh"),
+    ] {
+        let readings = energy::get_counter_readings(sql_pool, table, counter, read_from, to).await.map_err(ApiError::Sql)?;
+        let mut hourly = energy::total_bucket_energy(&readings, TimeDelta::hours(1), MAX_ENERGY_GAP);
+        hourly.retain( |hour_start, _| *hour_start >= from.duration_trunc(TimeDelta::hours(1)).unwrap_or(from) );
+        for (date, kwh) in energy::daily_energy(&hourly, state.tz) {
+            days.entry(date).or_insert_with( || json!({ "date": date }) )[name] = json!(kwh);
+        }
+    }
+    Ok( Json( json!({
+        "timezone": state.tz.name(),
+        "from": from,
+        "to": to,
+        "days": days.into_values().collect::<Vec<serde_json::Value>>(),
+    })))
+}
+
+fn parse_range(state: &ApiState, range: &RangeParams, default_length: TimeDelta) -> Result<(DateTime<Utc>, DateTime<Utc>), ApiError> {
+    let to = match &range.to {
+        Some(to) => export::parse_local_time(to, state.tz)?,
+        None => Utc::now(),
+    };
+    let from = match &range.from {
+        Some(from) => export::parse_local_time(from, state.tz)?,
+        None => to - default_length,
+    };
+    if from >= to {
+        return Err( ApiError::BadRequest("from must be before to".to_string()) )
+    } else if to - from > state.max_history {
+        return Err( ApiError::BadRequest( format!("Range longer than {} days", state.max_history.num_days()) ) )
+    }
+    Ok( (from, to) )
+}
+
+pub fn verify_api_conf(api_conf: &ApiConf, errors: &mut Vec<ConfigError>) {
+    // verifies bind address, timezone, max history and health max ages.  Adds a ConfigError to errors for each problem.
+    if api_conf.bind.parse::<SocketAddr>().is_err() {
+        errors.push( ConfigError::invalid("api.bind", &api_conf.bind, "Must be address:port, eg \"127.0.0.1:8080\".") );
+    }
+    if api_conf.timezone.parse::<Tz>().is_err() {
+        errors.push( ConfigError::invalid("api.timezone", &api_conf.timezone, "Must be a known timezone, eg \"America/New_York\".") );
+    }
+    if api_conf.max_history_days < 1 {
+        errors.push( ConfigError::invalid("api.max_history_days", api_conf.max_history_days, "Must be at least 1.") );
+    }
+    if api_conf.health_max_pvs6_age <= TimeDelta::zero() {
+        errors.push( ConfigError::invalid("api.health_max_pvs6_age", api_conf.health_max_pvs6_age.num_minutes(), "Must be at least 1 minute.") );
+    }
+    if api_conf.health_max_wx_age <= TimeDelta::zero() {
+        errors.push( ConfigError::invalid("api.health_max_wx_age", api_conf.health_max_wx_age.num_minutes(), "Must be at least 1 minute.") );
+    }
+}
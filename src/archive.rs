@@ -0,0 +1,320 @@
+/*
+Writes rows read from the solar db to files.  Rows are read as generic MySqlRows (any table) and converted to typed columns
+from the mysql column types (or built from typed structs by export), then written as CSV, gzip compressed CSV, newline
+delimited JSON or Parquet.  Used by retention to archive raw rows before they are deleted and by the export command.
+Times are written as RFC 3339 in the table timezone (UTC unless set).
+*/
+
+// USE STATEMENTS
+    use std::{ fmt, fs::{ self, File }, io::{ self, BufWriter, Write }, path::Path, sync::Arc };
+    use arrow_array::{ ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray, TimestampMillisecondArray };
+    use arrow_schema::{ DataType, Field, Schema, TimeUnit };
+    use chrono::{ DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Utc };
+    use chrono_tz::Tz;
+    use flate2::{ Compression, write::GzEncoder };
+    use parquet::{ arrow::ArrowWriter, basic::Compression as ParquetCompression, file::properties::WriterProperties };
+    use sqlx::{ Column, Row, TypeInfo, mysql::MySqlRow };
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArchiveFormat {
+    Csv,
+    CsvGz,
+    Ndjson,
+    Parquet,
+}
+impl ArchiveFormat {
+    pub fn parse(format: &str) -> Option<Self> {
+        // retention archive formats.  "csv" archives are always compressed
+        match format.trim().to_lowercase().as_str() {
+            "csv" | "csv.gz" => Some( ArchiveFormat::CsvGz ),
+            "parquet" => Some( ArchiveFormat::Parquet ),
+            _ => None,
+        }
+    }
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Csv => "csv",
+            ArchiveFormat::CsvGz => "csv.gz",
+            ArchiveFormat::Ndjson => "ndjson",
+            ArchiveFormat::Parquet => "parquet",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColumnKind {
+    Bool,
+    Int,
+    Float,
+    Time,
+    Text,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Time(DateTime<Utc>),
+    Text(String),
+}
+impl Value {
+    pub fn to_json(&self) -> serde_json::Value {
+        // times as RFC 3339 UTC
+        match self {
+            Value::Null => serde_json::Value::Null,
+            Value::Bool(value) => serde_json::Value::from(*value),
+            Value::Int(value) => serde_json::Value::from(*value),
+            // NaN / infinite aren't valid json numbers, written as null
+            Value::Float(value) => serde_json::Number::from_f64(*value).map(serde_json::Value::Number).unwrap_or_default(),
+            Value::Time(_) | Value::Text(_) => serde_json::Value::from( self.to_string() ),
+        }
+    }
+}
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Time(value) => write!(f, "{}", value.to_rfc3339_opts(SecondsFormat::Millis, true)),
+            Value::Text(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Table {
+    pub columns: Vec<(String, ColumnKind)>,
+    pub rows: Vec<Vec<Value>>,
+    // timezone times are written in.  None for UTC
+    pub timezone: Option<Tz>,
+}
+impl Table {
+    pub fn json_rows(&self) -> Vec<serde_json::Value> {
+        // rows as json objects keyed by column name
+        self.rows.iter()
+            .map( |row| serde_json::Value::Object( self.columns.iter().zip(row)
+                .map( |((name, _), value)| ( name.clone(), value.to_json() ) )
+                .collect() ) )
+            .collect()
+    }
+    fn text(&self, value: &Value) -> String {
+        // value as text, with times in table timezone
+        match ( value, self.timezone ) {
+            ( Value::Time(time), Some(tz) ) => time.with_timezone(&tz).to_rfc3339_opts(SecondsFormat::Millis, true),
+            _ => value.to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(io::Error),
+    Parquet(String),
+}
+impl std::error::Error for ArchiveError {}
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArchiveError::Io(io_eff) => write!(f, "File error: {}", io_eff),
+            ArchiveError::Parquet(parquet_eff) => write!(f, "Parquet error: {}", parquet_eff),
+        }
+    }
+}
+impl From<io::Error> for ArchiveError {
+    fn from(io_eff: io::Error) -> Self {
+        ArchiveError::Io(io_eff)
+    }
+}
+
+// FUNCTIONS
+
+pub fn rows_to_table(rows: &[MySqlRow]) -> Table {
+    // Converts mysql rows to a typed table.  Column kinds come from the mysql type of each column.  Values that don't decode as
+    // their column kind are stored as null.
+    let Some(first) = rows.first() else {
+        return Table::default()
+    };
+    let columns: Vec<(String, ColumnKind)> = first.columns().iter()
+        .map( |column| ( column.name().to_string(), column_kind(column.type_info().name()) ) )
+        .collect();
+
+    let rows = rows.iter()
+        .map( |row| columns.iter().enumerate().map( |(i, (_, kind))| decode_value(row, i, *kind) ).collect() )
+        .collect();
+    Table { columns, rows, timezone: None }
+}
+
+fn column_kind(mysql_type: &str) -> ColumnKind {
+    match mysql_type {
+        "BOOLEAN" => ColumnKind::Bool,
+        "TINYINT" | "SMALLINT" | "MEDIUMINT" | "INT" | "BIGINT" | "YEAR"
+            | "TINYINT UNSIGNED" | "SMALLINT UNSIGNED" | "MEDIUMINT UNSIGNED" | "INT UNSIGNED" | "BIGINT UNSIGNED" => ColumnKind::Int,
+        "FLOAT" | "DOUBLE" | "DECIMAL" => ColumnKind::Float,
+        "DATETIME" | "TIMESTAMP" | "DATE" => ColumnKind::Time,
+        _ => ColumnKind::Text,
+    }
+}
+
+fn decode_value(row: &MySqlRow, index: usize, kind: ColumnKind) -> Value {
+    let value = match kind {
+        ColumnKind::Bool => row.try_get::<Option<bool>, _>(index).ok().flatten().map(Value::Bool),
+        ColumnKind::Int => row.try_get::<Option<i64>, _>(index).ok().flatten()
+            .or_else( || row.try_get::<Option<u64>, _>(index).ok().flatten().map( |value| value as i64 ) )
+            .or_else( || row.try_get::<Option<i32>, _>(index).ok().flatten().map(i64::from) )
+            .or_else( || row.try_get::<Option<u32>, _>(index).ok().flatten().map(i64::from) )
+            .or_else( || row.try_get::<Option<i16>, _>(index).ok().flatten().map(i64::from) )
+            .or_else( || row.try_get::<Option<u16>, _>(index).ok().flatten().map(i64::from) )
+            .or_else( || row.try_get::<Option<i8>, _>(index).ok().flatten().map(i64::from) )
+            .or_else( || row.try_get::<Option<u8>, _>(index).ok().flatten().map(i64::from) )
+            .map(Value::Int),
+        ColumnKind::Float => row.try_get::<Option<f64>, _>(index).ok().flatten()
+            .or_else( || row.try_get::<Option<f32>, _>(index).ok().flatten().map(f64::from) )
+            .or_else( || row.try_get::<Option<String>, _>(index).ok().flatten().and_then( |value| value.parse().ok() ) )
+            .map(Value::Float),
+        ColumnKind::Time => row.try_get::<Option<DateTime<Utc>>, _>(index).ok().flatten()
+            .or_else( || row.try_get::<Option<NaiveDateTime>, _>(index).ok().flatten().map( |value| value.and_utc() ) )
+            .or_else( || row.try_get::<Option<NaiveDate>, _>(index).ok().flatten()
+                .and_then( |value| value.and_hms_opt(0, 0, 0) ).map( |value| value.and_utc() ) )
+            .map(Value::Time),
+        ColumnKind::Text => row.try_get::<Option<String>, _>(index).ok().flatten().map(Value::Text),
+    };
+    value.unwrap_or(Value::Null)
+}
+
+pub fn write_table(table: &Table, path: &Path, format: ArchiveFormat) -> Result<(), ArchiveError> {
+    // Writes table to path, creating parent directories.  Written to a temporary file first and renamed, so a partly written
+    // file is never left at path.
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    let written = File::create(&tmp_path).map_err(ArchiveError::from)
+        .and_then( |file| write_table_to(table, BufWriter::new(file), format) );
+    match written {
+        Ok(()) => Ok( fs::rename(&tmp_path, path)? ),
+        Err(write_eff) => {
+            let _ = fs::remove_file(&tmp_path);
+            Err(write_eff)
+        },
+    }
+}
+
+pub fn write_table_to<W: Write + Send>(table: &Table, mut writer: W, format: ArchiveFormat) -> Result<(), ArchiveError> {
+    // Writes table to writer (file or stdout)
+    match format {
+        ArchiveFormat::Csv => {
+            write_csv(table, &mut writer)?;
+            writer.flush()?;
+        },
+        ArchiveFormat::CsvGz => {
+            let mut encoder = GzEncoder::new(writer, Compression::default());
+            write_csv(table, &mut encoder)?;
+            encoder.finish()?.flush()?;
+        },
+        ArchiveFormat::Ndjson => {
+            write_ndjson(table, &mut writer)?;
+            writer.flush()?;
+        },
+        ArchiveFormat::Parquet => write_parquet(table, writer)?,
+    }
+    Ok(())
+}
+
+fn write_csv<W: Write>(table: &Table, writer: &mut W) -> Result<(), ArchiveError> {
+    let header: Vec<String> = table.columns.iter().map( |(name, _)| csv_field(name) ).collect();
+    writeln!(writer, "{}", header.join(","))?;
+    for row in &table.rows {
+        let fields: Vec<String> = row.iter().map( |value| csv_field(&table.text(value)) ).collect();
+        writeln!(writer, "{}", fields.join(","))?;
+    }
+    Ok(())
+}
+
+fn csv_field(field: &str) -> String {
+    // quotes fields containing separators, quotes or line breaks (RFC 4180)
+    if field.contains( [',', '"', '\n', '\r'] ) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn write_ndjson<W: Write>(table: &Table, writer: &mut W) -> Result<(), ArchiveError> {
+    // one json object per row.  Column order is kept
+    for row in &table.rows {
+        let fields: Vec<String> = table.columns.iter().zip(row)
+            .map( |((name, _), value)| {
+                let json_value = match value {
+                    Value::Time(_) => serde_json::Value::from( table.text(value) ),
+                    other => other.to_json(),
+                };
+                format!("{}:{}", serde_json::Value::from(name.as_str()), json_value)
+            })
+            .collect();
+        writeln!(writer, "{{{}}}", fields.join(","))?;
+    }
+    Ok(())
+}
+
+fn write_parquet<W: Write + Send>(table: &Table, writer: W) -> Result<(), ArchiveError> {
+    let parquet_eff = |eff: &dyn fmt::Display| ArchiveError::Parquet(eff.to_string());
+
+    let timezone = table.timezone.map( |tz| tz.name().to_string() ).unwrap_or( "UTC".to_string() );
+    let fields: Vec<Field> = table.columns.iter()
+        .map( |(name, kind)| Field::new(name, arrow_type(*kind, &timezone), true) )
+        .collect();
+    let schema = Arc::new( Schema::new(fields) );
+    let arrays: Vec<ArrayRef> = table.columns.iter().enumerate()
+        .map( |(i, (_, kind))| column_array(table, i, *kind, &timezone) )
+        .collect();
+    let batch = RecordBatch::try_new(schema.clone(), arrays).map_err( |eff| parquet_eff(&eff) )?;
+
+    let properties = WriterProperties::builder().set_compression(ParquetCompression::SNAPPY).build();
+    let mut writer = ArrowWriter::try_new( writer, schema, Some(properties) ).map_err( |eff| parquet_eff(&eff) )?;
+    writer.write(&batch).map_err( |eff| parquet_eff(&eff) )?;
+    writer.close().map_err( |eff| parquet_eff(&eff) )?;
+    Ok(())
+}
+
+fn arrow_type(kind: ColumnKind, timezone: &str) -> DataType {
+    match kind {
+        ColumnKind::Bool => DataType::Boolean,
+        ColumnKind::Int => DataType::Int64,
+        ColumnKind::Float => DataType::Float64,
+        ColumnKind::Time => DataType::Timestamp(TimeUnit::Millisecond, Some(timezone.into())),
+        ColumnKind::Text => DataType::Utf8,
+    }
+}
+
+fn column_array(table: &Table, index: usize, kind: ColumnKind, timezone: &str) -> ArrayRef {
+    let values = table.rows.iter().map( |row| row.get(index).unwrap_or(&Value::Null) );
+    match kind {
+        ColumnKind::Bool => Arc::new( values.map( |value| match value {
+            Value::Bool(value) => Some(*value),
+            Value::Int(value) => Some(*value != 0),
+            _ => None,
+        }).collect::<BooleanArray>() ),
+        ColumnKind::Int => Arc::new( values.map( |value| match value {
+            Value::Int(value) => Some(*value),
+            _ => None,
+        }).collect::<Int64Array>() ),
+        ColumnKind::Float => Arc::new( values.map( |value| match value {
+            Value::Float(value) => Some(*value),
+            _ => None,
+        }).collect::<Float64Array>() ),
+        ColumnKind::Time => Arc::new( values.map( |value| match value {
+            Value::Time(value) => Some( value.timestamp_millis() ),
+            _ => None,
+        }).collect::<TimestampMillisecondArray>().with_timezone(timezone) ),
+        ColumnKind::Text => Arc::new( values.map( |value| match value {
+            Value::Null => None,
+            other => Some( other.to_string() ),
+        }).collect::<StringArray>() ),
+    }
+}
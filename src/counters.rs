@@ -0,0 +1,183 @@
+/*
+Lifetime energy counter checks at PVS6 ingest.  Each new reading's *_ltea_3phsum_kwh counters are compared with the previous
+reading of the same serial and counter:
+    reset   counter went backwards.  Lifetime counters (inverter ltea_3phsum_kwh, consumption meter pos_ / neg_ltea_3phsum_kwh)
+            only go up.  Net counters (net_ltea_3phsum_kwh) go down while exporting, so only count as reset when they go down
+            faster than the device's max power allows.
+    jump    counter went up faster than the device's max power allows (pvs6 max_inverter_kw / max_meter_kw).
+Inverter replacement, firmware resets and meter swaps show up as these.  Events are logged and stored in counter_events with
+the step (delta_kwh) between the two readings.  Readings are stored unchanged: energy calculations stitch across events (see
+energy::get_counter_readings).
+The previous reading of each counter is kept in memory, and read from the solar db the first time a serial is seen.
+*/
+
+// USE STATEMENTS
+    use std::collections::HashMap;
+    use chrono::{ DateTime, Utc };
+    use log::{ debug, error, warn };
+
+    use crate::Pvs6DevicesResponse;
+
+// CONSTANTS
+    // counter changes within this (kWh) of what max power allows are rounding, not events
+    const COUNTER_SLACK_KWH: f64 = 0.1;
+    const INSERT_COUNTER_EVENT_QUERY: &str =
+    r#"
+        INSERT IGNORE INTO counter_events
+            ( serial, counter, event_time, event_type, previous_time, previous_kwh, kwh, delta_kwh )
+            VALUES ( ?, ?, ?, ?, ?, ?, ?, ? )
+    "#;
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CounterKind {
+    // only goes up
+    Lifetime,
+    // goes down while exporting
+    Net,
+}
+
+#[derive(Clone, Debug)]
+pub struct CounterEvent {
+    pub serial: String,
+    pub counter: &'static str,
+    // "reset" or "jump"
+    pub event_type: &'static str,
+    pub previous_time: DateTime<Utc>,
+    pub previous_kwh: f64,
+    pub time: DateTime<Utc>,
+    pub kwh: f64,
+}
+impl CounterEvent {
+    pub fn delta_kwh(&self) -> f64 {
+        self.kwh - self.previous_kwh
+    }
+}
+
+// previous reading of each ( serial, counter )
+pub struct CounterCheck {
+    max_inverter_kw: f64,
+    max_meter_kw: f64,
+    previous: HashMap<(String, &'static str), (DateTime<Utc>, f64)>,
+}
+impl CounterCheck {
+    pub fn new(max_inverter_kw: f64, max_meter_kw: f64) -> Self {
+        Self { max_inverter_kw, max_meter_kw, previous: HashMap::new() }
+    }
+
+    pub async fn check(&mut self, data: &Pvs6DevicesResponse, sql_pool_opt: &Option<sqlx::Pool<sqlx::MySql>>) -> Vec<CounterEvent> {
+        // events in a poll's readings.  Repeated readings (no new data) have no counter values and are skipped
+        let mut readings = vec![
+            ( "production_meters_data", &data.prod_meter.serial, data.prod_meter.data_time, "net_ltea_3phsum_kwh",
+                data.prod_meter.net_ltea_3phsum_kwh, CounterKind::Net, self.max_meter_kw ),
+            ( "consumption_meters_data", &data.cons_meter.serial, data.cons_meter.data_time, "pos_ltea_3phsum_kwh",
+                data.cons_meter.pos_ltea_3phsum_kwh, CounterKind::Lifetime, self.max_meter_kw ),
+            ( "consumption_meters_data", &data.cons_meter.serial, data.cons_meter.data_time, "neg_ltea_3phsum_kwh",
+                data.cons_meter.neg_ltea_3phsum_kwh, CounterKind::Lifetime, self.max_meter_kw ),
+            ( "consumption_meters_data", &data.cons_meter.serial, data.cons_meter.data_time, "net_ltea_3phsum_kwh",
+                data.cons_meter.net_ltea_3phsum_kwh, CounterKind::Net, self.max_meter_kw ),
+        ];
+        for inv in data.inverters.iter() {
+            readings.push( ( "inverters_data", &inv.serial, inv.data_time, "ltea_3phsum_kwh", inv.ltea_3phsum_kwh,
+                CounterKind::Lifetime, self.max_inverter_kw ) );
+        }
+
+        let mut events = Vec::new();
+        for (table, serial, time, counter, kwh, kind, max_kw) in readings {
+            let ( Some(time), Some(kwh) ) = ( time, kwh ) else { continue };
+            if serial.is_empty() {
+                continue
+            }
+            let key = ( serial.clone(), counter );
+            let previous = match self.previous.get(&key) {
+                Some(previous) => Some(*previous),
+                None => get_previous_reading(sql_pool_opt, table, counter, serial, time).await,
+            };
+            if let Some((previous_time, previous_kwh)) = previous
+                && time > previous_time
+                && let Some(event_type) = counter_event_type(kind, max_kw, previous_time, previous_kwh, time, kwh) {
+                events.push( CounterEvent {
+                    serial: serial.clone(), counter, event_type, previous_time, previous_kwh, time, kwh,
+                });
+            }
+            if previous.is_none_or( |(previous_time, _)| time > previous_time ) {
+                self.previous.insert(key, (time, kwh));
+            }
+        }
+        events
+    }
+}
+
+// FUNCTIONS
+
+fn counter_event_type(kind: CounterKind, max_kw: f64, previous_time: DateTime<Utc>, previous_kwh: f64, time: DateTime<Utc>,
+    kwh: f64) -> Option<&'static str> {
+    // "reset", "jump" or None for a plausible change between readings
+    let hours = ( time - previous_time ).num_milliseconds() as f64 / 3_600_000.0;
+    let max_change = max_kw * hours + COUNTER_SLACK_KWH;
+    let delta = kwh - previous_kwh;
+    let max_decrease = match kind {
+        CounterKind::Lifetime => COUNTER_SLACK_KWH,
+        CounterKind::Net => max_change,
+    };
+    if delta < -max_decrease {
+        Some("reset")
+    } else if delta > max_change {
+        Some("jump")
+    } else {
+        None
+    }
+}
+
+async fn get_previous_reading(sql_pool_opt: &Option<sqlx::Pool<sqlx::MySql>>, table: &str, counter: &str, serial: &str,
+    before: DateTime<Utc>) -> Option<(DateTime<Utc>, f64)> {
+    // last stored counter reading of serial before a time.  Table and counter are column names from code, never from user input.
+    let sql_pool = sql_pool_opt.as_ref()?;
+    let query = format!(
+        "SELECT data_time, {} FROM {} WHERE serial = ? AND data_time < ? AND {} IS NOT NULL ORDER BY data_time DESC LIMIT 1",
+        counter, table, counter
+    );
+    match sqlx::query_as::<_, (DateTime<Utc>, f64)>(&query).bind(serial).bind(before).fetch_optional(sql_pool).await {
+        Ok(previous) => previous,
+        Err(previous_eff) => {
+            error!("Couldn't read previous {} {} for {}. Error: {}", table, counter, serial, previous_eff);
+            None
+        },
+    }
+}
+
+pub async fn insert_counter_events_to_mysql(events: &[CounterEvent], sql_pool_opt: &Option<sqlx::Pool<sqlx::MySql>>) {
+    // logs counter events and uploads them to counter_events table
+    for event in events {
+        warn!("Counter {}: {} {} went from {:.3} kWh @ {} to {:.3} kWh @ {}.  Energy calculations skip the step.",
+            event.event_type, event.serial, event.counter, event.previous_kwh, event.previous_time.format("%Y-%m-%d %H:%M:%S"),
+            event.kwh, event.time.format("%Y-%m-%d %H:%M:%S"));
+    }
+    if events.is_empty() {
+        return
+    }
+    let Some(sql_pool) = sql_pool_opt else {
+        error!("Couldn't get sql pool");
+        return
+    };
+    for event in events {
+        let event_result = sqlx::query(INSERT_COUNTER_EVENT_QUERY)
+            .bind(&event.serial)
+            .bind(event.counter)
+            .bind(event.time)
+            .bind(event.event_type)
+            .bind(event.previous_time)
+            .bind(event.previous_kwh)
+            .bind(event.kwh)
+            .bind(event.delta_kwh())
+            .execute(sql_pool).await;
+
+        match event_result {
+            Ok(_) => debug!("Counter event: {} {} @ {} uploaded to Mysql solar database", event.serial, event.counter,
+                event.time.format("%Y-%m-%d %H:%M:%S")),
+            Err(event_eff) => error!("Counter event: {} {} @ {} failed to upload to Mysql solar database. Error: {}", event.serial,
+                event.counter, event.time.format("%Y-%m-%d %H:%M:%S"), event_eff),
+        }
+    }
+}
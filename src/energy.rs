@@ -0,0 +1,225 @@
+/*
+Energy calculations from PVS6 lifetime energy counters (net_ltea_3phsum_kwh, ltea_3phsum_kwh, pos_/neg_ltea_3phsum_kwh).
+Counters only ever increase, so energy over a period is the difference between readings.  Differences are assigned to the
+interval (bucket) the energy was produced in.
+Counter resets and implausible jumps found at ingest (counters module, counter_events table) are stitched out of readings
+read with get_counter_readings: the step at each event is removed from that reading and all later ones.
+*/
+
+// USE STATEMENTS
+    use std::collections::BTreeMap;
+    use chrono::{ DateTime, DurationRound, NaiveDate, TimeDelta, TimeZone, Utc };
+    use chrono_tz::Tz;
+    use log::debug;
+
+// CONSTANTS
+    // sql query to get counter events (counters module) of a counter in a time range
+    const QUERY_GET_COUNTER_EVENTS: &str =
+    r#"
+        SELECT serial, event_time, delta_kwh
+        FROM counter_events
+        WHERE counter = ? AND event_time > ? AND event_time <= ?
+        ORDER BY serial, event_time
+    "#;
+
+// FUNCTIONS
+
+pub fn bucket_energy( readings: &[(DateTime<Utc>, f64)], bucket: TimeDelta, max_gap: TimeDelta ) -> BTreeMap<DateTime<Utc>, f64> {
+    // Takes lifetime counter readings (time, kWh) for a single device, sorted by time, and returns energy (kWh) per bucket keyed
+    // by bucket start time.  The energy between two readings is assigned to the bucket that contains the later reading (less 1 s,
+    // so a reading exactly on the bucket boundary closes the previous bucket).
+    // Differences are skipped (not assigned to any bucket) when readings are more than max_gap apart (energy can't be placed in
+    // the right bucket) or when the counter went backwards (counter reset / device replaced).
+    bucket_differences(readings, bucket, max_gap, false)
+}
+
+pub fn bucket_change( readings: &[(DateTime<Utc>, f64)], bucket: TimeDelta, max_gap: TimeDelta ) -> BTreeMap<DateTime<Utc>, f64> {
+    // Signed change (kWh) per bucket, as bucket_energy.  For net counters, which go down while exporting, so decreases are kept.
+    bucket_differences(readings, bucket, max_gap, true)
+}
+
+fn bucket_differences( readings: &[(DateTime<Utc>, f64)], bucket: TimeDelta, max_gap: TimeDelta, keep_decreases: bool )
+    -> BTreeMap<DateTime<Utc>, f64> {
+    let mut energy: BTreeMap<DateTime<Utc>, f64> = BTreeMap::new();
+    let mut skipped: u32 = 0;
+
+    for pair in readings.windows(2) {
+        let (prev_time, prev_kwh) = pair[0];
+        let (cur_time, cur_kwh) = pair[1];
+        let delta = cur_kwh - prev_kwh;
+
+        if cur_time - prev_time > max_gap || ( delta < 0.0 && !keep_decreases ) {
+            skipped += 1;
+            continue;
+        }
+        let bucket_start = match ( cur_time - TimeDelta::seconds(1) ).duration_trunc(bucket) {
+            Ok(start) => start,
+            Err(_) => {
+                skipped += 1;
+                continue;
+            },
+        };
+        *energy.entry(bucket_start).or_insert(0.0) += delta;
+    }
+    if skipped > 0 {
+        debug!("{} counter differences skipped (gap over {} or counter went backwards).", skipped, max_gap);
+    }
+    energy
+}
+
+pub async fn get_counter_readings( sql_pool: &sqlx::Pool<sqlx::MySql>, table: &'static str, counter: &'static str, start: DateTime<Utc>,
+    end: DateTime<Utc> ) -> Result<BTreeMap<String, Vec<(DateTime<Utc>, f64)>>, sqlx::Error> {
+    // Lifetime counter readings (time, kWh) per device serial with data_time in ( start, end ], sorted by time and stitched across
+    // counter events.  Table and counter are column names from code, never from user input.
+    let query = format!(
+        "SELECT serial, data_time, {} FROM {} WHERE data_time > ? AND data_time <= ? AND {} IS NOT NULL ORDER BY serial, data_time",
+        counter, table, counter
+    );
+    let rows = sqlx::query_as::<_, (String, DateTime<Utc>, f64)>(&query).bind(start).bind(end).fetch_all(sql_pool).await?;
+
+    let mut readings: BTreeMap<String, Vec<(DateTime<Utc>, f64)>> = BTreeMap::new();
+    for (serial, time, kwh) in rows {
+        readings.entry(serial).or_default().push( (time, kwh) );
+    }
+    let events = get_counter_events(sql_pool, counter, start, end).await?;
+    for (serial, device_readings) in readings.iter_mut() {
+        if let Some(device_events) = events.get(serial) {
+            stitch_counter_events(device_readings, device_events);
+        }
+    }
+    Ok(readings)
+}
+
+pub async fn get_counter_events( sql_pool: &sqlx::Pool<sqlx::MySql>, counter: &str, start: DateTime<Utc>, end: DateTime<Utc> )
+    -> Result<BTreeMap<String, Vec<(DateTime<Utc>, f64)>>, sqlx::Error> {
+    // Counter events (event time, step kWh) per device serial with event_time in ( start, end ], sorted by time
+    let rows = sqlx::query_as::<_, (String, DateTime<Utc>, f64)>(QUERY_GET_COUNTER_EVENTS)
+        .bind(counter).bind(start).bind(end).fetch_all(sql_pool).await?;
+
+    let mut events: BTreeMap<String, Vec<(DateTime<Utc>, f64)>> = BTreeMap::new();
+    for (serial, time, delta_kwh) in rows {
+        events.entry(serial).or_default().push( (time, delta_kwh) );
+    }
+    Ok(events)
+}
+
+pub fn stitch_counter_events( readings: &mut [(DateTime<Utc>, f64)], events: &[(DateTime<Utc>, f64)] ) {
+    // Removes the step (kWh) of each event from readings at or after the event time, so the counter continues smoothly across
+    // resets and jumps.  Readings and events for a single device, sorted by time.  Energy between the readings either side of an
+    // event is lost.
+    let mut step = 0.0;
+    let mut next_event = events.iter().peekable();
+    for (time, kwh) in readings.iter_mut() {
+        while let Some((_, delta_kwh)) = next_event.next_if( |(event_time, _)| event_time <= time ) {
+            step += delta_kwh;
+        }
+        *kwh -= step;
+    }
+}
+
+pub fn total_bucket_energy( readings: &BTreeMap<String, Vec<(DateTime<Utc>, f64)>>, bucket: TimeDelta, max_gap: TimeDelta )
+    -> BTreeMap<DateTime<Utc>, f64> {
+    // bucket_energy summed over all devices
+    let mut energy: BTreeMap<DateTime<Utc>, f64> = BTreeMap::new();
+    for device_readings in readings.values() {
+        for (bucket_start, kwh) in bucket_energy(device_readings, bucket, max_gap) {
+            *energy.entry(bucket_start).or_insert(0.0) += kwh;
+        }
+    }
+    energy
+}
+
+pub fn daily_energy( hourly: &BTreeMap<DateTime<Utc>, f64>, tz: Tz ) -> BTreeMap<NaiveDate, f64> {
+    // Sums hourly energy into local days in tz.  Hours are assigned by their start time, so timezones with a whole hour
+    // offset from UTC split days exactly.
+    let mut daily: BTreeMap<NaiveDate, f64> = BTreeMap::new();
+    for (hour_start, kwh) in hourly {
+        *daily.entry( hour_start.with_timezone(&tz).date_naive() ).or_insert(0.0) += kwh;
+    }
+    daily
+}
+
+pub fn local_midnight_utc(date: NaiveDate, tz: Tz) -> DateTime<Utc> {
+    // start of local day in utc.  Uses earliest time if midnight is ambiguous, and 1 am if midnight doesn't exist (DST change)
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    match tz.from_local_datetime(&midnight).earliest() {
+        Some(local) => local.with_timezone(&Utc),
+        None => ( tz.from_local_datetime(&( midnight + TimeDelta::hours(1) )).earliest() )
+            .map( |local| local.with_timezone(&Utc) )
+            .unwrap_or( midnight.and_utc() ),
+    }
+}
+
+pub fn meter_read_energy( readings: &[(DateTime<Utc>, f64)], start: DateTime<Utc>, end: DateTime<Utc> ) -> Option<f64> {
+    // Energy (kWh) between meter reads at start and end, as a utility bills it: from the last reading at or before start (or the
+    // first reading after it, if there is none) to the last reading at or before end.  Readings for a single device, sorted by
+    // time.  Unlike bucket_energy, energy across gaps is kept.  Counter decreases (counter reset / device replaced) are skipped.
+    let (first, last) = meter_reads(readings, start, end)?;
+    Some( readings[first..=last].windows(2).map( |pair| ( pair[1].1 - pair[0].1 ).max(0.0) ).sum() )
+}
+
+pub fn counter_change( readings: &[(DateTime<Utc>, f64)], start: DateTime<Utc>, end: DateTime<Utc> ) -> Option<f64> {
+    // Signed change (kWh) between the same meter reads as meter_read_energy.  For net counters, which go down while exporting.
+    let (first, last) = meter_reads(readings, start, end)?;
+    Some( readings[last].1 - readings[first].1 )
+}
+
+fn meter_reads( readings: &[(DateTime<Utc>, f64)], start: DateTime<Utc>, end: DateTime<Utc> ) -> Option<(usize, usize)> {
+    // indexes of the meter reads at start and end.  None if there aren't two different readings
+    let first = readings.iter().rposition( |(time, _)| *time <= start )
+        .or_else( || readings.iter().position( |(time, _)| *time > start ) )?;
+    let last = readings.iter().rposition( |(time, _)| *time <= end )?;
+    if last <= first { None } else { Some( (first, last) ) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_750_000_000, 0).unwrap() + TimeDelta::minutes(minutes)
+    }
+
+    fn kwhs(readings: &[(DateTime<Utc>, f64)]) -> Vec<f64> {
+        readings.iter().map( |(_, kwh)| ( kwh * 1000.0 ).round() / 1000.0 ).collect()
+    }
+
+    #[test]
+    fn stitch_reset_between_readings() {
+        // counter reset to 0 between the 2nd and 3rd reading.  Step is applied from the 3rd reading on
+        let mut readings = vec![ (at(0), 100.0), (at(5), 100.5), (at(10), 0.2), (at(15), 0.7) ];
+        stitch_counter_events(&mut readings, &[ (at(7), -100.3) ]);
+        assert_eq!( kwhs(&readings), vec![100.0, 100.5, 100.5, 101.0] );
+    }
+
+    #[test]
+    fn stitch_event_exactly_on_reading() {
+        // event_time is the data_time of the reading after the step, so that reading is stitched
+        let mut readings = vec![ (at(0), 10.0), (at(5), 10.1), (at(10), 510.1), (at(15), 510.3) ];
+        stitch_counter_events(&mut readings, &[ (at(10), 500.0) ]);
+        assert_eq!( kwhs(&readings), vec![10.0, 10.1, 10.1, 10.3] );
+    }
+
+    #[test]
+    fn stitch_multiple_events() {
+        let mut readings = vec![ (at(0), 50.0), (at(5), 0.0), (at(10), 0.5), (at(15), 1000.5), (at(20), 1001.0) ];
+        stitch_counter_events(&mut readings, &[ (at(5), -50.0), (at(15), 1000.0) ]);
+        assert_eq!( kwhs(&readings), vec![50.0, 50.0, 50.5, 50.5, 51.0] );
+    }
+
+    #[test]
+    fn stitch_events_outside_readings() {
+        // an event before the first reading shifts every reading (differences unchanged), one after the last changes nothing
+        let mut readings = vec![ (at(10), 5.0), (at(15), 5.5) ];
+        stitch_counter_events(&mut readings, &[ (at(0), 2.0), (at(20), 100.0) ]);
+        assert_eq!( kwhs(&readings), vec![3.0, 3.5] );
+    }
+
+    #[test]
+    fn stitched_readings_have_no_spike() {
+        let mut readings = vec![ (at(0), 10.0), (at(30), 10.4), (at(60), 2010.4), (at(90), 2010.9) ];
+        stitch_counter_events(&mut readings, &[ (at(60), 2000.0) ]);
+        let energy: f64 = bucket_energy(&readings, TimeDelta::hours(1), TimeDelta::hours(1)).values().sum();
+        assert!( ( energy - 0.9 ).abs() < 1e-9, "{}", energy );
+    }
+}
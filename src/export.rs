@@ -0,0 +1,435 @@
+/*
+Export command.  Writes PVS6 device data (supervisor, production, consumption, inverter), weather (current_wx, daily_wx) or
+tariff billing cycles (billing) for a time range to CSV, newline delimited JSON or Parquet.
+    pvs6_to_mysql export --device inverter --serial E00121... --start 2024-06-01 --end 2024-07-01 --timezone America/New_York
+        --format parquet --resample 1h --output june_inverters.parquet
+Rows are read with the same sqlx::FromRow structs the collectors use.  Start / end (end exclusive) are in timezone and times are
+written in timezone.  Resampling groups rows by serial into fixed intervals aligned to local midnight: readings are averaged,
+lifetime energy counters (*_ltea_*) and text keep the last value in the interval, and a samples column counts the rows.
+Billing cycles are selected by period_start, compared with the local dates of start / end, and can't be resampled.
+*/
+
+// USE STATEMENTS
+    use std::{ collections::BTreeMap, fmt, path::PathBuf };
+    use chrono::{ DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Utc };
+    use chrono_tz::Tz;
+    use clap::{ Args, ValueEnum };
+    use log::info;
+    use sqlx::mysql::MySqlRow;
+
+    use crate::{ ConsumptionMeter, CurrentWx, DailyWxData, Inverter, ProductionMeter, Supervisor,
+        archive::{ self, ArchiveError, ArchiveFormat, ColumnKind, Table, Value }, scheduler::parse_duration, tariff::BillingPeriodRow };
+
+// CONSTANTS
+    const QUERY_EXPORT_SUPERVISORS: &str = "SELECT * FROM supervisors_data";
+    const QUERY_EXPORT_PRODUCTION_METERS: &str = "SELECT * FROM production_meters_data";
+    const QUERY_EXPORT_CONSUMPTION_METERS: &str = "SELECT * FROM consumption_meters_data";
+    const QUERY_EXPORT_INVERTERS: &str = "SELECT * FROM inverters_data";
+    // weather columns are renamed to the struct field names
+    const QUERY_EXPORT_CURRENT_WX: &str =
+    r#"
+        SELECT time, summary, icon, nearestStormDistance AS nearest_storm_distance, nearestStormBearing AS nearest_storm_bearing,
+            precipIntensity AS precip_intensity, precipProbability AS precip_probability, precipIntensityError AS precip_intensity_error,
+            precipType AS precip_type, temperature, apparentTemperature AS apparent_temperature, dewPoint AS dew_point, humidity,
+            pressure, windSpeed AS wind_speed, windGust AS wind_gust, windBearing AS wind_bearing, cloudCover AS cloud_cover,
+            uvIndex AS uv_index, visibility, ozone, smoke, fireIndex AS fire_index, feelsLike AS feels_like,
+            currentDayIce AS current_day_ice, currentDayLiquid AS current_day_liquid, currentDaySnow AS current_day_snow
+        FROM current_wx
+    "#;
+    const QUERY_EXPORT_DAILY_WX: &str =
+    r#"
+        SELECT time, summary, icon, dawnTime AS dawn_time, duskTime AS dusk_time, sunriseTime AS sunrise_time,
+            sunsetTime AS sunset_time, moonPhase AS moon_phase, precipAccumulation AS precip_accumulation,
+            precipProbability AS precip_probability, temperatureMin AS temperature_min, temperatureMinTime AS temperature_min_time,
+            temperatureMax AS temperature_max, temperatureMaxTime AS temperature_max_time, cloudCover AS cloud_cover,
+            uvIndex AS uv_index
+        FROM daily_wx
+    "#;
+    const QUERY_EXPORT_BILLING: &str = "SELECT * FROM tariff_billing_period";
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+#[derive(Args, Debug, Clone)]
+pub struct ExportArgs {
+    /// Data to export
+    #[arg(long, value_enum)]
+    pub device: ExportDevice,
+    /// Only export these serial numbers (comma separated or repeated).  PVS6 devices only
+    #[arg(long, value_delimiter = ',')]
+    pub serial: Vec<String>,
+    /// Start of time range (inclusive) in timezone, eg "2024-06-01" or "2024-06-01 06:00:00"
+    #[arg(long)]
+    pub start: String,
+    /// End of time range (exclusive) in timezone
+    #[arg(long)]
+    pub end: String,
+    /// Timezone of start / end and of exported times, eg "America/New_York"
+    #[arg(long, default_value = "UTC")]
+    pub timezone: String,
+    #[arg(long, value_enum, default_value = "csv")]
+    pub format: ExportFormat,
+    /// Resample to a fixed interval, eg "15m", "1h", "1d"
+    #[arg(long)]
+    pub resample: Option<String>,
+    /// File to write
+    #[arg(long, short)]
+    pub output: PathBuf,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ExportDevice {
+    Supervisor,
+    Production,
+    Consumption,
+    Inverter,
+    #[value(name = "current_wx")]
+    CurrentWx,
+    #[value(name = "daily_wx")]
+    DailyWx,
+    Billing,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+    Parquet,
+}
+impl From<ExportFormat> for ArchiveFormat {
+    fn from(format: ExportFormat) -> Self {
+        match format {
+            ExportFormat::Csv => ArchiveFormat::Csv,
+            ExportFormat::Ndjson => ArchiveFormat::Ndjson,
+            ExportFormat::Parquet => ArchiveFormat::Parquet,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Timezone(String),
+    Time(String),
+    Resample(String),
+    ResampleBilling,
+    Serial,
+    Sql(sqlx::Error),
+    Write(ArchiveError),
+}
+impl std::error::Error for ExportError {}
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportError::Timezone(tz) => write!(f, "Unknown timezone: {}", tz),
+            ExportError::Time(time) => write!(f, "Invalid time: {}. Use \"YYYY-MM-DD\", \"YYYY-MM-DD HH:MM[:SS]\" or RFC 3339", time),
+            ExportError::Resample(interval) => write!(f, "Invalid resample interval: {}. Use a positive whole number of seconds, eg \"15m\"", interval),
+            ExportError::ResampleBilling => write!(f, "Billing cycles can't be resampled"),
+            ExportError::Serial => write!(f, "Serial filter can only be used with PVS6 devices"),
+            ExportError::Sql(sql_eff) => write!(f, "Database error: {}", sql_eff),
+            ExportError::Write(write_eff) => write!(f, "{}", write_eff),
+        }
+    }
+}
+
+// Row types that can be exported.  columns() and values() are in the same order.
+pub trait ExportRecord: for<'r> sqlx::FromRow<'r, MySqlRow> + Send + Unpin {
+    const QUERY: &'static str;
+    const TIME_COLUMN: &'static str;
+    const HAS_SERIAL: bool;
+    fn columns() -> Vec<(&'static str, ColumnKind)>;
+    fn values(&self) -> Vec<Value>;
+
+    fn to_json(&self) -> serde_json::Value {
+        // record as json object keyed by column name
+        serde_json::Value::Object( Self::columns().into_iter().zip(self.values())
+            .map( |((name, _), value)| ( name.to_string(), value.to_json() ) )
+            .collect() )
+    }
+}
+
+impl ExportRecord for Supervisor {
+    const QUERY: &'static str = QUERY_EXPORT_SUPERVISORS;
+    const TIME_COLUMN: &'static str = "data_time";
+    const HAS_SERIAL: bool = true;
+    fn columns() -> Vec<(&'static str, ColumnKind)> {
+        use ColumnKind::*;
+        vec![ ("serial", Text), ("data_time", Time), ("dl_comm_err", Int), ("dl_cpu_load", Float), ("dl_err_count", Int),
+            ("dl_flash_avail", Int), ("dl_mem_used", Int), ("dl_scan_time", Int), ("dl_skipped_scans", Int), ("dl_untransmitted", Int),
+            ("dl_uptime", Int) ]
+    }
+    fn values(&self) -> Vec<Value> {
+        vec![ text(Some(&self.serial)), time(self.data_time), int(self.dl_comm_err), float(self.dl_cpu_load), int(self.dl_err_count),
+            int(self.dl_flash_avail), int(self.dl_mem_used), int(self.dl_scan_time), int(self.dl_skipped_scans),
+            int(self.dl_untransmitted), int(self.dl_uptime) ]
+    }
+}
+
+impl ExportRecord for ProductionMeter {
+    const QUERY: &'static str = QUERY_EXPORT_PRODUCTION_METERS;
+    const TIME_COLUMN: &'static str = "data_time";
+    const HAS_SERIAL: bool = true;
+    fn columns() -> Vec<(&'static str, ColumnKind)> {
+        use ColumnKind::*;
+        vec![ ("serial", Text), ("data_time", Time), ("freq_hz", Float), ("i_a", Float), ("net_ltea_3phsum_kwh", Float),
+            ("p_3phsum_kw", Float), ("q_3phsum_kvar", Float), ("s_3phsum_kva", Float), ("tot_pf_rto", Float), ("v12_v", Float) ]
+    }
+    fn values(&self) -> Vec<Value> {
+        vec![ text(Some(&self.serial)), time(self.data_time), float(self.freq_hz), float(self.i_a), float(self.net_ltea_3phsum_kwh),
+            float(self.p_3phsum_kw), float(self.q_3phsum_kvar), float(self.s_3phsum_kva), float(self.tot_pf_rto), float(self.v12_v) ]
+    }
+}
+
+impl ExportRecord for ConsumptionMeter {
+    const QUERY: &'static str = QUERY_EXPORT_CONSUMPTION_METERS;
+    const TIME_COLUMN: &'static str = "data_time";
+    const HAS_SERIAL: bool = true;
+    fn columns() -> Vec<(&'static str, ColumnKind)> {
+        use ColumnKind::*;
+        vec![ ("serial", Text), ("data_time", Time), ("freq_hz", Float), ("i1_a", Float), ("i2_a", Float), ("neg_ltea_3phsum_kwh", Float),
+            ("net_ltea_3phsum_kwh", Float), ("p_3phsum_kw", Float), ("p1_kw", Float), ("p2_kw", Float), ("pos_ltea_3phsum_kwh", Float),
+            ("q_3phsum_kvar", Float), ("s_3phsum_kva", Float), ("tot_pf_rto", Float), ("v12_v", Float), ("v1n_v", Float), ("v2n_v", Float) ]
+    }
+    fn values(&self) -> Vec<Value> {
+        vec![ text(Some(&self.serial)), time(self.data_time), float(self.freq_hz), float(self.i1_a), float(self.i2_a),
+            float(self.neg_ltea_3phsum_kwh), float(self.net_ltea_3phsum_kwh), float(self.p_3phsum_kw), float(self.p1_kw),
+            float(self.p2_kw), float(self.pos_ltea_3phsum_kwh), float(self.q_3phsum_kvar), float(self.s_3phsum_kva),
+            float(self.tot_pf_rto), float(self.v12_v), float(self.v1n_v), float(self.v2n_v) ]
+    }
+}
+
+impl ExportRecord for Inverter {
+    const QUERY: &'static str = QUERY_EXPORT_INVERTERS;
+    const TIME_COLUMN: &'static str = "data_time";
+    const HAS_SERIAL: bool = true;
+    fn columns() -> Vec<(&'static str, ColumnKind)> {
+        use ColumnKind::*;
+        vec![ ("serial", Text), ("data_time", Time), ("freq_hz", Float), ("i_3phsum_a", Float), ("i_mppt1_a", Float),
+            ("ltea_3phsum_kwh", Float), ("p_3phsum_kw", Float), ("p_mppt1_kw", Float), ("stat_ind", Float), ("t_htsnk_degc", Float),
+            ("v_mppt1_v", Float), ("vln_3phavg_v", Float) ]
+    }
+    fn values(&self) -> Vec<Value> {
+        vec![ text(Some(&self.serial)), time(self.data_time), float(self.freq_hz), float(self.i_3phsum_a), float(self.i_mppt1_a),
+            float(self.ltea_3phsum_kwh), float(self.p_3phsum_kw), float(self.p_mppt1_kw), float(self.stat_ind), float(self.t_htsnk_degc),
+            float(self.v_mppt1_v), float(self.vln_3phavg_v) ]
+    }
+}
+
+impl ExportRecord for CurrentWx {
+    const QUERY: &'static str = QUERY_EXPORT_CURRENT_WX;
+    const TIME_COLUMN: &'static str = "time";
+    const HAS_SERIAL: bool = false;
+    fn columns() -> Vec<(&'static str, ColumnKind)> {
+        use ColumnKind::*;
+        vec![ ("time", Time), ("summary", Text), ("icon", Text), ("nearest_storm_distance", Float), ("nearest_storm_bearing", Float),
+            ("precip_intensity", Float), ("precip_probability", Float), ("precip_intensity_error", Float), ("precip_type", Text),
+            ("temperature", Float), ("apparent_temperature", Float), ("dew_point", Float), ("humidity", Float), ("pressure", Float),
+            ("wind_speed", Float), ("wind_gust", Float), ("wind_bearing", Float), ("cloud_cover", Float), ("uv_index", Float),
+            ("visibility", Float), ("ozone", Float), ("smoke", Float), ("fire_index", Float), ("feels_like", Float),
+            ("current_day_ice", Float), ("current_day_liquid", Float), ("current_day_snow", Float) ]
+    }
+    fn values(&self) -> Vec<Value> {
+        vec![ time(Some(self.time)), text(self.summary.as_ref()), text(self.icon.as_ref()), float(self.nearest_storm_distance),
+            float(self.nearest_storm_bearing), float(self.precip_intensity), float(self.precip_probability),
+            float(self.precip_intensity_error), text(self.precip_type.as_ref()), float(self.temperature), float(self.apparent_temperature),
+            float(self.dew_point), float(self.humidity), float(self.pressure), float(self.wind_speed), float(self.wind_gust),
+            float(self.wind_bearing), float(self.cloud_cover), float(self.uv_index), float(self.visibility), float(self.ozone),
+            float(self.smoke), float(self.fire_index), float(self.feels_like), float(self.current_day_ice),
+            float(self.current_day_liquid), float(self.current_day_snow) ]
+    }
+}
+
+impl ExportRecord for DailyWxData {
+    const QUERY: &'static str = QUERY_EXPORT_DAILY_WX;
+    const TIME_COLUMN: &'static str = "time";
+    const HAS_SERIAL: bool = false;
+    fn columns() -> Vec<(&'static str, ColumnKind)> {
+        use ColumnKind::*;
+        vec![ ("time", Time), ("summary", Text), ("icon", Text), ("dawn_time", Time), ("dusk_time", Time), ("sunrise_time", Time),
+            ("sunset_time", Time), ("moon_phase", Float), ("precip_accumulation", Float), ("precip_probability", Float),
+            ("temperature_min", Float), ("temperature_min_time", Time), ("temperature_max", Float), ("temperature_max_time", Time),
+            ("cloud_cover", Float), ("uv_index", Float) ]
+    }
+    fn values(&self) -> Vec<Value> {
+        vec![ time(Some(self.time)), text(self.summary.as_ref()), text(self.icon.as_ref()), time(self.dawn_time), time(self.dusk_time),
+            time(self.sunrise_time), time(self.sunset_time), float(self.moon_phase), float(self.precip_accumulation),
+            float(self.precip_probability), float(self.temperature_min), time(self.temperature_min_time), float(self.temperature_max),
+            time(self.temperature_max_time), float(self.cloud_cover), float(self.uv_index) ]
+    }
+}
+
+impl ExportRecord for BillingPeriodRow {
+    const QUERY: &'static str = QUERY_EXPORT_BILLING;
+    const TIME_COLUMN: &'static str = "period_start";
+    const HAS_SERIAL: bool = false;
+    fn columns() -> Vec<(&'static str, ColumnKind)> {
+        use ColumnKind::*;
+        vec![ ("period_start", Text), ("period_end", Text), ("days", Int), ("complete", Bool), ("import_kwh", Float),
+            ("export_kwh", Float), ("production_kwh", Float), ("load_kwh", Float), ("energy_cost", Float), ("energy_credit", Float),
+            ("fixed_charges", Float), ("net_cost", Float), ("no_solar_cost", Float), ("savings", Float), ("true_up_start", Text),
+            ("meter_import_kwh", Float), ("meter_export_kwh", Float), ("meter_net_kwh", Float), ("true_up_import_kwh", Float),
+            ("true_up_export_kwh", Float), ("true_up_net_kwh", Float), ("true_up_net_cost", Float), ("true_up_savings", Float),
+            ("calc_time", Time) ]
+    }
+    fn values(&self) -> Vec<Value> {
+        vec![ Value::Text(self.period_start.to_string()), Value::Text(self.period_end.to_string()), int(Some(self.days)),
+            Value::Bool(self.complete), float(Some(self.import_kwh)), float(Some(self.export_kwh)), float(Some(self.production_kwh)),
+            float(Some(self.load_kwh)), float(Some(self.energy_cost)), float(Some(self.energy_credit)), float(Some(self.fixed_charges)),
+            float(Some(self.net_cost)), float(Some(self.no_solar_cost)), float(Some(self.savings)),
+            Value::Text(self.true_up_start.to_string()), float(self.meter_import_kwh), float(self.meter_export_kwh),
+            float(self.meter_net_kwh), float(Some(self.true_up_import_kwh)), float(Some(self.true_up_export_kwh)),
+            float(Some(self.true_up_net_kwh)), float(Some(self.true_up_net_cost)), float(Some(self.true_up_savings)),
+            time(Some(self.calc_time)) ]
+    }
+}
+
+// FUNCTIONS
+
+fn text(value: Option<&String>) -> Value {
+    value.map( |value| Value::Text(value.clone()) ).unwrap_or(Value::Null)
+}
+fn time(value: Option<DateTime<Utc>>) -> Value {
+    value.map(Value::Time).unwrap_or(Value::Null)
+}
+fn int<T: Into<i64>>(value: Option<T>) -> Value {
+    value.map( |value| Value::Int(value.into()) ).unwrap_or(Value::Null)
+}
+fn float<T: Into<f64>>(value: Option<T>) -> Value {
+    value.map( |value| Value::Float(value.into()) ).unwrap_or(Value::Null)
+}
+
+pub async fn run_export(export_args: &ExportArgs, sql_pool: &sqlx::Pool<sqlx::MySql>) -> Result<(), ExportError> {
+    let tz: Tz = export_args.timezone.parse().map_err( |_| ExportError::Timezone(export_args.timezone.clone()) )?;
+    let start = parse_local_time(&export_args.start, tz)?;
+    let end = parse_local_time(&export_args.end, tz)?;
+    let resample = match &export_args.resample {
+        Some(interval) => match parse_duration(interval) {
+            Some(period) if period >= TimeDelta::seconds(1) && period.subsec_nanos() == 0 => Some(period),
+            _ => return Err( ExportError::Resample(interval.clone()) ),
+        },
+        None => None,
+    };
+
+    let mut table = match export_args.device {
+        ExportDevice::Supervisor => read_table::<Supervisor>(sql_pool, &export_args.serial, start, end).await?,
+        ExportDevice::Production => read_table::<ProductionMeter>(sql_pool, &export_args.serial, start, end).await?,
+        ExportDevice::Consumption => read_table::<ConsumptionMeter>(sql_pool, &export_args.serial, start, end).await?,
+        ExportDevice::Inverter => read_table::<Inverter>(sql_pool, &export_args.serial, start, end).await?,
+        ExportDevice::CurrentWx => read_table::<CurrentWx>(sql_pool, &export_args.serial, start, end).await?,
+        ExportDevice::DailyWx => read_table::<DailyWxData>(sql_pool, &export_args.serial, start, end).await?,
+        ExportDevice::Billing => {
+            if resample.is_some() {
+                return Err(ExportError::ResampleBilling)
+            }
+            // period_start is a local date.  Compared as midnight of the local start / end dates
+            let local_date = |time: DateTime<Utc>| time.with_timezone(&tz).date_naive().and_time(NaiveTime::MIN).and_utc();
+            read_table::<BillingPeriodRow>(sql_pool, &export_args.serial, local_date(start), local_date(end)).await?
+        },
+    };
+    if let Some(period) = resample {
+        table = resample_table(&table, period, tz);
+    }
+    table.timezone = Some(tz);
+
+    archive::write_table(&table, &export_args.output, export_args.format.into()).map_err(ExportError::Write)?;
+    info!("Export: {} {:?} rows from {} to {} written to {}", table.rows.len(), export_args.device, start, end, export_args.output.display());
+    Ok(())
+}
+
+pub async fn read_table<R: ExportRecord>(sql_pool: &sqlx::Pool<sqlx::MySql>, serials: &[String], start: DateTime<Utc>, end: DateTime<Utc>)
+    -> Result<Table, ExportError> {
+    // reads rows in [ start, end ) ordered by serial then time.  Serial filter is bound, never formatted into the query.
+    if !serials.is_empty() && !R::HAS_SERIAL {
+        return Err(ExportError::Serial)
+    }
+    let serial_filter = if serials.is_empty() {
+        String::new()
+    } else {
+        format!(" AND serial IN ( {} )", vec!["?"; serials.len()].join(", "))
+    };
+    let order = if R::HAS_SERIAL { "serial, " } else { "" };
+    let query = format!("SELECT * FROM ( {} ) AS export WHERE {} >= ? AND {} < ?{} ORDER BY {}{}",
+        R::QUERY, R::TIME_COLUMN, R::TIME_COLUMN, serial_filter, order, R::TIME_COLUMN);
+
+    let mut rows_query = sqlx::query_as::<_, R>(&query).bind(start).bind(end);
+    for serial in serials {
+        rows_query = rows_query.bind(serial);
+    }
+    let records = rows_query.fetch_all(sql_pool).await.map_err(ExportError::Sql)?;
+
+    Ok( Table {
+        columns: R::columns().into_iter().map( |(name, kind)| (name.to_string(), kind) ).collect(),
+        rows: records.iter().map( |record| record.values() ).collect(),
+        timezone: None,
+    })
+}
+
+pub fn parse_local_time(time: &str, tz: Tz) -> Result<DateTime<Utc>, ExportError> {
+    // RFC 3339 (with offset) or a local date / date time in tz
+    let time = time.trim();
+    if let Ok(with_offset) = DateTime::parse_from_rfc3339(time) {
+        return Ok( with_offset.with_timezone(&Utc) )
+    }
+    let local = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"].iter()
+        .find_map( |format| NaiveDateTime::parse_from_str(time, format).ok() )
+        .or_else( || NaiveDate::parse_from_str(time, "%Y-%m-%d").ok().and_then( |date| date.and_hms_opt(0, 0, 0) ) )
+        .ok_or( ExportError::Time(time.to_string()) )?;
+    // times skipped by a DST change don't exist locally.  Ambiguous times use the earlier one.
+    tz.from_local_datetime(&local).earliest()
+        .map( |local| local.with_timezone(&Utc) )
+        .ok_or( ExportError::Time(time.to_string()) )
+}
+
+fn interval_start(time: DateTime<Utc>, period: TimeDelta, tz: Tz) -> DateTime<Utc> {
+    // start of the resample interval containing time.  Intervals are aligned to local midnight in tz (for intervals that divide
+    // a day evenly).
+    let local_s = time.with_timezone(&tz).naive_local().and_utc().timestamp();
+    let start_s = local_s - local_s.rem_euclid(period.num_seconds());
+    DateTime::from_timestamp(start_s, 0)
+        .and_then( |local_start| tz.from_local_datetime(&local_start.naive_utc()).earliest() )
+        .map( |start| start.with_timezone(&Utc) )
+        .unwrap_or(time)
+}
+
+fn resample_table(table: &Table, period: TimeDelta, tz: Tz) -> Table {
+    // Groups rows by serial (if any) and interval.  Numbers are averaged, except lifetime energy counters which keep the last
+    // reading.  Other values keep the last non-null value.  The time column becomes the interval start.
+    let time_index = table.columns.iter().position( |(_, kind)| *kind == ColumnKind::Time ).unwrap_or(0);
+    let serial_index = table.columns.iter().position( |(name, _)| name == "serial" );
+
+    let mut groups: BTreeMap<(String, DateTime<Utc>), Vec<&Vec<Value>>> = BTreeMap::new();
+    for row in &table.rows {
+        let Some(Value::Time(row_time)) = row.get(time_index) else { continue };
+        let serial = match serial_index.and_then( |index| row.get(index) ) {
+            Some(Value::Text(serial)) => serial.clone(),
+            _ => String::new(),
+        };
+        groups.entry( (serial, interval_start(*row_time, period, tz)) ).or_default().push(row);
+    }
+
+    let averaged = |index: usize, name: &str, kind: ColumnKind| index != time_index && !name.contains("ltea")
+        && matches!( kind, ColumnKind::Int | ColumnKind::Float );
+    let mut columns: Vec<(String, ColumnKind)> = table.columns.iter().enumerate()
+        .map( |(index, (name, kind))| ( name.clone(), if averaged(index, name, *kind) { ColumnKind::Float } else { *kind } ) )
+        .collect();
+    columns.push( ("samples".to_string(), ColumnKind::Int) );
+
+    let rows = groups.into_iter().map( |((_, start), rows)| {
+        let mut values: Vec<Value> = table.columns.iter().enumerate().map( |(index, (name, kind))| {
+            if index == time_index {
+                Value::Time(start)
+            } else if averaged(index, name, *kind) {
+                let numbers: Vec<f64> = rows.iter().filter_map( |row| match row.get(index) {
+                    Some(Value::Float(value)) => Some(*value),
+                    Some(Value::Int(value)) => Some(*value as f64),
+                    _ => None,
+                }).collect();
+                if numbers.is_empty() { Value::Null } else { Value::Float( numbers.iter().sum::<f64>() / numbers.len() as f64 ) }
+            } else {
+                rows.iter().rev().filter_map( |row| row.get(index) ).find( |value| **value != Value::Null ).cloned().unwrap_or(Value::Null)
+            }
+        }).collect();
+        values.push( Value::Int(rows.len() as i64) );
+        values
+    }).collect();
+
+    Table { columns, rows, timezone: table.timezone }
+}
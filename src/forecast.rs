@@ -0,0 +1,399 @@
+/*
+Solar production forecast.  Runs fully offline from data already stored in the solar db.
+1)  Fits a per-site model on production history: hourly energy from production_meters_data against clear-sky irradiation
+    (from sun position) and observed cloud cover (from current_wx).
+        expected kWh = k * clear-sky kWh/m² * ( 1 - a * cloudCover^3.4 )
+    k scales clear-sky irradiation to this array (size, orientation, losses) and a is how strongly clouds reduce output.
+2)  Applies the model to the latest stored hourly forecast (hourly_wx_forecast), falling back to the daily forecast (daily_wx)
+    for hours past the end of the hourly forecast, and writes expected kWh per hour and per day to production_forecast_hourly
+    and production_forecast_daily for Grafana.
+*/
+
+// USE STATEMENTS
+    use std::collections::BTreeMap;
+    use chrono::{ DateTime, DurationRound, NaiveDate, TimeDelta, Utc };
+    use chrono_tz::Tz;
+    use log::{ debug, error, info, warn };
+    use serde::Deserialize;
+
+    use crate::{ energy, scheduler::{ Job, Schedule }, solar_position, validation::ConfigError };
+
+// CONSTANTS
+    // exponent of cloud cover in model (Kasten-Czeplak cloud cover model)
+    const CLOUD_COVER_EXPONENT: f64 = 3.4;
+    // cloud cover factor used when history isn't enough to fit it
+    const DEFAULT_CLOUD_FACTOR: f64 = 0.75;
+    // minimum hours of history needed to fit both model parameters
+    const MIN_FIT_SAMPLES: usize = 48;
+    // hours with less clear-sky irradiation than this (kWh/m²) are left out of the fit.  Dawn / dusk hours are mostly noise.
+    const MIN_FIT_CLEAR_SKY_KWH_M2: f64 = 0.05;
+    // sql query to get observed cloud cover since a time
+    const QUERY_GET_CLOUD_COVER_HISTORY: &str =
+    r#"
+        SELECT time, cloudCover
+        FROM current_wx
+        WHERE time >= ? AND cloudCover IS NOT NULL
+        ORDER BY time
+    "#;
+    // sql query to get latest hourly cloud cover forecast from a time on
+    const QUERY_GET_LATEST_HOURLY_CLOUD_FORECAST: &str =
+    r#"
+        SELECT time, cloudCover
+        FROM hourly_wx_forecast
+        WHERE issueTime = ( SELECT MAX(issueTime) FROM hourly_wx_forecast ) AND time >= ? AND cloudCover IS NOT NULL
+        ORDER BY time
+    "#;
+    // sql query to get daily cloud cover forecast from a time on
+    const QUERY_GET_DAILY_CLOUD_FORECAST: &str =
+    r#"
+        SELECT time, cloudCover
+        FROM daily_wx
+        WHERE time >= ? AND cloudCover IS NOT NULL
+        ORDER BY time
+    "#;
+    const INSERT_FORECAST_MODEL_QUERY: &str =
+    r#"
+        REPLACE INTO production_forecast_model
+            ( fitTime, k, cloudFactor, samples, rmseKwh )
+            VALUES ( ?, ?, ?, ?, ? )
+    "#;
+    const REPLACE_PRODUCTION_FORECAST_HOURLY_QUERY: &str =
+    r#"
+        REPLACE INTO production_forecast_hourly
+            ( issueTime, time, clearSkyKwhM2, cloudCover, expectedKwh, source )
+            VALUES ( ?, ?, ?, ?, ?, ? )
+    "#;
+    const REPLACE_PRODUCTION_FORECAST_DAILY_QUERY: &str =
+    r#"
+        REPLACE INTO production_forecast_daily
+            ( issueTime, date, expectedKwh, hoursFromHourly, hoursFromDaily )
+            VALUES ( ?, ?, ?, ?, ? )
+    "#;
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+#[derive(Debug, Deserialize, Clone )]
+pub struct ForecastConf {
+    #[serde( default = "default_forecast_interval" )]
+    pub interval: u64,
+    #[serde( default = "default_forecast_interval_unit" )]
+    pub interval_unit: char,
+    #[serde( with = "crate::integer_to_chrono_time_delta_ms", default = "default_forecast_offset" )]
+    pub offset: TimeDelta,
+    // cron expression or "every N<unit> [at <offset>]".  Used instead of interval / interval_unit / offset if provided
+    #[serde( default = "default_forecast_schedule" )]
+    pub schedule: String,
+    #[serde( with = "crate::integer_to_chrono_time_delta_ms", default = "default_forecast_jitter" )]
+    pub jitter: TimeDelta,
+    // days of production history used to fit model
+    #[serde( default = "default_forecast_history_days" )]
+    pub history_days: i64,
+    // days to forecast, starting today
+    #[serde( default = "default_forecast_days" )]
+    pub forecast_days: i64,
+    // timezone used to group hours into days, eg "America/Los_Angeles"
+    #[serde( default = "default_forecast_timezone" )]
+    pub timezone: String,
+}
+fn default_forecast_interval() -> u64 {
+    1
+}
+fn default_forecast_interval_unit() -> char {
+    'h'
+}
+fn default_forecast_offset() -> TimeDelta {
+    TimeDelta::minutes(2)
+}
+fn default_forecast_schedule() -> String {
+    String::new()
+}
+fn default_forecast_jitter() -> TimeDelta {
+    TimeDelta::zero()
+}
+fn default_forecast_history_days() -> i64 {
+    60
+}
+fn default_forecast_days() -> i64 {
+    3
+}
+fn default_forecast_timezone() -> String {
+    "UTC".to_string()
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ForecastModel {
+    k: f64,
+    cloud_factor: f64,
+    samples: usize,
+    rmse_kwh: f64,
+}
+
+impl ForecastModel {
+    fn expected_kwh(&self, clear_sky_kwh_m2: f64, cloud_cover: f64) -> f64 {
+        ( self.k * clear_sky_kwh_m2 * ( 1.0 - self.cloud_factor * cloud_cover.clamp(0.0, 1.0).powf(CLOUD_COVER_EXPONENT) ) ).max(0.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct FitSample {
+    clear_sky_kwh_m2: f64,
+    cloud_cover: f64,
+    energy_kwh: f64,
+}
+
+// FUNCTIONS
+
+pub async fn forecast_to_mysql(solar_pool: Option<sqlx::Pool<sqlx::MySql>>, forecast_conf: ForecastConf, lat: f64, long: f64,
+    mut forecast_job: Job) {
+    // timezone verified at startup by verify_forecast_conf
+    let tz: Tz = forecast_conf.timezone.parse().unwrap_or(Tz::UTC);
+
+    while forecast_job.tick().await.is_some() {
+
+        match &solar_pool {
+            Some(sql_pool) => {
+                let fit_time = Utc::now().duration_trunc(TimeDelta::seconds(1)).unwrap_or(Utc::now());
+                match fit_forecast_model(sql_pool, &forecast_conf, lat, long, fit_time).await {
+                    Some(model) => {
+                        insert_forecast_model_to_mysql(sql_pool, &model, fit_time).await;
+                        run_production_forecast(sql_pool, &forecast_conf, &model, lat, long, tz, fit_time).await;
+                    },
+                    None => warn!("Production forecast model could not be fit.  Forecast skipped."),
+                }
+            },
+            None => error!("Couldn't get sql pool"),
+        }
+    }
+}
+
+async fn fit_forecast_model(sql_pool: &sqlx::Pool<sqlx::MySql>, forecast_conf: &ForecastConf, lat: f64, long: f64,
+    fit_time: DateTime<Utc>) -> Option<ForecastModel> {
+    // Fits model parameters k and a by least squares on hourly history.  Model is linear in k and k*a:
+    //     E = k * CS - (k * a) * CS * cc^3.4
+    // If there isn't enough history (or cloud cover never varied), a is fixed at DEFAULT_CLOUD_FACTOR and only k is fit.
+
+    let history_start = fit_time - TimeDelta::days(forecast_conf.history_days);
+
+    let production = energy::get_counter_readings(sql_pool, "production_meters_data", "net_ltea_3phsum_kwh", history_start, fit_time).await;
+    let production = match production {
+        Ok(readings) => readings,
+        Err(prod_eff) => {
+            error!("Unable to get production history for forecast model. Err: {}", prod_eff);
+            return None
+        },
+    };
+
+    let cloud_cover = sqlx::query_as::<_, (DateTime<Utc>, f64)>(QUERY_GET_CLOUD_COVER_HISTORY)
+        .bind(history_start)
+        .fetch_all(sql_pool).await;
+    let cloud_cover = match cloud_cover {
+        Ok(rows) => rows,
+        Err(cc_eff) => {
+            error!("Unable to get cloud cover history for forecast model. Err: {}", cc_eff);
+            return None
+        },
+    };
+
+    // hourly energy per production meter, summed across meters
+    let hourly_energy = energy::total_bucket_energy(&production, TimeDelta::hours(1), TimeDelta::hours(1));
+
+    // average observed cloud cover per hour
+    let mut hourly_cloud: BTreeMap<DateTime<Utc>, (f64, u32)> = BTreeMap::new();
+    for (time, cc) in cloud_cover.iter() {
+        if let Ok(hour) = time.duration_trunc(TimeDelta::hours(1)) {
+            let entry = hourly_cloud.entry(hour).or_insert((0.0, 0));
+            entry.0 += cc;
+            entry.1 += 1;
+        }
+    }
+
+    let samples: Vec<FitSample> = hourly_energy.iter()
+        .filter_map( |(hour, energy_kwh)| {
+            let (cc_sum, cc_count) = hourly_cloud.get(hour)?;
+            let clear_sky_kwh_m2 = solar_position::clear_sky_ghi_energy(lat, long, *hour, TimeDelta::hours(1));
+            if clear_sky_kwh_m2 < MIN_FIT_CLEAR_SKY_KWH_M2 {
+                return None
+            }
+            Some( FitSample { clear_sky_kwh_m2, cloud_cover: cc_sum / *cc_count as f64, energy_kwh: *energy_kwh } )
+        })
+        .collect();
+
+    if samples.is_empty() {
+        warn!("No hours with both production and cloud cover history in last {} days.", forecast_conf.history_days);
+        return None
+    }
+
+    let model = fit_samples(&samples);
+    info!("Production forecast model fit on {} hours. k: {:.3}, cloud factor: {:.3}, rmse: {:.3} kWh",
+        model.samples, model.k, model.cloud_factor, model.rmse_kwh);
+    Some(model)
+}
+
+fn fit_samples(samples: &[FitSample]) -> ForecastModel {
+    let (mut s11, mut s12, mut s22, mut t1, mut t2) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for sample in samples.iter() {
+        let x1 = sample.clear_sky_kwh_m2;
+        let x2 = sample.clear_sky_kwh_m2 * sample.cloud_cover.clamp(0.0, 1.0).powf(CLOUD_COVER_EXPONENT);
+        s11 += x1 * x1;
+        s12 += x1 * x2;
+        s22 += x2 * x2;
+        t1 += x1 * sample.energy_kwh;
+        t2 += x2 * sample.energy_kwh;
+    }
+
+    let det = s11 * s22 - s12 * s12;
+    let (mut k, mut cloud_factor) = (0.0, DEFAULT_CLOUD_FACTOR);
+    let mut two_param_fit = false;
+    if samples.len() >= MIN_FIT_SAMPLES && det > 1e-9 * s11 * s22 {
+        let beta1 = ( t1 * s22 - t2 * s12 ) / det;
+        let beta2 = ( s11 * t2 - s12 * t1 ) / det;
+        if beta1 > 0.0 {
+            k = beta1;
+            cloud_factor = ( -beta2 / beta1 ).clamp(0.0, 1.0);
+            two_param_fit = true;
+        }
+    }
+    if !two_param_fit {
+        // fit only k with cloud factor fixed:  E = k * CS * ( 1 - a * cc^3.4 )
+        let (mut sxx, mut sxy) = (0.0, 0.0);
+        for sample in samples.iter() {
+            let x = sample.clear_sky_kwh_m2 * ( 1.0 - cloud_factor * sample.cloud_cover.clamp(0.0, 1.0).powf(CLOUD_COVER_EXPONENT) );
+            sxx += x * x;
+            sxy += x * sample.energy_kwh;
+        }
+        if sxx > 0.0 {
+            k = ( sxy / sxx ).max(0.0);
+        }
+        debug!("Forecast model fit with fixed cloud factor {} ({} samples).", cloud_factor, samples.len());
+    }
+
+    let mut model = ForecastModel { k, cloud_factor, samples: samples.len(), rmse_kwh: 0.0 };
+    let sq_err: f64 = samples.iter()
+        .map( |sample| ( model.expected_kwh(sample.clear_sky_kwh_m2, sample.cloud_cover) - sample.energy_kwh ).powi(2) )
+        .sum();
+    model.rmse_kwh = ( sq_err / samples.len() as f64 ).sqrt();
+    model
+}
+
+async fn insert_forecast_model_to_mysql(sql_pool: &sqlx::Pool<sqlx::MySql>, model: &ForecastModel, fit_time: DateTime<Utc>) {
+    let model_result = sqlx::query(INSERT_FORECAST_MODEL_QUERY)
+        .bind(fit_time)
+        .bind(model.k)
+        .bind(model.cloud_factor)
+        .bind(model.samples as u32)
+        .bind(model.rmse_kwh)
+        .execute(sql_pool).await;
+
+    if let Err(model_eff) = model_result {
+        error!("Forecast model failed to upload to Mysql solar db production_forecast_model table. Error: {}", model_eff);
+    }
+}
+
+async fn run_production_forecast(sql_pool: &sqlx::Pool<sqlx::MySql>, forecast_conf: &ForecastConf, model: &ForecastModel,
+    lat: f64, long: f64, tz: Tz, issue_time: DateTime<Utc>) {
+    // Forecasts every hour from local midnight today through the end of the last forecast day.  Hours use the latest hourly
+    // cloud cover forecast when there is one, otherwise the daily cloud cover forecast for that local day.
+
+    let today = issue_time.with_timezone(&tz).date_naive();
+    let forecast_start = energy::local_midnight_utc(today, tz);
+    let forecast_end = energy::local_midnight_utc(today + TimeDelta::days(forecast_conf.forecast_days), tz);
+
+    let hourly_forecast = sqlx::query_as::<_, (DateTime<Utc>, f64)>(QUERY_GET_LATEST_HOURLY_CLOUD_FORECAST)
+        .bind(forecast_start)
+        .fetch_all(sql_pool).await;
+    let hourly_cloud: BTreeMap<DateTime<Utc>, f64> = match hourly_forecast {
+        Ok(rows) => rows.into_iter().collect(),
+        Err(hourly_eff) => {
+            error!("Unable to get hourly cloud cover forecast. Err: {}", hourly_eff);
+            BTreeMap::new()
+        },
+    };
+
+    let daily_forecast = sqlx::query_as::<_, (DateTime<Utc>, f64)>(QUERY_GET_DAILY_CLOUD_FORECAST)
+        .bind(forecast_start - TimeDelta::days(1))
+        .fetch_all(sql_pool).await;
+    let daily_cloud: BTreeMap<NaiveDate, f64> = match daily_forecast {
+        Ok(rows) => rows.into_iter().map( |(time, cc)| (time.with_timezone(&tz).date_naive(), cc) ).collect(),
+        Err(daily_eff) => {
+            error!("Unable to get daily cloud cover forecast. Err: {}", daily_eff);
+            BTreeMap::new()
+        },
+    };
+
+    // date -> ( expected kWh, hours from hourly forecast, hours from daily forecast )
+    let mut daily_totals: BTreeMap<NaiveDate, (f64, u32, u32)> = BTreeMap::new();
+    let mut hours_failed: u32 = 0;
+    let mut hour = forecast_start;
+
+    while hour < forecast_end {
+        let date = hour.with_timezone(&tz).date_naive();
+        let clear_sky_kwh_m2 = solar_position::clear_sky_ghi_energy(lat, long, hour, TimeDelta::hours(1));
+
+        let (cloud_cover, source) = match hourly_cloud.get(&hour) {
+            Some(cc) => (Some(*cc), "hourly"),
+            None => (daily_cloud.get(&date).copied(), "daily"),
+        };
+
+        if let Some(cc) = cloud_cover {
+            let expected_kwh = model.expected_kwh(clear_sky_kwh_m2, cc);
+            let totals = daily_totals.entry(date).or_insert((0.0, 0, 0));
+            totals.0 += expected_kwh;
+            if source == "hourly" { totals.1 += 1 } else { totals.2 += 1 }
+
+            let hourly_result = sqlx::query(REPLACE_PRODUCTION_FORECAST_HOURLY_QUERY)
+                .bind(issue_time)
+                .bind(hour)
+                .bind(clear_sky_kwh_m2)
+                .bind(cc)
+                .bind(expected_kwh)
+                .bind(source)
+                .execute(sql_pool).await;
+            if let Err(hourly_eff) = hourly_result {
+                error!("Production forecast for {} failed to upload to Mysql solar db production_forecast_hourly table. Error: {}",
+                    hour.format("%Y-%m-%d %H:%M:%S"), hourly_eff);
+                hours_failed += 1;
+            }
+        } else if clear_sky_kwh_m2 > 0.0 {
+            debug!("No cloud cover forecast for {}.  Hour not forecast.", hour.format("%Y-%m-%d %H:%M:%S"));
+        }
+        hour += TimeDelta::hours(1);
+    }
+
+    for (date, (expected_kwh, hours_hourly, hours_daily)) in daily_totals.iter() {
+        let daily_result = sqlx::query(REPLACE_PRODUCTION_FORECAST_DAILY_QUERY)
+            .bind(issue_time)
+            .bind(date)
+            .bind(expected_kwh)
+            .bind(hours_hourly)
+            .bind(hours_daily)
+            .execute(sql_pool).await;
+        match daily_result {
+            Ok(_) => debug!("Production forecast for {}: {:.2} kWh", date, expected_kwh),
+            Err(daily_eff) => error!("Production forecast for {} failed to upload to Mysql solar db production_forecast_daily table. Error: {}",
+                date, daily_eff),
+        }
+    }
+    match hours_failed {
+        0 => info!("Production forecast for {} days uploaded to MySql solar db.", daily_totals.len()),
+        _ => warn!("Production forecast partially uploaded to MySql solar db. {} hours failed.", hours_failed),
+    }
+}
+
+pub fn verify_forecast_conf(forecast_conf: &ForecastConf, errors: &mut Vec<ConfigError>) {
+    // verifies timezone is a valid IANA timezone, interval_unit, schedule and days.  Adds a ConfigError to errors for each problem
+    if forecast_conf.timezone.parse::<Tz>().is_err() {
+        errors.push( ConfigError::invalid("forecast.timezone", &forecast_conf.timezone, "Must be a valid timezone (eg America/Los_Angeles).") );
+    }
+    if !matches!( forecast_conf.interval_unit, 'd' | 'h' | 'm' | 's') {
+        errors.push( ConfigError::invalid("forecast.interval_unit", forecast_conf.interval_unit, "Must be 'd', 'h', 'm', or 's'.") );
+    } else if let Err(schedule_eff) = Schedule::from_conf(&forecast_conf.schedule, forecast_conf.interval, forecast_conf.interval_unit,
+        forecast_conf.offset) {
+        errors.push( ConfigError::invalid("forecast.schedule", &forecast_conf.schedule, &schedule_eff.to_string()) );
+    }
+    if forecast_conf.history_days <= 0 {
+        errors.push( ConfigError::invalid("forecast.history_days", forecast_conf.history_days, "Must be positive.") );
+    }
+    if forecast_conf.forecast_days <= 0 {
+        errors.push( ConfigError::invalid("forecast.forecast_days", forecast_conf.forecast_days, "Must be positive.") );
+    }
+}
@@ -6,28 +6,87 @@ Program completes the following actions:
 2)  Future: Processes energy produced and energy consumed data (in solar db) to tabular format for data analysis  
 */
 
+// MODULES
+    mod api;
+    mod archive;
+    mod counters;
+    mod energy;
+    mod export;
+    mod forecast;
+    mod performance;
+    mod plausibility;
+    mod pws;
+    mod reconciliation;
+    mod reload;
+    mod retention;
+    mod scheduler;
+    mod settings;
+    mod shutdown;
+    mod solar_position;
+    mod supervisor;
+    mod systemd;
+    mod tariff;
+    mod validation;
+    mod weather;
+
 // USE STATEMENTS
     use reqwest::get;
     use regex::Regex;
     use once_cell::sync::{Lazy, OnceCell};
     use myloginrs::parse as myloginrs_parse;
-    use tokio::{ spawn, time::{ Interval, interval_at, Duration as TokioDuration } };
-    use std::{ str, fs, path::PathBuf, env, cmp::Ordering, error, fmt, sync::Mutex };
+    use tokio::{ sync::watch, time::MissedTickBehavior };
+    use std::{ str, fs, path::PathBuf, env, cmp::Ordering, error, fmt, sync::Mutex, collections::BTreeMap, future::Future, pin::Pin };
     use log::{ debug, error, info, warn };
     use log4rs;
-    use chrono::{ TimeDelta, DateTime, Utc, NaiveDateTime, Duration, DurationRound };
+    use chrono::{ TimeDelta, DateTime, Utc, NaiveDate };
     use sqlx::mysql::MySqlPoolOptions;
     use serde::Deserialize;
     use serde_json::Result;
     use config::Config;
+    use clap::{ Parser, Subcommand };
+
+    use api::{ ApiConf, Latest, serve_api, verify_api_conf };
+    use counters::{ CounterCheck, insert_counter_events_to_mysql };
+    use export::{ ExportArgs, run_export };
+    use forecast::{ ForecastConf, forecast_to_mysql, verify_forecast_conf };
+    use performance::insert_production_performance_to_mysql;
+    use plausibility::{ Plausibility, PlausibilityConf, insert_quarantined_readings_to_mysql, out_of_range_counts, verify_plausibility_conf };
+    use pws::{ PwsConf, PwsReadings, insert_pws_data_to_mysql, poll_pws, receive_pws_push, verify_pws_conf };
+    use reconciliation::{ ReconciliationConf, reconciliation_to_mysql, verify_reconciliation_conf };
+    use reload::ConfigWatcher;
+    use retention::{ RetentionConf, retention_to_mysql, verify_retention_conf };
+    use scheduler::{ Job, Schedule, Scheduler, parse_missed_tick_behavior };
+    use shutdown::Shutdown;
+    use supervisor::{ TaskSupervisor, run_or_idle };
+    use tariff::{ TariffConf, tariff_to_mysql, verify_tariff_conf };
+    use validation::{ ConfigError, EXIT_CONFIG_ERROR };
+    use weather::{ OpenMeteo, OpenMeteoConf, PirateWeather, WxStation, WxStationConf, verify_open_meteo_conf, verify_wx_station_conf,
+        weather_to_mysql };
 
 // CONSTANTS
+    // configuration file (config.yml) name without extension
+    const CONFIG_NAME: &str = "config";
+    // tasks restarted on config reload when a setting in one of their sections (or a listed setting) changes.  mysql changes
+    // restart every task.
+    const TASK_CONF_SECTIONS: [(&str, &[&str]); 8] = [
+        ("weather", &weather::WX_PROVIDERS),
+        ("pvs6", &["pvs6", "site", "plausibility", "pirate_wx.lat", "pirate_wx.long", "open_meteo.lat", "open_meteo.long", "wx_station.lat", "wx_station.long"]),
+        ("forecast", &["forecast", "site", "pirate_wx.lat", "pirate_wx.long", "open_meteo.lat", "open_meteo.long", "wx_station.lat", "wx_station.long"]),
+        ("pws", &["pws"]),
+        ("tariff", &["tariff"]),
+        ("reconciliation", &["reconciliation"]),
+        ("retention", &["retention"]),
+        ("api", &["api"]),
+    ];
     const URL_DEVICES_API: &str = "/cgi-bin/dl_cgi?Command=DeviceList";
     const SUPERVISOR: &str = "PVS";
     const METER: &str = "Power Meter";
     const INVERTER: &str = "Inverter";
     const PRODUCTION_METER: &str = "PVS5-METER-P";
     const CONSUMPTION_METER: &str = "PVS5-METER-C";
+    // longest wait for tasks to finish work in progress after SIGTERM / SIGINT.  Keep below systemd TimeoutStopSec (default 90s)
+    const SHUTDOWN_DEADLINE: std::time::Duration = std::time::Duration::from_secs(30);
+    const POOL_CLOSE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
     // SQL QUERY CONSTANTS
     const SUP_INSERT_QUERY: &str = 
     r#"
@@ -101,13 +160,23 @@ Program completes the following actions:
         ) AS inv_max
         ON inv.serial = inv_max.serial AND inv.data_time = inv_max.dt_max
     "#;
-    //sql query insert (if new) or replace daily weather.
+    //sql query insert (if new) or replace daily weather.  daily_wx holds the latest forecast for each day.
     const REPLACE_DAILY_WX_QUERY: &str = 
         r#"
             REPLACE INTO daily_wx 
-                ( latitude, longitude, time, sunriseTime, dawnTime, sunsetTime, duskTime, moonPhase, 
-                precipAccumulation, temperatureMin, temperatureMinTime, temperatureMax, temperatureMaxTime ) 
-                VALUES ( ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ? )
+                ( latitude, longitude, time, summary, icon, sunriseTime, dawnTime, sunsetTime, duskTime, moonPhase, 
+                precipAccumulation, precipProbability, temperatureMin, temperatureMinTime, temperatureMax, temperatureMaxTime,
+                cloudCover, uvIndex ) 
+                VALUES ( ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ? )
+        "#;
+    //sql query insert (if new) or replace daily weather forecast history.  Keyed by issueTime (time forecast was retrieved) and time (day
+    // forecast is for) so changes in each day's forecast over successive retrievals are kept.
+    const REPLACE_DAILY_WX_FORECAST_QUERY: &str =
+        r#"
+            REPLACE INTO daily_wx_forecast
+                ( latitude, longitude, issueTime, time, summary, icon, sunriseTime, sunsetTime, precipAccumulation, precipProbability,
+                temperatureMin, temperatureMax, cloudCover, uvIndex )
+                VALUES ( ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ? )
         "#;
     //sql query insert current Wx
     const INSERT_CURRENT_WX_QUERY: &str = 
@@ -119,6 +188,43 @@ Program completes the following actions:
                     currentDayLiquid, currentDaySnow ) 
                 VALUES ( ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ? )
         "#;
+    //sql query insert timing of each PVS6 poll.  Keyed by scheduled time of poll
+    const INSERT_PVS6_POLL_TIMING_QUERY: &str =
+        r#"
+            INSERT INTO pvs6_poll_timing
+                ( scheduled_time, request_time, response_time, data_time, latency_ms, data_lag_ms, offset_ms, success )
+                VALUES ( ?, ?, ?, ?, ?, ?, ?, ? )
+        "#;
+    //sql query insert (if new) or replace locally calculated sun times for a day
+    const REPLACE_SUN_TIMES_QUERY: &str =
+        r#"
+            REPLACE INTO sun_times
+                ( latitude, longitude, date, sunriseTime, solarNoonTime, sunsetTime )
+                VALUES ( ?, ?, ?, ?, ?, ? )
+        "#;
+    //sql query insert (if new) or replace hourly forecast.  Keyed by issueTime (time forecast was retrieved) and time (time forecast is valid for)
+    const REPLACE_HOURLY_WX_FORECAST_QUERY: &str =
+        r#"
+            REPLACE INTO hourly_wx_forecast
+                ( latitude, longitude, issueTime, time, summary, icon, precipIntensity, precipProbability, precipAccumulation,
+                    precipType, temperature, apparentTemperature, dewPoint, humidity, pressure, windSpeed, windGust, windBearing,
+                    cloudCover, uvIndex, visibility, ozone, smoke )
+                VALUES ( ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ? )
+        "#;
+    //sql query insert weather alert if not already stored.  Alerts are deduplicated by alertId (alert uri, or title/time/regions if no uri)
+    const INSERT_IGNORE_WX_ALERT_QUERY: &str =
+        r#"
+            INSERT IGNORE INTO wx_alerts
+                ( alertId, latitude, longitude, title, severity, regions, time, expires, description, uri, firstSeen )
+                VALUES ( ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ? )
+        "#;
+    //sql query insert (if new) or replace minutely precipitation forecast.  Keyed by issueTime and time
+    const REPLACE_MINUTELY_WX_FORECAST_QUERY: &str =
+        r#"
+            REPLACE INTO minutely_wx_forecast
+                ( latitude, longitude, issueTime, time, precipIntensity, precipProbability, precipIntensityError, precipType )
+                VALUES ( ?, ?, ?, ?, ?, ?, ?, ? )
+        "#;
 
 // ENUMS, STRUCTURES AND IMPLEMENTATIONS
 
@@ -480,21 +586,166 @@ impl fmt::Display for ErrDeSerEpoch {
 }
 
 //Config structures
+// Command line.  With no command, runs the collectors.
+#[derive(Parser, Debug)]
+#[command(version, about = "Sunpower PVS6 and Pirate Weather to mysql")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Export device or weather data for a time range to CSV, NDJSON or Parquet
+    Export(ExportArgs),
+}
+
 #[derive(Debug, Deserialize, Clone)]
 struct Conf {
-    pirate_wx: PirateWxConf,
-    pvs6: Pvs6Conf,
-    mysql: MySqlConf
+    // collectors.  Each is optional.  Only one weather provider (pirate_wx, open_meteo or wx_station) can be configured.
+    #[serde( default = "default_opt_pirate_wx_conf" )]
+    pirate_wx: Option<PirateWxConf>,
+    #[serde( default = "default_opt_open_meteo_conf" )]
+    open_meteo: Option<OpenMeteoConf>,
+    #[serde( default = "default_opt_wx_station_conf" )]
+    wx_station: Option<WxStationConf>,
+    #[serde( default = "default_opt_pvs6_conf" )]
+    pvs6: Option<Pvs6Conf>,
+    // range checks of PVS6 meter and inverter readings
+    #[serde( default = "default_opt_plausibility_conf" )]
+    plausibility: Option<PlausibilityConf>,
+    // personal weather station readings, stored with PVS6 production meter readings
+    #[serde( default = "default_opt_pws_conf" )]
+    pws: Option<PwsConf>,
+    mysql: MySqlConf,
+    #[serde( default = "default_opt_site_conf" )]
+    site: Option<SiteConf>,
+    #[serde( default = "default_opt_forecast_conf" )]
+    forecast: Option<ForecastConf>,
+    #[serde( default = "default_opt_tariff_conf" )]
+    tariff: Option<TariffConf>,
+    #[serde( default = "default_opt_reconciliation_conf" )]
+    reconciliation: Option<ReconciliationConf>,
+    #[serde( default = "default_opt_retention_conf" )]
+    retention: Option<RetentionConf>,
+    #[serde( default = "default_opt_api_conf" )]
+    api: Option<ApiConf>,
 }
 impl Conf {
     fn new() -> Self {
         Self {
-            pirate_wx: PirateWxConf::new(),
-            pvs6: Pvs6Conf::new(),
+            pirate_wx: None,
+            open_meteo: None,
+            wx_station: None,
+            pvs6: None,
+            plausibility: None,
+            pws: None,
             mysql: MySqlConf::new(),
+            site: None,
+            forecast: None,
+            tariff: None,
+            reconciliation: None,
+            retention: None,
+            api: None,
+        }
+    }
+    fn site_location(&self) -> Option<(f64, f64)> {
+        // site lat / long.  From site section if configured, otherwise weather lat / long.
+        if let Some(site_conf) = &self.site {
+            return Some( site_conf.location() )
         }
+        self.weather_location()
+    }
+    fn weather_location(&self) -> Option<(f64, f64)> {
+        // lat / long of configured weather provider.  None if there isn't one, or pirate wx lat / long aren't numbers.
+        if let Some(pirate_wx_conf) = &self.pirate_wx {
+            return match ( pirate_wx_conf.lat.trim().parse::<f64>(), pirate_wx_conf.long.trim().parse::<f64>() ) {
+                (Ok(lat), Ok(long)) => Some( (lat, long) ),
+                _ => None,
+            }
+        }
+        self.open_meteo.as_ref().map( |open_meteo_conf| (open_meteo_conf.lat, open_meteo_conf.long) )
+            .or( self.wx_station.as_ref().map( |wx_station_conf| (wx_station_conf.lat, wx_station_conf.long) ) )
+    }
+    fn weather_providers(&self) -> Vec<&'static str> {
+        // config sections of configured weather providers
+        let configured = [ self.pirate_wx.is_some(), self.open_meteo.is_some(), self.wx_station.is_some() ];
+        weather::WX_PROVIDERS.into_iter().zip(configured).filter( |(_, configured)| *configured ).map( |(provider, _)| provider ).collect()
+    }
+}
+fn default_opt_pirate_wx_conf() -> Option<PirateWxConf> {
+    None
+}
+fn default_opt_open_meteo_conf() -> Option<OpenMeteoConf> {
+    None
+}
+fn default_opt_wx_station_conf() -> Option<WxStationConf> {
+    None
+}
+fn default_opt_pvs6_conf() -> Option<Pvs6Conf> {
+    None
+}
+fn default_opt_plausibility_conf() -> Option<PlausibilityConf> {
+    None
+}
+fn default_opt_pws_conf() -> Option<PwsConf> {
+    None
+}
+fn default_opt_site_conf() -> Option<SiteConf> {
+    None
+}
+fn default_opt_forecast_conf() -> Option<ForecastConf> {
+    None
+}
+fn default_opt_tariff_conf() -> Option<TariffConf> {
+    None
+}
+fn default_opt_reconciliation_conf() -> Option<ReconciliationConf> {
+    None
+}
+fn default_opt_retention_conf() -> Option<RetentionConf> {
+    None
+}
+fn default_opt_api_conf() -> Option<ApiConf> {
+    None
+}
+
+#[derive(Debug, Deserialize, Clone )]
+struct SiteConf {
+    #[serde( default = "default_opt_f64" )]
+    lat: Option<f64>,
+    #[serde( default = "default_opt_f64" )]
+    long: Option<f64>,
+    // array DC capacity in kW (sum of panel ratings)
+    capacity_kw: f64,
+    // panel tilt from horizontal, degrees
+    #[serde( default = "default_site_tilt" )]
+    tilt: f64,
+    // direction panels face, degrees clockwise from north (180 = south)
+    #[serde( default = "default_site_azimuth" )]
+    azimuth: f64,
+    // fraction of output lost to inverter, wiring, temperature, etc
+    #[serde( default = "default_site_losses" )]
+    losses: f64,
+}
+impl SiteConf {
+    fn location(&self) -> (f64, f64) {
+        // lat / long filled in from pirate wx conf by verify_site_conf if not in site section
+        ( self.lat.unwrap_or_default(), self.long.unwrap_or_default() )
     }
 }
+fn default_opt_f64() -> Option<f64> {
+    None
+}
+fn default_site_tilt() -> f64 {
+    20.0
+}
+fn default_site_azimuth() -> f64 {
+    180.0
+}
+fn default_site_losses() -> f64 {
+    0.14
+}
 
 #[derive(Debug, Deserialize, Clone )]
 struct PirateWxConf {
@@ -514,6 +765,23 @@ struct PirateWxConf {
     interval_unit: char,
     #[serde( with = "integer_to_chrono_time_delta_ms", default = "default_pirate_wx_offset" )]
     offset: TimeDelta,
+    // cron expression or "every N<unit> [at <offset>]".  Used instead of interval / interval_unit / offset if provided
+    #[serde( default = "default_string" )]
+    schedule: String,
+    #[serde( with = "integer_to_chrono_time_delta_ms", default = "default_jitter" )]
+    jitter: TimeDelta,
+    #[serde( default = "default_pirate_wx_hourly_forecast_hours" )]
+    hourly_forecast_hours: i64,
+    #[serde( default = "default_pirate_wx_minutely_forecast" )]
+    minutely_forecast: bool,
+    #[serde( default = "default_pirate_wx_alerts" )]
+    alerts: bool,
+    #[serde( default = "default_pirate_wx_alert_notify_severities" )]
+    alert_notify_severities: Vec<String>,
+    #[serde( default = "default_pirate_wx_alert_notify_keywords" )]
+    alert_notify_keywords: Vec<String>,
+    #[serde( default = "default_string" )]
+    alert_webhook_url: String,
 }
 impl PirateWxConf {
     fn new() -> Self {
@@ -525,7 +793,15 @@ impl PirateWxConf {
             api_key: String::new(),
             interval: 5,
             interval_unit: 'm',
-            offset: TimeDelta::milliseconds(0)
+            offset: TimeDelta::milliseconds(0),
+            schedule: String::new(),
+            jitter: TimeDelta::zero(),
+            hourly_forecast_hours: 48,
+            minutely_forecast: true,
+            alerts: true,
+            alert_notify_severities: default_pirate_wx_alert_notify_severities(),
+            alert_notify_keywords: default_pirate_wx_alert_notify_keywords(),
+            alert_webhook_url: String::new(),
         }
     }
 }
@@ -538,6 +814,21 @@ fn default_pirate_wx_interval_unit() -> char {
 fn default_pirate_wx_offset() -> TimeDelta{
     TimeDelta::milliseconds(0)
 }
+fn default_pirate_wx_hourly_forecast_hours() -> i64 {
+    48
+}
+fn default_pirate_wx_minutely_forecast() -> bool {
+    true
+}
+fn default_pirate_wx_alerts() -> bool {
+    true
+}
+fn default_pirate_wx_alert_notify_severities() -> Vec<String> {
+    vec!["Severe".to_string(), "Extreme".to_string()]
+}
+fn default_pirate_wx_alert_notify_keywords() -> Vec<String> {
+    vec!["hail".to_string(), "snow".to_string()]
+}
 
 #[derive(Debug, Deserialize, Clone )]
 struct Pvs6Conf {
@@ -548,7 +839,34 @@ struct Pvs6Conf {
     #[serde( default = "default_pvs6_interval_unit" )]
     get_device_interval_unit: char,
     #[serde( with = "integer_to_chrono_time_delta_ms", default = "default_pvs6_offset" )]
-    get_device_offset: TimeDelta
+    get_device_offset: TimeDelta,
+    // cron expression or "every N<unit> [at <offset>]".  Used instead of get_device_interval / unit / offset if provided
+    #[serde( default = "default_string" )]
+    get_device_schedule: String,
+    #[serde( with = "integer_to_chrono_time_delta_ms", default = "default_jitter" )]
+    get_device_jitter: TimeDelta,
+    // polling at night (sun down, outside of daylight_margin).  "full" polls as in daylight, "reduced" polls every night_interval,
+    // "meters_only" polls at full rate but doesn't store inverter data.
+    #[serde( default = "default_pvs6_night_mode" )]
+    night_mode: String,
+    #[serde( default = "default_pvs6_night_interval" )]
+    night_interval: u64,
+    #[serde( default = "default_pvs6_night_interval_unit" )]
+    night_interval_unit: char,
+    // minutes before sunrise and after sunset that are still polled as daylight
+    #[serde( with = "integer_to_chrono_time_delta_minutes", default = "default_pvs6_daylight_margin" )]
+    daylight_margin: TimeDelta,
+    // what to do with polls missed while the system was asleep or busy.  "skip", "burst" or "delay" (tokio MissedTickBehavior)
+    #[serde( default = "default_pvs6_missed_tick_behavior" )]
+    missed_tick_behavior: String,
+    // adjust get_device_offset from observed PVS6 data_time so data_time lands in the first second after the scheduled time
+    #[serde( default = "default_pvs6_auto_tune_offset" )]
+    auto_tune_offset: bool,
+    // highest plausible power of one inverter / meter.  Lifetime counters changing faster than this are recorded as counter events
+    #[serde( default = "default_pvs6_max_inverter_kw" )]
+    max_inverter_kw: f64,
+    #[serde( default = "default_pvs6_max_meter_kw" )]
+    max_meter_kw: f64,
 }
 impl Pvs6Conf {
     fn new() -> Self {
@@ -557,6 +875,16 @@ impl Pvs6Conf {
             get_device_interval: 5,
             get_device_interval_unit: 'm',
             get_device_offset: TimeDelta::milliseconds(0),
+            get_device_schedule: String::new(),
+            get_device_jitter: TimeDelta::zero(),
+            night_mode: default_pvs6_night_mode(),
+            night_interval: 30,
+            night_interval_unit: 'm',
+            daylight_margin: TimeDelta::minutes(30),
+            missed_tick_behavior: default_pvs6_missed_tick_behavior(),
+            auto_tune_offset: false,
+            max_inverter_kw: default_pvs6_max_inverter_kw(),
+            max_meter_kw: default_pvs6_max_meter_kw(),
         }
     }
 }
@@ -569,6 +897,67 @@ fn default_pvs6_interval_unit() -> char {
 fn default_pvs6_offset() -> TimeDelta{
     TimeDelta::milliseconds(-200)
 }
+fn default_jitter() -> TimeDelta {
+    TimeDelta::zero()
+}
+fn default_pvs6_night_mode() -> String {
+    "full".to_string()
+}
+fn default_pvs6_night_interval() -> u64 {
+    30
+}
+fn default_pvs6_night_interval_unit() -> char {
+    'm'
+}
+fn default_pvs6_daylight_margin() -> TimeDelta {
+    TimeDelta::minutes(30)
+}
+fn default_pvs6_missed_tick_behavior() -> String {
+    "skip".to_string()
+}
+fn default_pvs6_auto_tune_offset() -> bool {
+    false
+}
+fn default_pvs6_max_inverter_kw() -> f64 {
+    1.0
+}
+fn default_pvs6_max_meter_kw() -> f64 {
+    100.0
+}
+
+// used with each new PVS6 reading before it is stored: counter reset / jump detection (keeps the previous counter readings),
+// plausibility checks and personal weather station readings to store with it
+struct Pvs6Ingest {
+    counters: CounterCheck,
+    plausibility: Option<Plausibility>,
+    pws_readings: PwsReadings,
+}
+
+#[derive(Clone, Debug)]
+struct Pvs6PollTiming {
+    // time poll was scheduled for (without jitter)
+    scheduled_time: DateTime<Utc>,
+    request_time: DateTime<Utc>,
+    response_time: DateTime<Utc>,
+    // supervisor data_time reported by PVS6.  None if PVS6 didn't respond or response couldn't be deserialized
+    data_time: Option<DateTime<Utc>>,
+    // schedule offset the poll's tick was planned with.  None for cron schedules
+    offset: Option<TimeDelta>,
+}
+impl Pvs6PollTiming {
+    fn latency(&self) -> TimeDelta {
+        self.response_time - self.request_time
+    }
+    fn interval_start(&self) -> DateTime<Utc> {
+        // scheduled time without offset (the interval boundary polls are aligned to)
+        self.scheduled_time - self.offset.unwrap_or(TimeDelta::zero())
+    }
+    fn data_lag(&self) -> Option<TimeDelta> {
+        // how far after the interval boundary the PVS6 data_time is.  Negative if PVS6 data is from before the boundary.
+        // Measured from the boundary, not the scheduled time, so it changes when the offset does
+        self.data_time.map( |data_time| data_time - self.interval_start() )
+    }
+}
 
 #[derive(Debug, Deserialize, Clone )]
 struct MySqlConf {
@@ -627,9 +1016,18 @@ struct Wx {
 
     currently: CurrentWx,
     daily: DailyWx,
+
+    #[serde( default = "default_opt_hourly_wx" )]
+    hourly: Option<HourlyWx>,
+
+    #[serde( default = "default_opt_minutely_wx" )]
+    minutely: Option<MinutelyWx>,
+
+    #[serde( default = "default_wx_alerts" )]
+    alerts: Vec<WxAlert>,
 }
 
-#[derive( Clone, Deserialize, Debug, sqlx::FromRow )]
+#[derive( Clone, Default, Deserialize, Debug, sqlx::FromRow )]
 struct CurrentWx {
     #[ serde( with = "unix_epoch_to_chrono_utc_date_time" )]
     time: DateTime<Utc>,
@@ -718,11 +1116,17 @@ struct DailyWx {
     data: Vec<DailyWxData>
 }
 
-#[derive( Clone, Deserialize, Debug, sqlx::FromRow )]
+#[derive( Clone, Default, Deserialize, Debug, sqlx::FromRow )]
 struct DailyWxData {
     #[ serde( with = "unix_epoch_to_chrono_utc_date_time" )]
     time: DateTime<Utc>,
 
+    #[serde( with = "string_to_opt_string", default = "default_opt_string" )]
+    summary: Option<String>,
+
+    #[serde( with = "string_to_opt_string", default = "default_opt_string"  )]
+    icon: Option<String>,
+
     #[serde( alias = "dawnTime", with = "unix_epoch_to_opt_chrono_utc_date_time", default = "default_opt_utc_dt" )]
     dawn_time:Option<DateTime<Utc>>,
 
@@ -742,6 +1146,9 @@ struct DailyWxData {
     #[serde( alias = "precipAccumulation", with = "float_to_opt_f32", default = "default_opt_f32" )]
     precip_accumulation: Option<f32>,
 
+    #[serde( alias = "precipProbability", with = "float_to_opt_f32", default = "default_opt_f32"  )]
+    precip_probability: Option<f32>,
+
     #[serde( alias = "temperatureMin", with = "float_to_opt_f32", default = "default_opt_f32" )]
     temperature_min: Option<f32>,
 
@@ -755,8 +1162,150 @@ struct DailyWxData {
 
     #[serde( alias = "temperatureMaxTime", with = "unix_epoch_to_opt_chrono_utc_date_time", default = "default_opt_utc_dt" )]
     temperature_max_time: Option<DateTime<Utc>>,
+
+    #[serde( alias = "cloudCover", with = "float_to_opt_f32", default = "default_opt_f32"  )]
+    cloud_cover: Option<f32>,
+
+    #[serde( alias = "uvIndex", with = "float_to_opt_f32", default = "default_opt_f32"  )]
+    uv_index: Option<f32>,
+}
+
+#[derive( Clone, Deserialize, Debug, sqlx::FromRow )]
+struct HourlyWx {
+    data: Vec<HourlyWxData>
+}
+
+#[derive( Clone, Default, Deserialize, Debug, sqlx::FromRow )]
+struct HourlyWxData {
+    #[ serde( with = "unix_epoch_to_chrono_utc_date_time" )]
+    time: DateTime<Utc>,
+
+    #[serde( with = "string_to_opt_string", default = "default_opt_string" )]
+    summary: Option<String>,
+
+    #[serde( with = "string_to_opt_string", default = "default_opt_string"  )]
+    icon: Option<String>,
+
+    #[serde( alias = "precipIntensity", with = "float_to_opt_f32", default = "default_opt_f32"  )]
+    precip_intensity: Option<f32>,
+
+    #[serde( alias = "precipProbability", with = "float_to_opt_f32", default = "default_opt_f32"  )]
+    precip_probability: Option<f32>,
+
+    #[serde( alias = "precipAccumulation", with = "float_to_opt_f32", default = "default_opt_f32" )]
+    precip_accumulation: Option<f32>,
+
+    #[ serde( alias = "precipType", with = "string_to_opt_string", default = "default_opt_string"  ) ]
+    precip_type: Option<String>,
+
+    #[serde( with = "float_to_opt_f32", default = "default_opt_f32" )]
+    temperature: Option<f32>,
+
+    #[serde( alias = "apparentTemperature", with = "float_to_opt_f32", default = "default_opt_f32"  )]
+    apparent_temperature: Option<f32>,
+
+    #[serde( alias = "dewPoint", with = "float_to_opt_f32", default = "default_opt_f32"  )]
+    dew_point: Option<f32>,
+
+    #[serde( with = "float_to_opt_f32", default = "default_opt_f32" )]
+    humidity: Option<f32>,
+
+    #[serde( with = "float_to_opt_f32", default = "default_opt_f32" )]
+    pressure: Option<f32>,
+
+    #[serde( alias = "windSpeed", with = "float_to_opt_f32", default = "default_opt_f32"  )]
+    wind_speed: Option<f32>,
+
+    #[serde( alias = "windGust", with = "float_to_opt_f32", default = "default_opt_f32"  )]
+    wind_gust: Option<f32>,
+
+    #[serde( alias = "windBearing", with = "float_to_opt_f32", default = "default_opt_f32"  )]
+    wind_bearing: Option<f32>,
+
+    #[serde( alias = "cloudCover", with = "float_to_opt_f32", default = "default_opt_f32"  )]
+    cloud_cover: Option<f32>,
+
+    #[serde( alias = "uvIndex", with = "float_to_opt_f32", default = "default_opt_f32"  )]
+    uv_index: Option<f32>,
+
+    #[serde( with = "float_to_opt_f32", default = "default_opt_f32" )]
+    visibility: Option<f32>,
+
+    #[serde( with = "float_to_opt_f32", default = "default_opt_f32" )]
+    ozone: Option<f32>,
+
+    #[serde( with = "float_to_opt_f32", default = "default_opt_f32" )]
+    smoke: Option<f32>,
+}
+
+#[derive( Clone, Deserialize, Debug, sqlx::FromRow )]
+struct MinutelyWx {
+    data: Vec<MinutelyWxData>
 }
 
+#[derive( Clone, Deserialize, Debug, sqlx::FromRow )]
+struct MinutelyWxData {
+    #[ serde( with = "unix_epoch_to_chrono_utc_date_time" )]
+    time: DateTime<Utc>,
+
+    #[serde( alias = "precipIntensity", with = "float_to_opt_f32", default = "default_opt_f32"  )]
+    precip_intensity: Option<f32>,
+
+    #[serde( alias = "precipProbability", with = "float_to_opt_f32", default = "default_opt_f32"  )]
+    precip_probability: Option<f32>,
+
+    #[serde( alias = "precipIntensityError", with = "float_to_opt_f32", default = "default_opt_f32"  )]
+    precip_intensity_error: Option<f32>,
+
+    #[ serde( alias = "precipType", with = "string_to_opt_string", default = "default_opt_string"  ) ]
+    precip_type: Option<String>,
+}
+
+#[derive( Clone, Deserialize, Debug, sqlx::FromRow )]
+struct WxAlert {
+    title: String,
+
+    #[serde( default = "default_vec_string" )]
+    regions: Vec<String>,
+
+    #[serde( with = "string_to_opt_string", default = "default_opt_string" )]
+    severity: Option<String>,
+
+    #[ serde( with = "unix_epoch_to_chrono_utc_date_time" )]
+    time: DateTime<Utc>,
+
+    #[serde( with = "unix_epoch_to_opt_chrono_utc_date_time", default = "default_opt_utc_dt" )]
+    expires: Option<DateTime<Utc>>,
+
+    #[serde( with = "string_to_opt_string", default = "default_opt_string" )]
+    description: Option<String>,
+
+    #[serde( with = "string_to_opt_string", default = "default_opt_string" )]
+    uri: Option<String>,
+}
+
+impl WxAlert {
+    fn alert_id(&self) -> String {
+        // Alert uri is unique per alert issued.  If alert doesn't have one, build id from title, issue time and regions.
+        match &self.uri {
+            Some(uri) => uri.to_owned(),
+            None => format!("{}|{}|{}", self.title, self.time.timestamp(), self.regions.join(",")),
+        }
+    }
+}
+
+fn default_wx_alerts() -> Vec<WxAlert> {
+    Vec::new()
+}
+fn default_vec_string() -> Vec<String> {
+    Vec::new()
+}
+fn default_opt_hourly_wx() -> Option<HourlyWx> {
+    None
+}
+fn default_opt_minutely_wx() -> Option<MinutelyWx> {
+    None
+}
 fn default_opt_string() -> Option<String> {
     None
 }
@@ -847,6 +1396,19 @@ mod integer_to_chrono_time_delta_ms {
     }
 }
 
+mod integer_to_chrono_time_delta_minutes {
+    use chrono::TimeDelta;
+    use serde::{self, Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D>( deserializer: D, ) -> Result<TimeDelta, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let val = i64::deserialize(deserializer)?;
+        Ok( TimeDelta::minutes(val) )
+    }
+}
+
 mod pvs6_date_format {
     use chrono::{ DateTime, NaiveDateTime, Utc };
     use serde::{self, Deserialize, Deserializer};
@@ -915,86 +1477,474 @@ mod string_to_f64 {
 #[tokio::main]
 async fn main() {
     
+    let cli = Cli::parse();
     log4rs::init_file("log_config.yml", Default::default()).unwrap();   //Need Error Handling here.  What if log doesn't unwrap
     //get sqlx mysql pool and connection
-    let mut conf ;
-    
 
-    let settings = Config::builder()
-        // Add in `./Settings.toml`
-        .add_source(config::File::with_name("config"))
-        .build().unwrap();
-    let conf_res = settings.try_deserialize::<Conf>();
-    match conf_res {
-        Ok(c) => conf = c,
-        Err(de_eff) => {
-            error!("Fatal Error.  Could not deserialize Pirate WX configuration file {}", de_eff);
-            panic!("Fatal Error.  Could not deserialize Pirate WX configuration file {}", de_eff);
+    let settings = match read_conf_settings() {
+        Ok(settings) => settings,
+        Err(read_eff) => exit_conf_errors( &[ ConfigError::Unreadable { error: read_eff.to_string() } ] ),
+    };
+    // flattened settings, compared on config reload
+    let conf_keys = conf_settings_map(&settings);
+    warn_unknown_keys(&conf_keys);
+
+    // export command only needs the solar db
+    if let Some(Command::Export(export_args)) = cli.command {
+        let mut errors = Vec::new();
+        let mysql_conf = match settings.get::<MySqlConf>("mysql") {
+            Ok(mysql_conf) => verify_mysql_conf(mysql_conf, &mut errors),
+            Err(de_eff) => exit_conf_errors( &[ ConfigError::Unreadable { error: de_eff.to_string() } ] ),
+        };
+        if !errors.is_empty() {
+            exit_conf_errors(&errors);
         }
+        let Some(solar_pool) = get_sqlx_solar_pool(&mysql_conf).await else {
+            error!("Export failed.  Couldn't connect to Mysql solar database.");
+            std::process::exit(1);
+        };
+        if let Err(export_eff) = run_export(&export_args, &solar_pool).await {
+            error!("Export failed. {}", export_eff);
+            eprintln!("Export failed. {}", export_eff);
+            std::process::exit(1);
+        }
+        return
     }
 
-    // verify config file has the required parameters. These functions do not validate that parameter values are correct to work,
-    // it only verifies they exist.
-
-    // verifies pirate wx conf data
-    conf.pirate_wx = verify_pirate_wx_conf(conf.pirate_wx);
-    // verifies pvs6 conf data
-    verify_pvs6_conf(&conf.pvs6);
-    // verifies mysql conf data
-    conf.mysql = verify_mysql_conf(conf.mysql); 
+    let conf = match load_conf(settings) {
+        Ok(conf) => conf,
+        Err(errors) => exit_conf_errors(&errors),
+    };
 
     let solar_pool = get_sqlx_solar_pool(&conf.mysql).await;
-    
-    
-    let pirate_wx_handle = spawn( pirate_wx_to_mysql(solar_pool.clone(), conf.pirate_wx.clone() ) );
-    let pvs6_handle = spawn( pvs6_to_mysql( solar_pool.clone(), conf.pvs6.clone() ) );
-
-    let _ = pvs6_handle.await;
-    let _ = pirate_wx_handle.await;
-
-}
+    // current config and solar pool.  Tasks read them each time they are built, so a config reload reaches a task when the
+    // supervisor restarts it.
+    let (pool_tx, pool_rx) = watch::channel(solar_pool);
+    let (conf_tx, conf_rx) = watch::channel(conf);
+
+    // triggered by SIGTERM / SIGINT.  Stops scheduler jobs, task restarts and the api.
+    let shutdown = Shutdown::default();
+    // all collectors share one scheduler.  Schedules verified by verify_*_conf when config is loaded
+    let scheduler = Scheduler::new();
+    // tasks are restarted by the supervisor if they panic or exit, or their config changes.  Each restart builds the task again
+    // from the current config with a new scheduler job.
+    let supervisor = TaskSupervisor::new( shutdown.clone() );
+    let mut handles = Vec::new();
+
+    // latest collector data, served by the api
+    let latest = Latest::default();
+    // personal weather station readings waiting for the next PVS6 poll
+    let pws_readings = PwsReadings::default();
+
+    // collectors idle while their section isn't configured, so a config reload can start them.
+    // weather comes from whichever provider is configured (only one can be)
+    handles.push( supervisor.spawn( "weather", {
+        let (scheduler, conf_rx, pool_rx, latest) = (scheduler.clone(), conf_rx.clone(), pool_rx.clone(), latest.clone());
+        move |stop| {
+            let conf = conf_rx.borrow().clone();
+            let solar_pool = pool_rx.borrow().clone();
+            let weather_job = |schedule: Schedule, jitter: TimeDelta| scheduler.add_job( "weather", schedule, jitter, stop.clone() );
+            let task: Option<Pin<Box<dyn Future<Output = ()> + Send>>> = if let Some(wx_conf) = conf.pirate_wx {
+                let job = weather_job( conf_schedule( &wx_conf.schedule, wx_conf.interval, wx_conf.interval_unit, wx_conf.offset ), wx_conf.jitter );
+                Some( Box::pin( weather_to_mysql( solar_pool, PirateWeather::new(wx_conf), job, latest.clone() ) ) )
+            } else if let Some(wx_conf) = conf.open_meteo {
+                let job = weather_job( conf_schedule( &wx_conf.schedule, wx_conf.interval, wx_conf.interval_unit, wx_conf.offset ), wx_conf.jitter );
+                Some( Box::pin( weather_to_mysql( solar_pool, OpenMeteo::new(wx_conf), job, latest.clone() ) ) )
+            } else if let Some(wx_conf) = conf.wx_station {
+                let job = weather_job( conf_schedule( &wx_conf.schedule, wx_conf.interval, wx_conf.interval_unit, wx_conf.offset ), wx_conf.jitter );
+                Some( Box::pin( weather_to_mysql( solar_pool, WxStation::new(wx_conf), job, latest.clone() ) ) )
+            } else {
+                scheduler.remove_job("weather");
+                None
+            };
+            run_or_idle(task, stop)
+        }
+    }));
+
+    handles.push( supervisor.spawn( "pvs6", {
+        let (scheduler, conf_rx, pool_rx, latest, pws_readings) = 
+            (scheduler.clone(), conf_rx.clone(), pool_rx.clone(), latest.clone(), pws_readings.clone());
+        move |stop| {
+            let conf = conf_rx.borrow().clone();
+            let site_location = conf.site_location();
+            let task = conf.pvs6.map( |pvs6_conf| {
+                // plausibility verified at startup by verify_plausibility_conf
+                let ingest = Pvs6Ingest {
+                    counters: CounterCheck::new(pvs6_conf.max_inverter_kw, pvs6_conf.max_meter_kw),
+                    plausibility: conf.plausibility.as_ref().and_then( |plausibility_conf| Plausibility::from_conf(plausibility_conf).ok() ),
+                    pws_readings: pws_readings.clone(),
+                };
+                let pvs6_schedule = conf_schedule( &pvs6_conf.get_device_schedule, pvs6_conf.get_device_interval,
+                    pvs6_conf.get_device_interval_unit, pvs6_conf.get_device_offset );
+                check_watchdog_timeout( &pvs6_schedule, pvs6_conf.get_device_jitter );
+                let pvs6_job = scheduler.add_job( "pvs6", pvs6_schedule, pvs6_conf.get_device_jitter, stop.clone() )
+                    .with_missed_tick_behavior( parse_missed_tick_behavior(&pvs6_conf.missed_tick_behavior).unwrap_or(MissedTickBehavior::Skip) );
+                pvs6_to_mysql( pool_rx.borrow().clone(), pvs6_conf, conf.site, site_location, pvs6_job, latest.clone(), ingest )
+            });
+            if task.is_none() {
+                scheduler.remove_job("pvs6");
+            }
+            // pvs6 poll loop pings the systemd watchdog.  Without it, pings come from an idle loop.
+            async move {
+                match task {
+                    Some(task) => task.await,
+                    None => systemd::watchdog_while_idle(stop).await,
+                }
+            }
+        }
+    }));
+
+    // personal weather station readings are buffered here and stored by the pvs6 poll loop
+    handles.push( supervisor.spawn( "pws", {
+        let (scheduler, conf_rx, pws_readings) = (scheduler.clone(), conf_rx.clone(), pws_readings.clone());
+        move |stop| {
+            let pws_conf = conf_rx.borrow().pws.clone();
+            let task: Option<Pin<Box<dyn Future<Output = ()> + Send>>> = match pws_conf {
+                Some(pws_conf) if pws_conf.mode == "poll" => {
+                    let pws_job = scheduler.add_job( "pws", conf_schedule( &pws_conf.schedule, pws_conf.interval, pws_conf.interval_unit,
+                        pws_conf.offset ), pws_conf.jitter, stop.clone() );
+                    Some( Box::pin( poll_pws( pws_conf, pws_readings.clone(), pws_job ) ) )
+                },
+                Some(pws_conf) => {
+                    scheduler.remove_job("pws");
+                    Some( Box::pin( receive_pws_push( pws_conf, pws_readings.clone(), stop.clone() ) ) )
+                },
+                None => {
+                    scheduler.remove_job("pws");
+                    None
+                },
+            };
+            run_or_idle(task, stop)
+        }
+    }));
+
+    // production forecast runs from stored data only.  Site location is the site lat / long (or weather provider location).
+    handles.push( supervisor.spawn( "forecast", {
+        let (scheduler, conf_rx, pool_rx) = (scheduler.clone(), conf_rx.clone(), pool_rx.clone());
+        move |stop| {
+            let conf = conf_rx.borrow().clone();
+            let site_location = conf.site_location();
+            let task = match ( conf.forecast, site_location ) {
+                ( Some(forecast_conf), Some((lat, long)) ) => {
+                    let forecast_job = scheduler.add_job( "forecast", conf_schedule( &forecast_conf.schedule, forecast_conf.interval,
+                        forecast_conf.interval_unit, forecast_conf.offset ), forecast_conf.jitter, stop.clone() );
+                    Some( forecast_to_mysql( pool_rx.borrow().clone(), forecast_conf, lat, long, forecast_job ) )
+                },
+                ( Some(_), None ) => {
+                    error!("Production forecast needs site lat / long, or a weather provider lat / long.  Production forecast not started.");
+                    None
+                },
+                ( None, _ ) => None,
+            };
+            if task.is_none() {
+                scheduler.remove_job("forecast");
+            }
+            run_or_idle(task, stop)
+        }
+    }));
+    // tariff prices energy already in the solar db
+    handles.push( supervisor.spawn( "tariff", {
+        let (scheduler, conf_rx, pool_rx) = (scheduler.clone(), conf_rx.clone(), pool_rx.clone());
+        move |stop| {
+            let tariff_conf = conf_rx.borrow().tariff.clone();
+            let task = match tariff_conf {
+                Some(tariff_conf) => {
+                    let tariff_job = scheduler.add_job( "tariff", conf_schedule( &tariff_conf.schedule, tariff_conf.interval,
+                        tariff_conf.interval_unit, tariff_conf.offset ), tariff_conf.jitter, stop.clone() );
+                    Some( tariff_to_mysql( pool_rx.borrow().clone(), tariff_conf, tariff_job ) )
+                },
+                None => {
+                    scheduler.remove_job("tariff");
+                    None
+                },
+            };
+            run_or_idle(task, stop)
+        }
+    }));
+    // reconciliation checks energy already in the solar db
+    handles.push( supervisor.spawn( "reconciliation", {
+        let (scheduler, conf_rx, pool_rx) = (scheduler.clone(), conf_rx.clone(), pool_rx.clone());
+        move |stop| {
+            let reconciliation_conf = conf_rx.borrow().reconciliation.clone();
+            let task = match reconciliation_conf {
+                Some(reconciliation_conf) => {
+                    let reconciliation_job = scheduler.add_job( "reconciliation", conf_schedule( &reconciliation_conf.schedule,
+                        reconciliation_conf.interval, reconciliation_conf.interval_unit, reconciliation_conf.offset ),
+                        reconciliation_conf.jitter, stop.clone() );
+                    Some( reconciliation_to_mysql( pool_rx.borrow().clone(), reconciliation_conf, reconciliation_job ) )
+                },
+                None => {
+                    scheduler.remove_job("reconciliation");
+                    None
+                },
+            };
+            run_or_idle(task, stop)
+        }
+    }));
+    // retention runs against the solar db only
+    handles.push( supervisor.spawn( "retention", {
+        let (scheduler, conf_rx, pool_rx) = (scheduler.clone(), conf_rx.clone(), pool_rx.clone());
+        move |stop| {
+            let retention_conf = conf_rx.borrow().retention.clone();
+            let task = match retention_conf {
+                Some(retention_conf) => {
+                    let retention_job = scheduler.add_job( "retention", conf_schedule( &retention_conf.schedule, 0, 's', TimeDelta::zero() ),
+                        retention_conf.jitter, stop.clone() );
+                    Some( retention_to_mysql( pool_rx.borrow().clone(), retention_conf, retention_job ) )
+                },
+                None => {
+                    scheduler.remove_job("retention");
+                    None
+                },
+            };
+            run_or_idle(task, stop)
+        }
+    }));
+    handles.push( supervisor.spawn( "api", {
+        let (scheduler, conf_rx, pool_rx, latest, supervisor) = 
+            (scheduler.clone(), conf_rx.clone(), pool_rx.clone(), latest.clone(), supervisor.clone());
+        move |stop| {
+            let api_conf = conf_rx.borrow().api.clone();
+            let task = api_conf.map( |api_conf| serve_api( api_conf, pool_rx.borrow().clone(), latest.clone(), scheduler.clone(), 
+                supervisor.clone(), stop.clone() ) );
+            run_or_idle(task, stop)
+        }
+    }));
 
-async fn pirate_wx_to_mysql(solar_pool: Option<sqlx::Pool<sqlx::MySql>>, pirate_wx_conf: PirateWxConf) {
-    let mut get_wx_interval = set_interval(&pirate_wx_conf.interval, &pirate_wx_conf.interval_unit, &pirate_wx_conf.offset);
-    
-    loop {
-        get_wx_interval.tick().await;
-        let wx_opt = get_weather( &pirate_wx_conf ).await;
+    // config file changes (or SIGHUP) are verified and sent to the tasks they affect
+    tokio::spawn( reload_conf( conf_tx, pool_tx, conf_keys, supervisor.clone(), shutdown.clone() ) );
 
-        if let Some(wx) = wx_opt {
-            insert_pirate_wx_to_mysql(wx, &solar_pool ).await;
+    for (name, status) in scheduler.job_statuses() {
+        info!("Job {} scheduled {}. Next run: {:?}, last run: {:?}, missed ticks: {}", 
+            name, status.schedule, status.next_run, status.last_run, status.missed_ticks);
+    }
+    // tell systemd (Type=notify) startup is done.  Watchdog pings come from the pvs6 poll loop.
+    systemd::notify_ready( &format!("Collecting. {} jobs scheduled.", scheduler.job_statuses().len()) );
+
+    let signal = shutdown::wait_for_signal().await;
+    info!("{} received.  Shutting down.", signal);
+    systemd::notify_stopping();
+    shutdown.trigger();
+
+    // jobs stop ticking now.  Wait for polls and db writes already in progress to finish.
+    let stopped = tokio::time::timeout( SHUTDOWN_DEADLINE, async move {
+        let mut clean = true;
+        for handle in handles {
+            clean &= matches!( handle.await, Ok(true) );
+        }
+        clean
+    }).await;
+    let exit_code = match stopped {
+        Ok(true) => {
+            info!("All tasks stopped.");
+            0
+        },
+        Ok(false) => {
+            error!("Tasks failed during shutdown.");
+            1
+        },
+        Err(_) => {
+            error!("Tasks did not stop within {}s.  Exiting with work in progress.", SHUTDOWN_DEADLINE.as_secs());
+            1
+        },
+    };
+    let solar_pool = pool_rx.borrow().clone();
+    if let Some(sql_pool) = solar_pool {
+        // close waits for connections in use to be returned.  Tasks past the deadline may still hold some.
+        match tokio::time::timeout( POOL_CLOSE_TIMEOUT, sql_pool.close() ).await {
+            Ok(()) => info!("Pool closed"),
+            Err(_) => warn!("Pool did not close within {}s.", POOL_CLOSE_TIMEOUT.as_secs()),
         }
     }
+    std::process::exit(exit_code);
+
 }
 
-async fn pvs6_to_mysql(solar_pool: Option<sqlx::Pool<sqlx::MySql>>, pvs6_conf: Pvs6Conf) {
+async fn pvs6_to_mysql(solar_pool: Option<sqlx::Pool<sqlx::MySql>>, pvs6_conf: Pvs6Conf, site_conf: Option<SiteConf>,
+    site_location: Option<(f64, f64)>, mut get_pvs6_device_job: Job, latest: Latest, mut ingest: Pvs6Ingest) {
      
-    // Set the offset duration of the interval.  For fine tuning timing request.  We want the pvs6 response time for the request (ie the data_time) to be as close to the 
+    // The offset of the schedule is for fine tuning timing request.  We want the pvs6 response time for the request (ie the data_time) to be as close to the 
     // interval time we planned, but not before it.  There is some small delay in the PVS6 receiving the request and then processing it.  This negative offset is meant to 
     // fine tune that as needed.     
-    // set the start time and interval used to get pvs6 device data.
-    let mut get_pvs6_device_interval = set_interval(&pvs6_conf.get_device_interval, &pvs6_conf.get_device_interval_unit, &pvs6_conf.get_device_offset);
+    // night interval used in "reduced" night_mode.  Units verified at startup.
+    let night_interval = TimeDelta::seconds( interval_seconds(pvs6_conf.night_interval, pvs6_conf.night_interval_unit).unwrap_or(1800) as i64 );
+    // scheduled time of last poll at night in "reduced" night_mode
+    let mut last_night_poll: Option<DateTime<Utc>> = None;
+    // local date sun times were last stored for
+    let mut sun_times_date: Option<NaiveDate> = None;
+
+    if site_location.is_none() && pvs6_conf.night_mode != "full" {
+        warn!("PVS6 night_mode {} needs site lat / long to find sunrise and sunset.  Polling at full rate day and night.", pvs6_conf.night_mode);
+    }
      
-    loop {
-        // Wait until the next tick (start time and interval)
-        get_pvs6_device_interval.tick().await; 
+    // Wait until the next tick of schedule.  Ends on shutdown, after the current poll is stored.
+    while let Some(scheduled) = get_pvs6_device_job.tick().await {
+        // systemd watchdog.  Pings stop if this loop panics or hangs, so systemd restarts the service.
+        systemd::notify_watchdog();
         // get pvs6 data and upload to mysql solar database
         //println!( "Run at: {}", Utc::now().to_string() ); //for loop timing testing
         debug!( "Run at: {}", Utc::now().to_string() ); //for loop timing testing
+        let now = Utc::now();
+
+        // find sun times locally once a day and decide if it is daylight.  Without site location it is always daylight
+        let mut daylight = true;
+        if let Some((lat, long)) = site_location {
+            let today = solar_position::solar_date(long, now);
+            if sun_times_date != Some(today) {
+                insert_sun_times_to_mysql(lat, long, today, &solar_pool).await;
+                sun_times_date = Some(today);
+            }
+            daylight = solar_position::is_daylight(lat, long, now, pvs6_conf.daylight_margin);
+        }
+
+        let mut skip_inverters = false;
+        if daylight {
+            last_night_poll = None;
+        } else {
+            match pvs6_conf.night_mode.as_str() {
+                "reduced" => {
+                    // only poll once night_interval has passed since last night poll
+                    if let Some(last) = last_night_poll
+                        && scheduled - last < night_interval {
+                        debug!("Night.  PVS6 poll skipped (reduced night polling).");
+                        continue;
+                    }
+                    last_night_poll = Some(scheduled);
+                },
+                "meters_only" => skip_inverters = true,
+                _ => {},
+            }
+        }
         
-        
+        let request_time = Utc::now();
         let pvs6_opt = get_pvs6_device_data(&pvs6_conf).await;
+        let mut poll_timing = Pvs6PollTiming {
+            scheduled_time: scheduled,
+            request_time,
+            response_time: Utc::now(),
+            data_time: None,
+            offset: get_pvs6_device_job.tick_offset(),
+        };
 
-        let mut latest_data = get_latest_pvs6_data_from_sql(&solar_pool).await;
+        let latest_data = get_latest_pvs6_data_from_sql(&solar_pool).await;
 
         if let Some( pvs6_data ) = pvs6_opt {
-            if let Some (deser_pvs6) = deserialize_pvs6_devices(pvs6_data) {
+            if let Some (mut deser_pvs6) = deserialize_pvs6_devices(pvs6_data) {
+                poll_timing.data_time = deser_pvs6.supervisor.data_time;
+                // api gets the values stored in the solar db, so quarantined values are removed from its copy too.  They are
+                // counted and stored once, from the new readings below
+                match &ingest.plausibility {
+                    Some(plausibility) if plausibility.quarantine() => {
+                        let mut api_pvs6 = deser_pvs6.clone();
+                        plausibility.check(&mut api_pvs6);
+                        latest.set_pvs6(&api_pvs6);
+                    },
+                    _ => latest.set_pvs6(&deser_pvs6),
+                }
                 //println!("{:#?}", deser_pvs6);
-                let cleaned_pvs6_data = update_pvs6_old_responses(deser_pvs6, &latest_data);
+                if skip_inverters {
+                    debug!("Night.  {} inverters not stored (meters_only night polling).", deser_pvs6.inverters.len());
+                    deser_pvs6.inverters.clear();
+                }
+                let mut cleaned_pvs6_data = update_pvs6_old_responses(deser_pvs6, &latest_data);
+                // out of range values are quarantined (or only logged) before anything uses them
+                if let Some(plausibility) = &ingest.plausibility {
+                    let out_of_range = plausibility.check(&mut cleaned_pvs6_data);
+                    latest.add_out_of_range( out_of_range_counts(&out_of_range) );
+                    insert_quarantined_readings_to_mysql( &out_of_range, plausibility, &solar_pool ).await;
+                }
                 //println!("{:#?}", cleaned_pvs6_data);
+                // personal weather station readings since the last poll are stored with a new production meter reading
+                let pws_data = match ( cleaned_pvs6_data.prod_meter.data_time, cleaned_pvs6_data.prod_meter.p_3phsum_kw ) {
+                    ( Some(data_time), Some(_) ) => ingest.pws_readings.align(data_time).map( |pws_data| (data_time, pws_data) ),
+                    _ => None,
+                };
+                if let Some( (data_time, pws_data) ) = &pws_data {
+                    insert_pws_data_to_mysql( *data_time, pws_data, &solar_pool ).await;
+                }
+                // calculate expected output and performance ratio for production meter reading, if site is configured
+                if let Some(site) = &site_conf {
+                    insert_production_performance_to_mysql( &cleaned_pvs6_data.prod_meter, site, pws_data.as_ref().map( |(_, pws_data)| pws_data ),
+                        &solar_pool ).await;
+                }
+                let counter_events = ingest.counters.check( &cleaned_pvs6_data, &solar_pool ).await;
+                insert_counter_events_to_mysql( &counter_events, &solar_pool ).await;
                 insert_pvs6_data_to_mysql( cleaned_pvs6_data, &solar_pool ).await;
             } 
         }
+
+        insert_pvs6_poll_timing_to_mysql( &poll_timing, &solar_pool ).await;
+        if pvs6_conf.auto_tune_offset {
+            auto_tune_pvs6_offset( &poll_timing, &mut get_pvs6_device_job );
+        }
+    }
+}
+
+async fn insert_pvs6_poll_timing_to_mysql(poll_timing: &Pvs6PollTiming, sql_pool_opt: &Option<sqlx::Pool<sqlx::MySql>>) {
+    // uploads timing of a PVS6 poll to pvs6_poll_timing table.  Used to check get_device_offset is tuned correctly.
+    let data_lag = poll_timing.data_lag();
+    debug!("PVS6 poll scheduled {} latency {} ms, data_time lag {:?} ms", poll_timing.scheduled_time, 
+        poll_timing.latency().num_milliseconds(), data_lag.map( |lag| lag.num_milliseconds() ));
+
+    if let Some(sql_pool) = sql_pool_opt {
+        let timing_result = sqlx::query(INSERT_PVS6_POLL_TIMING_QUERY)
+            .bind(poll_timing.scheduled_time)
+            .bind(poll_timing.request_time)
+            .bind(poll_timing.response_time)
+            .bind(poll_timing.data_time)
+            .bind(poll_timing.latency().num_milliseconds())
+            .bind(data_lag.map( |lag| lag.num_milliseconds() ))
+            .bind(poll_timing.offset.map( |offset| offset.num_milliseconds() ))
+            .bind(poll_timing.data_time.is_some())
+            .execute(sql_pool).await;
+        if let Err(timing_eff) = timing_result {
+            error!("PVS6 poll timing for {} failed to upload to Mysql solar db pvs6_poll_timing table. Error: {}", 
+                poll_timing.scheduled_time, timing_eff);
+        }
+    }
+}
+
+fn auto_tune_pvs6_offset(poll_timing: &Pvs6PollTiming, job: &mut Job) {
+    // PVS6 data_time has 1 second resolution, so the target is data_time in the first second after the interval boundary.
+    // Offset is stepped earlier when data_time is late and later when data_time is before the boundary.  Small steps
+    // keep one slow response from moving the offset far.  Polls without data_time (PVS6 didn't respond) are ignored.
+    const OFFSET_STEP: TimeDelta = TimeDelta::milliseconds(100);
+    const MAX_OFFSET: TimeDelta = TimeDelta::seconds(10);
+
+    let (Some(data_lag), Some(offset)) = ( poll_timing.data_lag(), job.offset() ) else {
+        return
+    };
+    let new_offset = if data_lag < TimeDelta::zero() {
+        offset + OFFSET_STEP
+    } else if data_lag >= TimeDelta::seconds(1) {
+        offset - OFFSET_STEP
+    } else {
+        return
+    };
+    let new_offset = new_offset.clamp(-MAX_OFFSET, MAX_OFFSET);
+    if new_offset != offset && job.set_offset(new_offset) {
+        info!("PVS6 data_time lag {} ms.  get_device_offset tuned from {} ms to {} ms.", data_lag.num_milliseconds(), 
+            offset.num_milliseconds(), new_offset.num_milliseconds());
+    }
+}
+
+async fn insert_sun_times_to_mysql(lat: f64, long: f64, date: NaiveDate, sql_pool_opt: &Option<sqlx::Pool<sqlx::MySql>>) {
+    // calculates sunrise, solar noon and sunset for date at lat / long and uploads to sun_times table
+    let sun_times = solar_position::sun_times(lat, long, date);
+    info!("Sun times for {}: sunrise {:?}, solar noon {}, sunset {:?}", date, sun_times.sunrise, sun_times.solar_noon, sun_times.sunset);
+
+    if let Some(sql_pool) = sql_pool_opt {
+        let sun_times_result = sqlx::query(REPLACE_SUN_TIMES_QUERY)
+            .bind(lat)
+            .bind(long)
+            .bind(date)
+            .bind(sun_times.sunrise)
+            .bind(sun_times.solar_noon)
+            .bind(sun_times.sunset)
+            .execute(sql_pool).await;
+        if let Err(sun_times_eff) = sun_times_result {
+            error!("Sun times for {} failed to upload to Mysql solar db sun_times table. Error: {}", date, sun_times_eff);
+        }
     }
 }
 
@@ -1520,100 +2470,207 @@ async fn get_latest_pvs6_data_from_sql( solar_sql_upload_pool: &Option<sqlx::Poo
     latest_sql_pvs6_data
 }
 
-fn set_interval(repeat_interval: &u64, units: &char, offset: &Duration) -> Interval {
-    // repeat_interval: time in seconds that interval should repeat
-    // units: unit of time.  Only d, h, m, s are accepted.  All others will panic
-    // Set start time and interval of pvs6 data pulls.  
+fn interval_seconds(repeat_interval: u64, units: char) -> Option<u64> {
+    // converts interval in units 'd' day(s), 'h' hour(s), 'm' minute(s)), or 's' second(s) to seconds.  None for any other unit
+    match units {
+        'd' => Some( repeat_interval * 60 * 60 * 24 ),
+        'h' => Some( repeat_interval * 60 * 60 ),
+        'm' => Some( repeat_interval * 60 ),
+        's' => Some( repeat_interval ),
+        _ => None,
+    }
+}
 
-    // convert repeat interval to seconds.  Panic if units is not 'd' day(s), 'h' hour(s), 'm' minute(s)), or 's' second(s) 
-    let repeat_interval_s =   match units {
-        'd' => repeat_interval * 60 * 60 * 24,
-        'h' => repeat_interval * 60 * 60,
-        'm' => repeat_interval * 60,
-        's' => *repeat_interval,
-        other => panic!("Invalid time unit: {}. Use d, h, m, or s", other),
+fn check_watchdog_timeout(pvs6_schedule: &Schedule, pvs6_jitter: TimeDelta) {
+    // warns if systemd WatchdogSec is shorter than the time between pvs6 polls (which would restart the service every poll)
+    let Some(watchdog) = systemd::watchdog_timeout() else {
+        return
     };
-    debug!("Repeat Interval in seconds: {}", repeat_interval_s);
-    // Get current time 
-    let now: DateTime<Utc> = Utc::now();
-
-    let mut target_time: NaiveDateTime = now.naive_utc();
-    debug!("Original Target Time: {}", target_time.to_string() );
-    let mut next_start: Duration = chrono::Duration::seconds( 60 );
-    debug!("Original next_start: {}", next_start); 
-
-    // Round target_time (planned Start time) to nearest interval based on interval length.  Intent is to have intervals that will
-    // match clock times as much as possible.  Only works if exact intervals used here.  Future changes may round repeat_interval to match options.
-    if repeat_interval_s <= 60 {  // round to nearest minute
-        target_time = target_time.duration_round( Duration::minutes( 1 ) ).unwrap();
-        debug!("target_time rounded to nearest minute. Target Time: {}", target_time);
-    }
-    else if repeat_interval_s <= 60 * 5 {  // round to nearest 5 minutes
-        target_time = target_time.duration_round( Duration::minutes( 5 ) ).unwrap();
-        next_start = chrono::Duration::seconds ( 60 * 5 );
-        debug!("target_time rounded to nearest 5 minutes. Target Time: {}", target_time);
-    }
-	else if repeat_interval_s <= 60 * 10 {  // round to nearest 10 minutes
-        target_time = target_time.duration_round( Duration::minutes( 10 ) ).unwrap();
-        next_start = chrono::Duration::seconds ( 60 * 10 );
-        debug!("target_time rounded to nearest 10 minutes. Target Time: {}", target_time);
-    }
-	else if repeat_interval_s <= 60 * 15 {  // round to nearest 15 minutes
-        target_time = target_time.duration_round( Duration::minutes( 15 ) ).unwrap();
-        next_start = chrono::Duration::minutes ( 15 );
-        debug!("target_time rounded to nearest 15 minutes. Target Time: {}", target_time);
-    }
-	else if repeat_interval_s <= 60 * 30 {  // round to nearest 30 minutes
-        target_time = target_time.duration_round( Duration::minutes( 30 ) ).unwrap();
-        next_start = chrono::Duration::minutes ( 30 );
-        debug!("target_time rounded to nearest 30 minutes. Target Time: {}", target_time);
-    }
-	else if repeat_interval_s <= 60 * 60 * 1 {  // round to nearest hour
-        target_time = target_time.duration_round( Duration::hours( 1 ) ).unwrap();
-        next_start = chrono::Duration::hours ( 1 );
-        debug!("target_time rounded to nearest hour. Target Time: {}", target_time);
-    }
-	else if repeat_interval_s <= 60 * 60 * 2 {  // round to nearest 2 hours
-        target_time = target_time.duration_round( Duration::hours( 2 ) ).unwrap();
-        next_start = chrono::Duration::hours ( 2 );
-        debug!("target_time rounded to nearest 2 hours. Target Time: {}", target_time);
-    }
-	else if repeat_interval_s <= 60 * 60 * 3 {  // round to nearest 3 hours
-        target_time = target_time.duration_round( Duration::hours( 3 ) ).unwrap();
-        next_start = chrono::Duration::hours ( 3 );
-        debug!("target_time rounded to nearest 3 hours. Target Time: {}", target_time);
-    }
-	else if repeat_interval_s <= 60 * 60 * 4 {  // round to nearest 4 hours
-        target_time = target_time.duration_round( Duration::hours( 4 ) ).unwrap();
-        next_start = chrono::Duration::hours ( 4 );
-        debug!("target_time rounded to nearest 4 hours. Target Time: {}", target_time);
-    }
-	else if repeat_interval_s <= 60 * 60 * 6 {  // round to nearest 6 hours
-        target_time = target_time.duration_round( Duration::hours( 6 ) ).unwrap();
-        next_start = chrono::Duration::hours ( 6 );
-        debug!("target_time rounded to nearest 6 hours. Target Time: {}", target_time);
-    }
-	else if repeat_interval_s <= 60 * 60 * 12 {  // round to nearest 12 hours
-        target_time = target_time.duration_round( Duration::hours( 12 ) ).unwrap();
-        next_start = chrono::Duration::hours ( 12 );
-        debug!("target_time rounded to nearest 12 hours. Target Time: {}", target_time);
-    }
-	else {  // round to nearest day
-        target_time = target_time.duration_round( Duration::days( 1 ) ).unwrap();
-        next_start = chrono::Duration::days ( 1 );
-        debug!("target_time rounded to nearest day. Target Time: {}", target_time);
+    let first = pvs6_schedule.next_after( Utc::now() );
+    let second = first.and_then( |first| pvs6_schedule.next_after(first) );
+    if let ( Some(first), Some(second) ) = (first, second) {
+        let poll_gap = second - first + pvs6_jitter;
+        if watchdog <= poll_gap {
+            warn!("systemd WatchdogSec ({}s) is not longer than time between PVS6 polls ({}s).  Service will be restarted between polls.",
+                watchdog.num_seconds(), poll_gap.num_seconds());
+        } else {
+            info!("systemd watchdog enabled.  Timeout {}s.", watchdog.num_seconds());
+        }
     }
-    
-    target_time += *offset;  //adjust target time by user supplied offset
-    
-    let mut start = target_time.signed_duration_since(now.naive_utc());
-    
-    
-    if start < chrono::Duration::milliseconds(500) {
-        start = start + next_start;
+}
+
+fn read_conf_settings() -> std::result::Result<Config, config::ConfigError> {
+    // reads configuration file (config.yml) with environment variable and secret file overrides
+    settings::read_settings(CONFIG_NAME)
+}
+
+fn conf_settings_map(settings: &Config) -> BTreeMap<String, String> {
+    // flattened "section.key" = value settings.  Used to find what changed on config reload.
+    match settings.clone().try_deserialize::<serde_json::Value>() {
+        Ok(settings) => reload::flatten_settings(&settings),
+        Err(_) => BTreeMap::new(),
     }
+}
 
-    interval_at(tokio::time::Instant::now() + start.to_std().unwrap(), TokioDuration::from_secs( repeat_interval_s ) )
+fn verify_conf(mut conf: Conf) -> std::result::Result<Conf, Vec<ConfigError>> {
+    // verifies every section of the config and returns all problems found, not just the first one
+
+    let mut errors = Vec::new();
+    // verifies weather provider conf data, if configured.  Only one provider can be.
+    let providers = conf.weather_providers();
+    if providers.len() > 1 {
+        errors.push( ConfigError::invalid( "weather", providers.join(", "), "Only one weather provider section can be configured." ) );
+    }
+    if let Some(pirate_wx_conf) = conf.pirate_wx {
+        conf.pirate_wx = Some( verify_pirate_wx_conf(pirate_wx_conf, &mut errors) );
+    }
+    if let Some(open_meteo_conf) = &conf.open_meteo {
+        verify_open_meteo_conf(open_meteo_conf, &mut errors);
+    }
+    if let Some(wx_station_conf) = &conf.wx_station {
+        verify_wx_station_conf(wx_station_conf, &mut errors);
+    }
+    // verifies pvs6 conf data, if pvs6 is configured
+    if let Some(pvs6_conf) = &conf.pvs6 {
+        verify_pvs6_conf(pvs6_conf, &mut errors);
+    }
+    if conf.pvs6.is_none() && providers.is_empty() {
+        warn!("No collector (pvs6, pirate_wx, open_meteo or wx_station) is configured.  No new data will be collected.");
+    }
+    // verifies plausibility conf data, if configured.  Readings are only checked with pvs6
+    if let Some(plausibility_conf) = &conf.plausibility {
+        verify_plausibility_conf(plausibility_conf, &mut errors);
+        if conf.pvs6.is_none() {
+            warn!("plausibility is configured without pvs6.  Only PVS6 readings are checked.");
+        }
+    }
+    // verifies personal weather station conf data, if configured.  Readings are only stored with PVS6 readings
+    if let Some(pws_conf) = &conf.pws {
+        verify_pws_conf(pws_conf, &mut errors);
+        if conf.pvs6.is_none() {
+            warn!("pws is configured without pvs6.  Personal weather station readings are only stored with PVS6 readings.");
+        }
+    }
+    // verifies mysql conf data
+    conf.mysql = verify_mysql_conf(conf.mysql, &mut errors);
+    // verifies site conf data, if site is configured
+    let weather_location = conf.weather_location();
+    if let Some(site_conf) = conf.site {
+        conf.site = Some( verify_site_conf(site_conf, weather_location, &mut errors) );
+    }
+    // verifies forecast conf data, if forecast is configured
+    if let Some(forecast_conf) = &conf.forecast {
+        verify_forecast_conf(forecast_conf, &mut errors);
+    }
+    // verifies tariff conf data, if tariff is configured
+    if let Some(tariff_conf) = &conf.tariff {
+        verify_tariff_conf(tariff_conf, &mut errors);
+    }
+    // verifies reconciliation conf data, if reconciliation is configured
+    if let Some(reconciliation_conf) = &conf.reconciliation {
+        verify_reconciliation_conf(reconciliation_conf, &mut errors);
+    }
+    // verifies retention conf data, if retention is configured
+    if let Some(retention_conf) = &conf.retention {
+        verify_retention_conf(retention_conf, &mut errors);
+    }
+    // verifies api conf data, if api is configured
+    if let Some(api_conf) = &conf.api {
+        verify_api_conf(api_conf, &mut errors);
+    }
+    if errors.is_empty() { Ok(conf) } else { Err(errors) }
+}
+
+fn load_conf(settings: Config) -> std::result::Result<Conf, Vec<ConfigError>> {
+    // deserializes and verifies settings.  Wrong types (eg text for a number) stop deserializing, so are reported alone.
+    let conf = settings.try_deserialize::<Conf>()
+        .map_err( |de_eff| vec![ ConfigError::Unreadable { error: de_eff.to_string() } ] )?;
+    verify_conf(conf)
+}
+
+fn warn_unknown_keys(conf_keys: &BTreeMap<String, String>) {
+    // settings no section reads, eg misspelled keys.  They are ignored, so the default is used.
+    for key in validation::unknown_keys(conf_keys) {
+        warn!("Unknown configuration setting {} is ignored.  Check spelling.", key);
+    }
+}
+
+fn exit_conf_errors(errors: &[ConfigError]) -> ! {
+    // reports config errors on log and stderr and exits
+    let report = validation::report(errors);
+    error!("{}", report);
+    eprintln!("{}", report);
+    std::process::exit(EXIT_CONFIG_ERROR)
+}
+
+async fn reload_conf(conf_tx: watch::Sender<Conf>, pool_tx: watch::Sender<Option<sqlx::Pool<sqlx::MySql>>>,
+    mut conf_keys: BTreeMap<String, String>, supervisor: TaskSupervisor, shutdown: Shutdown) {
+    // reads config again when the file changes or on SIGHUP.  A valid config is sent to the tasks and the tasks whose settings
+    // changed are restarted (after their current work).  An invalid config is rejected and logged with its changes.
+    let mut watcher = ConfigWatcher::new(CONFIG_NAME);
+    loop {
+        let reason = tokio::select! {
+            reason = watcher.changed() => reason,
+            _ = shutdown.wait() => return,
+        };
+        let settings = match read_conf_settings() {
+            Ok(settings) => settings,
+            Err(read_eff) => {
+                error!("Config reload ({}) rejected.  Couldn't read configuration file. {}  Keeping current config.", reason, read_eff);
+                continue;
+            },
+        };
+        let new_keys = conf_settings_map(&settings);
+        let changed = reload::changed_keys(&conf_keys, &new_keys);
+        if changed.is_empty() {
+            debug!("Config reload ({}).  No changes.", reason);
+            continue;
+        }
+        let diff = reload::settings_diff(&conf_keys, &new_keys);
+
+        warn_unknown_keys(&new_keys);
+        let new_conf = match load_conf(settings) {
+            Ok(new_conf) => new_conf,
+            Err(errors) => {
+                error!("Config reload ({}) rejected.  {}\nKeeping current config.  Rejected changes:\n{}", reason, validation::report(&errors), diff);
+                continue;
+            },
+        };
+        // new mysql settings need a new pool.  Settings that can't connect are rejected.
+        let mysql_changed = changed.iter().any( |key| reload::key_in_sections(key, &["mysql"]) );
+        if mysql_changed {
+            match get_sqlx_solar_pool(&new_conf.mysql).await {
+                Some(new_pool) => {
+                    pool_tx.send_replace( Some(new_pool) );
+                },
+                None => {
+                    error!("Config reload ({}) rejected.  Couldn't connect with new mysql settings.  Keeping current config.  Rejected changes:\n{}",
+                        reason, diff);
+                    continue;
+                },
+            }
+        }
+
+        info!("Config reloaded ({}).  Changes:\n{}", reason, diff);
+        conf_tx.send_replace(new_conf);
+        for (task, sections) in TASK_CONF_SECTIONS {
+            if mysql_changed || changed.iter().any( |key| reload::key_in_sections(key, sections) ) {
+                supervisor.restart(task);
+            }
+        }
+        conf_keys = new_keys;
+    }
+}
+
+fn conf_schedule(schedule: &str, interval: u64, units: char, offset: TimeDelta) -> Schedule {
+    // schedule for a job from its config.  Config schedules are checked by verify_*_conf at startup, so this only panics on a bug.
+    match Schedule::from_conf(schedule, interval, units, offset) {
+        Ok(schedule) => schedule,
+        Err(schedule_eff) => {
+            error!("Invalid schedule in configuration. {}", schedule_eff);
+            panic!("Invalid schedule in configuration. {}", schedule_eff);
+        },
+    }
 }
 
 async fn get_pvs6_device_data(conf: &Pvs6Conf) -> Option<String> {
@@ -1790,9 +2847,23 @@ fn greater_option_dt( dt_vec: Vec<&Option<DateTime<Utc>>> ) -> Option<DateTime<U
 }
 
 async fn get_weather(wx_conf: &PirateWxConf) -> Option<Wx> {
+    // build list of forecast blocks to exclude from response.  hourly is only requested if hourly forecast hours are wanted,
+    // and extended (up to 168 hours) if more than the default 48 hours are wanted.
+    let mut exclude: Vec<&str> = Vec::new();
+    if !wx_conf.alerts {
+        exclude.push("alerts");
+    }
+    if !wx_conf.minutely_forecast {
+        exclude.push("minutely");
+    }
+    if wx_conf.hourly_forecast_hours <= 0 {
+        exclude.push("hourly");
+    }
+    let extend = if wx_conf.hourly_forecast_hours > 48 { "&extend=hourly" } else { "" };
+
     // create pirate_weather API url with pirate_wx_conf parameters
-    let pirate_wx_api_url= format!("https://api.pirateweather.net/forecast/{}/{},{}?exclude=minutely,hourly,alerts&units={}&version=2", 
-        wx_conf.api_key, wx_conf.lat, wx_conf.long, wx_conf.units ) ;
+    let pirate_wx_api_url= format!("https://api.pirateweather.net/forecast/{}/{},{}?exclude={}{}&units={}&version=2",
+        wx_conf.api_key, wx_conf.lat, wx_conf.long, exclude.join(","), extend, wx_conf.units ) ;
     // create new client for keep alive connections
     let pirate_wx_client = reqwest::Client::new();
     //send api request
@@ -1874,180 +2945,426 @@ async fn insert_pirate_wx_to_mysql(wx: Wx, sql_pool_opt: &Option<sqlx::Pool<sqlx
             Err(cur_wx_eff) => error!("Current Wx failed to upload to Mysql solar db current_wx table. Error: {}", cur_wx_eff),
         }
 
-        let daily_wx_result = sqlx::query(REPLACE_DAILY_WX_QUERY)
-            .bind(&wx.latitude)
-            .bind(&wx.longitude)
-            .bind(&wx.daily.data[0].time)
-            .bind(&wx.daily.data[0].sunrise_time)
-            .bind(&wx.daily.data[0].dawn_time)
-            .bind(&wx.daily.data[0].sunset_time)
-            .bind(&wx.daily.data[0].dusk_time)
-            .bind(&wx.daily.data[0].moon_phase)
-            .bind(&wx.daily.data[0].precip_accumulation)
-            .bind(&wx.daily.data[0].temperature_min)
-            .bind(&wx.daily.data[0].temperature_min_time)
-            .bind(&wx.daily.data[0].temperature_max)
-            .bind(&wx.daily.data[0].temperature_max_time)
-            .execute(sql_pool).await;
-        
-        match daily_wx_result {
-            Ok(res) => {
-                match res.rows_affected() {
-                0 => warn!("No rows of daily_wx table changed. Row should have been added (1 row affected) or replaced 
-                    (2 rows affected).  Response: {:#?}", res),
-                1 => info!("Daily Wx added to MySql solar db daily_wx table. {:#?}", res),
-                2 => info!("Daily Wx replaced existing weather for day in MySql solar db daily_wx table. {:#?}", res),
-                3_u64..=u64::MAX => warn!("More than 2 rows of daily_wx table changed. Row should have been added (1 row affected) or replaced 
-                (2 rows affected).  Response: {:#?}", res),
+        // Upload every day returned (today plus ~7 days of forecast).  daily_wx keeps the latest forecast for each day and
+        // daily_wx_forecast keeps every retrieval so changes in a day's forecast can be tracked.
+        if wx.daily.data.is_empty() {
+            // weather stations have no daily forecast
+            debug!("Weather response contained no daily data.  Nothing uploaded to daily_wx table.");
+        }
+        for day in wx.daily.data.iter() {
+            let daily_wx_result = sqlx::query(REPLACE_DAILY_WX_QUERY)
+                .bind(wx.latitude)
+                .bind(wx.longitude)
+                .bind(day.time)
+                .bind(&day.summary)
+                .bind(&day.icon)
+                .bind(day.sunrise_time)
+                .bind(day.dawn_time)
+                .bind(day.sunset_time)
+                .bind(day.dusk_time)
+                .bind(day.moon_phase)
+                .bind(day.precip_accumulation)
+                .bind(day.precip_probability)
+                .bind(day.temperature_min)
+                .bind(day.temperature_min_time)
+                .bind(day.temperature_max)
+                .bind(day.temperature_max_time)
+                .bind(day.cloud_cover)
+                .bind(day.uv_index)
+                .execute(sql_pool).await;
+
+            let day_str = day.time.format("%Y-%m-%d");
+            match daily_wx_result {
+                Ok(res) => {
+                    match res.rows_affected() {
+                    0 => warn!("No rows of daily_wx table changed for {}. Row should have been added (1 row affected) or replaced 
+                        (2 rows affected).  Response: {:#?}", day_str, res),
+                    1 => debug!("Daily Wx for {} added to MySql solar db daily_wx table. {:#?}", day_str, res),
+                    2 => debug!("Daily Wx for {} replaced existing weather for day in MySql solar db daily_wx table. {:#?}", day_str, res),
+                    3_u64..=u64::MAX => warn!("More than 2 rows of daily_wx table changed for {}. Row should have been added (1 row affected) or replaced 
+                    (2 rows affected).  Response: {:#?}", day_str, res),
+                    }
+                },
+
+                Err(daily_wx_eff) => error!("Daily Wx for {} failed to upload to Mysql solar db daily_wx table. Error: {}", day_str, daily_wx_eff),
+            }
+
+            let daily_forecast_result = sqlx::query(REPLACE_DAILY_WX_FORECAST_QUERY)
+                .bind(wx.latitude)
+                .bind(wx.longitude)
+                .bind(wx.currently.time)
+                .bind(day.time)
+                .bind(&day.summary)
+                .bind(&day.icon)
+                .bind(day.sunrise_time)
+                .bind(day.sunset_time)
+                .bind(day.precip_accumulation)
+                .bind(day.precip_probability)
+                .bind(day.temperature_min)
+                .bind(day.temperature_max)
+                .bind(day.cloud_cover)
+                .bind(day.uv_index)
+                .execute(sql_pool).await;
+
+            if let Err(daily_forecast_eff) = daily_forecast_result {
+                error!("Daily Wx forecast for {} failed to upload to Mysql solar db daily_wx_forecast table. Error: {}", day_str, daily_forecast_eff);
+            }
+        }
+        info!("Daily Wx ({} days) processed for MySql solar db daily_wx and daily_wx_forecast tables.", wx.daily.data.len());
+    }
+}
+
+async fn insert_pirate_wx_forecast_to_mysql(wx: &Wx, sql_pool_opt: &Option<sqlx::Pool<sqlx::MySql>>, hourly_forecast_hours: i64 ) {
+    // Inserts hourly and minutely forecasts into hourly_wx_forecast and minutely_wx_forecast tables.  Each forecast row is keyed by
+    // issueTime (currently.time of the response) and time (time the forecast is valid for) so successive forecasts for the same
+    // hour are kept and can later be compared against actual production.  Only hours up to hourly_forecast_hours after issueTime are kept.
+
+    if let Some(sql_pool) = sql_pool_opt {
+        let issue_time = wx.currently.time;
+
+        if let Some(hourly) = &wx.hourly {
+            let forecast_end = issue_time + TimeDelta::hours(hourly_forecast_hours);
+            let mut rows_ok: u32 = 0;
+            let mut rows_failed: u32 = 0;
+
+            for hour in hourly.data.iter().filter( |h| h.time <= forecast_end ) {
+                let hourly_result = sqlx::query(REPLACE_HOURLY_WX_FORECAST_QUERY)
+                    .bind(wx.latitude)
+                    .bind(wx.longitude)
+                    .bind(issue_time)
+                    .bind(hour.time)
+                    .bind(&hour.summary)
+                    .bind(&hour.icon)
+                    .bind(hour.precip_intensity)
+                    .bind(hour.precip_probability)
+                    .bind(hour.precip_accumulation)
+                    .bind(&hour.precip_type)
+                    .bind(hour.temperature)
+                    .bind(hour.apparent_temperature)
+                    .bind(hour.dew_point)
+                    .bind(hour.humidity)
+                    .bind(hour.pressure)
+                    .bind(hour.wind_speed)
+                    .bind(hour.wind_gust)
+                    .bind(hour.wind_bearing)
+                    .bind(hour.cloud_cover)
+                    .bind(hour.uv_index)
+                    .bind(hour.visibility)
+                    .bind(hour.ozone)
+                    .bind(hour.smoke)
+                    .execute(sql_pool).await;
+
+                match hourly_result {
+                    Ok(_) => rows_ok += 1,
+                    Err(hourly_eff) => {
+                        error!("Hourly Wx forecast for {} failed to upload to Mysql solar db hourly_wx_forecast table. Error: {}",
+                            hour.time.format("%Y-%m-%d %H:%M:%S"), hourly_eff);
+                        rows_failed += 1;
+                    },
                 }
-            },
+            }
+            match rows_failed {
+                0 => info!("Hourly Wx forecast ({} hours) uploaded to MySql solar db hourly_wx_forecast table.", rows_ok),
+                _ => warn!("Hourly Wx forecast partially uploaded to MySql solar db hourly_wx_forecast table. {} hours uploaded, {} failed.",
+                    rows_ok, rows_failed),
+            }
+        }
+
+        if let Some(minutely) = &wx.minutely {
+            let mut rows_ok: u32 = 0;
+            let mut rows_failed: u32 = 0;
+
+            for minute in minutely.data.iter() {
+                let minutely_result = sqlx::query(REPLACE_MINUTELY_WX_FORECAST_QUERY)
+                    .bind(wx.latitude)
+                    .bind(wx.longitude)
+                    .bind(issue_time)
+                    .bind(minute.time)
+                    .bind(minute.precip_intensity)
+                    .bind(minute.precip_probability)
+                    .bind(minute.precip_intensity_error)
+                    .bind(&minute.precip_type)
+                    .execute(sql_pool).await;
 
-            Err(daily_wx_eff) => error!("Daily Wx failed to upload to Mysql solar db daily_wx table. Error: {}", daily_wx_eff),
+                match minutely_result {
+                    Ok(_) => rows_ok += 1,
+                    Err(minutely_eff) => {
+                        error!("Minutely Wx forecast for {} failed to upload to Mysql solar db minutely_wx_forecast table. Error: {}",
+                            minute.time.format("%Y-%m-%d %H:%M:%S"), minutely_eff);
+                        rows_failed += 1;
+                    },
+                }
+            }
+            match rows_failed {
+                0 => info!("Minutely Wx forecast ({} minutes) uploaded to MySql solar db minutely_wx_forecast table.", rows_ok),
+                _ => warn!("Minutely Wx forecast partially uploaded to MySql solar db minutely_wx_forecast table. {} minutes uploaded, {} failed.",
+                    rows_ok, rows_failed),
+            }
         }
     }
 }
 
-fn verify_pirate_wx_conf(pirate_wx_conf: PirateWxConf ) -> PirateWxConf {
-    // confirms that lat, long, and units are all present in conf file (does not validate parameters are correct, only that they exist.)
-    // confirms that either API key or file path to API key are included in file (does not validatey that are correct)
-    // uses API key if it is in file,  if not, attempts to read API key from file.  
-    // Logs error and panics if above conditions are not met
+async fn insert_pirate_wx_alerts_to_mysql(wx: &Wx, sql_pool_opt: &Option<sqlx::Pool<sqlx::MySql>> ) -> Vec<WxAlert> {
+    // Inserts weather alerts into wx_alerts table if they are not already stored.  Returns alerts that were new (inserted) so
+    // they can be passed on for notification.  Alerts are returned by Pirate WX on every request while active, so dedup happens
+    // in the db by alertId.  Without a db, new alerts can't be told apart from ones already sent, so none are returned.
 
-    let mut wx_conf = pirate_wx_conf;
-    // if lat, long, and units don't exist in conf file, log error and panic.
-    if wx_conf.lat.is_empty() || wx_conf.long.is_empty() || wx_conf.units.is_empty() {
-        error!("Pirate Weather configuration file parameters latitute, longitute, or units are missing or empty");
-        panic!("Missing Pirate Weather Config Parameters");
-    }
-    // if API key and API Key file path don't exist in conf file, log error and panic.
-    else if wx_conf.api_key.is_empty() && wx_conf.api_key_path.is_empty() {
-        error!("Pirate Weather configuration file parameters ap i_key_path and api_key are both
-                missing or empty.  Must have at least one");
-        panic!("Missing Pirate Weather Config Parameters");
-    } 
-    else if  !matches!( wx_conf.interval_unit, 'd' | 'h' | 'm' | 's') {
-        // if interval units is not d, h, m or s, log error and panic
-        error!("Pirate Weather configuration file parameters interval_units is incorrect value. Must be 'd', 'h', 'm', or 's'.");
-        panic!("Incorrect Pirate WX interval_units value.");
-    } else {
-        // if api Key exists in file, use it and return data
-        if !wx_conf.api_key.is_empty() {
-            return wx_conf
-        } else {
-            // otherwise, get API key from file path.
-            let api_key_res = fs::read_to_string( &wx_conf.api_key_path );
-            match api_key_res {
-                // success reading from file, save value to API Key and return data
-                Ok(api) => {
-                    wx_conf.api_key = api;
-                    return wx_conf
+    let mut new_alerts: Vec<WxAlert> = Vec::new();
+
+    if let Some(sql_pool) = sql_pool_opt {
+        for alert in wx.alerts.iter() {
+            let alert_result = sqlx::query(INSERT_IGNORE_WX_ALERT_QUERY)
+                .bind(alert.alert_id())
+                .bind(wx.latitude)
+                .bind(wx.longitude)
+                .bind(&alert.title)
+                .bind(&alert.severity)
+                .bind(alert.regions.join(", "))
+                .bind(alert.time)
+                .bind(alert.expires)
+                .bind(&alert.description)
+                .bind(&alert.uri)
+                .bind(wx.currently.time)
+                .execute(sql_pool).await;
+
+            match alert_result {
+                Ok(res) => {
+                    if res.rows_affected() > 0 {
+                        info!("New Wx alert \"{}\" added to MySql solar db wx_alerts table.", alert.title);
+                        new_alerts.push(alert.clone());
+                    } else {
+                        debug!("Wx alert \"{}\" already in MySql solar db wx_alerts table.", alert.title);
+                    }
                 },
-                // Error reading from file, log error and panic.
-                Err(api_eff) => {
-                    error!( "Unable to retrieve Pirate Weather API key from file at {}. Err: {}", wx_conf.api_key_path, api_eff );
-                    panic!("Couldn't get Pirate WX API Key");
-                }
+                Err(alert_eff) => error!("Wx alert \"{}\" failed to upload to Mysql solar db wx_alerts table. Error: {}", alert.title, alert_eff),
             }
-            
+        }
+    } else if !wx.alerts.is_empty() {
+        warn!("{} active Wx alert(s) not stored or notified.  No MySql solar db to check which alerts were already sent.",
+            wx.alerts.len());
+    }
+    new_alerts
+}
+
+async fn notify_wx_alerts(new_alerts: &[WxAlert], wx_conf: &PirateWxConf) {
+    // Sends notification for new alerts that match configured severities (eg Severe, Extreme) or keywords (eg hail, snow) in the
+    // alert title.  Matching alerts are logged to the wx_alert logger (configure its appender in log_config.yml) and posted as json
+    // to alert_webhook_url if one is configured.  Other alerts are only stored in wx_alerts.
+
+    for alert in new_alerts.iter() {
+        let severity_match = match &alert.severity {
+            Some(severity) => wx_conf.alert_notify_severities.iter().any( |s| s.eq_ignore_ascii_case(severity) ),
+            None => false,
+        };
+        let title_lower = alert.title.to_lowercase();
+        let keyword_match = wx_conf.alert_notify_keywords.iter().any( |k| title_lower.contains(&k.to_lowercase()) );
+
+        if !severity_match && !keyword_match {
+            continue;
+        }
+
+        let expires = match alert.expires {
+            Some(exp) => exp.format("%Y-%m-%d %H:%M:%S").to_string(),
+            None => "Unknown".to_string(),
+        };
+        warn!(target: "wx_alert", "{} ({}) for {}. Expires: {} UTC",
+            alert.title, alert.severity.as_deref().unwrap_or("Unknown severity"), alert.regions.join(", "), expires);
+
+        if !wx_conf.alert_webhook_url.is_empty() {
+            let alert_json = serde_json::json!({
+                "title": alert.title,
+                "severity": alert.severity,
+                "regions": alert.regions,
+                "time": alert.time,
+                "expires": alert.expires,
+                "description": alert.description,
+                "uri": alert.uri,
+            });
+            let webhook_response = reqwest::Client::new().post(&wx_conf.alert_webhook_url)
+                .json(&alert_json)
+                .send()
+                .await;
+            match webhook_response {
+                Ok(response) if response.status().is_success() => debug!("Wx alert \"{}\" posted to webhook.", alert.title),
+                Ok(response) => error!("Wx alert webhook returned error code: {}", response.status()),
+                Err(webhook_eff) => error!("Unable to post Wx alert to webhook. Err: {}", webhook_eff),
+            }
+        }
+    }
+}
+
+fn verify_pirate_wx_conf(pirate_wx_conf: PirateWxConf, errors: &mut Vec<ConfigError>) -> PirateWxConf {
+    // verifies lat / long are in range, units is one pirate weather supports, and either api_key or api_key_path is included.
+    // uses API key if it is in file,  if not, attempts to read API key from file.
+    // Adds a ConfigError to errors for each problem
+
+    let mut wx_conf = pirate_wx_conf;
+    verify_lat_long("pirate_wx", &wx_conf.lat, &wx_conf.long, errors);
+    if wx_conf.units.is_empty() {
+        errors.push( ConfigError::missing("pirate_wx.units", "Must be 'us', 'ca', 'uk' or 'si'.") );
+    } else if !weather::WX_UNITS.contains( &wx_conf.units.as_str() ) {
+        errors.push( ConfigError::invalid("pirate_wx.units", &wx_conf.units, "Must be 'us', 'ca', 'uk' or 'si'.") );
+    }
+    if !matches!( wx_conf.interval_unit, 'd' | 'h' | 'm' | 's') {
+        errors.push( ConfigError::invalid("pirate_wx.interval_unit", wx_conf.interval_unit, "Must be 'd', 'h', 'm', or 's'.") );
+    } else if let Err(schedule_eff) = Schedule::from_conf(&wx_conf.schedule, wx_conf.interval, wx_conf.interval_unit, wx_conf.offset) {
+        errors.push( ConfigError::invalid("pirate_wx.schedule", &wx_conf.schedule, &schedule_eff.to_string()) );
+    }
+    if !wx_conf.alert_webhook_url.is_empty() && !is_http_url(&wx_conf.alert_webhook_url) {
+        // url may hold a token, so it isn't shown
+        errors.push( ConfigError::invalid("pirate_wx.alert_webhook_url", "***", "Must be an http:// or https:// url.") );
+    }
+    // if api Key exists in file, use it.  Otherwise get API key from file path.
+    if wx_conf.api_key.is_empty() && wx_conf.api_key_path.is_empty() {
+        errors.push( ConfigError::missing("pirate_wx.api_key", "Must have api_key or api_key_path.") );
+    } else if wx_conf.api_key.is_empty() {
+        match fs::read_to_string( &wx_conf.api_key_path ) {
+            Ok(api) => wx_conf.api_key = api,
+            Err(api_eff) => errors.push( ConfigError::file("pirate_wx.api_key_path", &wx_conf.api_key_path, api_eff) ),
+        }
+    }
+    wx_conf
+}
+
+fn verify_lat_long(section: &str, lat: &str, long: &str, errors: &mut Vec<ConfigError>) {
+    // lat -90 to 90 and long -180 to 180 degrees
+    for (name, value, range) in [ ("lat", lat, -90.0..=90.0), ("long", long, -180.0..=180.0) ] {
+        let key = format!("{}.{}", section, name);
+        if value.trim().is_empty() {
+            errors.push( ConfigError::missing(&key, "") );
+        } else if !value.trim().parse::<f64>().is_ok_and( |degrees| range.contains(&degrees) ) {
+            errors.push( ConfigError::invalid(&key, value, &format!("Must be a number from {} to {} degrees.", range.start(), range.end())) );
         }
     }
 }
 
-fn verify_pvs6_conf(pvs6_conf: &Pvs6Conf) {
-    // verifies if host provided.  if not, logs error and panics
-    // verifies if get_device)interval_units is 'd', 'h', 'm' or 's'
+fn is_http_url(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and( |url| matches!( url.scheme(), "http" | "https" ) && url.host().is_some() )
+}
+
+fn verify_site_conf(site_conf: SiteConf, weather_location: Option<(f64, f64)>, errors: &mut Vec<ConfigError>) -> SiteConf {
+    // verifies capacity, tilt, azimuth and losses are in range.  Uses weather lat / long if site lat / long not provided.
+    // Adds a ConfigError to errors for each problem
+    let mut conf = site_conf;
+
+    if conf.lat.is_none() || conf.long.is_none() {
+        // weather lat / long are verified with weather provider conf
+        match weather_location {
+            Some( (lat, long) ) => {
+                conf.lat = Some(lat);
+                conf.long = Some(long);
+            },
+            None if conf.lat.is_none() => errors.push( ConfigError::missing("site.lat", "Set it, or a weather provider lat / long.") ),
+            None => errors.push( ConfigError::missing("site.long", "Set it, or a weather provider lat / long.") ),
+        }
+    } else {
+        let (lat, long) = conf.location();
+        verify_lat_long( "site", &lat.to_string(), &long.to_string(), errors );
+    }
+    if conf.capacity_kw <= 0.0 {
+        errors.push( ConfigError::invalid("site.capacity_kw", conf.capacity_kw, "Must be positive.") );
+    }
+    if !( 0.0..=90.0 ).contains(&conf.tilt) {
+        errors.push( ConfigError::invalid("site.tilt", conf.tilt, "Must be from 0 to 90 degrees.") );
+    }
+    if !( 0.0..=360.0 ).contains(&conf.azimuth) {
+        errors.push( ConfigError::invalid("site.azimuth", conf.azimuth, "Must be from 0 to 360 degrees.") );
+    }
+    if !( 0.0..1.0 ).contains(&conf.losses) {
+        errors.push( ConfigError::invalid("site.losses", conf.losses, "Must be a fraction from 0 to less than 1.") );
+    }
+    conf
+}
+
+fn verify_pvs6_conf(pvs6_conf: &Pvs6Conf, errors: &mut Vec<ConfigError>) {
+    // verifies host is an http url, interval units, schedule, night mode, missed tick behavior and max power.
+    // Adds a ConfigError to errors for each problem
     if pvs6_conf.host.is_empty() {
-        error!("PVS6 configuration file parameter host is missing or empty");
-        panic!("Missing PVS6 Config Parameters");
-    } else if !matches!( pvs6_conf.get_device_interval_unit, 'd' | 'h' | 'm' | 's') {
-        error!("PVS6 configuration file parameters interval_units is incorrect value. Must be 'd', 'h', 'm', or 's'.");
-        panic!("Incorrect PVS6 interval_units value.");
+        errors.push( ConfigError::missing("pvs6.host", "Must be the PVS6 installer port url, eg \"http://172.27.153.1\".") );
+    } else if !is_http_url(&pvs6_conf.host) {
+        errors.push( ConfigError::invalid("pvs6.host", &pvs6_conf.host, "Must be an http:// url, eg \"http://172.27.153.1\".") );
+    }
+    if !matches!( pvs6_conf.get_device_interval_unit, 'd' | 'h' | 'm' | 's') {
+        errors.push( ConfigError::invalid("pvs6.get_device_interval_unit", pvs6_conf.get_device_interval_unit, "Must be 'd', 'h', 'm', or 's'.") );
+    } else if let Err(schedule_eff) = Schedule::from_conf(&pvs6_conf.get_device_schedule, pvs6_conf.get_device_interval,
+        pvs6_conf.get_device_interval_unit, pvs6_conf.get_device_offset) {
+        errors.push( ConfigError::invalid("pvs6.get_device_schedule", &pvs6_conf.get_device_schedule, &schedule_eff.to_string()) );
+    }
+    if !matches!( pvs6_conf.night_mode.as_str(), "full" | "reduced" | "meters_only" ) {
+        errors.push( ConfigError::invalid("pvs6.night_mode", &pvs6_conf.night_mode, "Must be 'full', 'reduced', or 'meters_only'.") );
+    }
+    if interval_seconds(pvs6_conf.night_interval, pvs6_conf.night_interval_unit).is_none() {
+        errors.push( ConfigError::invalid("pvs6.night_interval_unit", pvs6_conf.night_interval_unit, "Must be 'd', 'h', 'm', or 's'.") );
+    }
+    if parse_missed_tick_behavior(&pvs6_conf.missed_tick_behavior).is_none() {
+        errors.push( ConfigError::invalid("pvs6.missed_tick_behavior", &pvs6_conf.missed_tick_behavior, "Must be 'skip', 'burst', or 'delay'.") );
+    }
+    if pvs6_conf.max_inverter_kw <= 0.0 {
+        errors.push( ConfigError::invalid("pvs6.max_inverter_kw", pvs6_conf.max_inverter_kw, "Must be greater than 0.") );
+    }
+    if pvs6_conf.max_meter_kw <= 0.0 {
+        errors.push( ConfigError::invalid("pvs6.max_meter_kw", pvs6_conf.max_meter_kw, "Must be greater than 0.") );
+    }
+    if pvs6_conf.auto_tune_offset && !pvs6_conf.get_device_schedule.trim().is_empty() 
+        && !pvs6_conf.get_device_schedule.trim().starts_with("every") {
+        warn!("PVS6 auto_tune_offset only works with \"every\" schedules.  Offset will not be tuned for cron get_device_schedule.");
     }
 }
 
-fn verify_mysql_conf(mysql_conf: MySqlConf ) -> MySqlConf {
+fn verify_mysql_conf(mysql_conf: MySqlConf, errors: &mut Vec<ConfigError>) -> MySqlConf {
     // confirms that database is present in conf file (does not validate parameters are correct, only that they exist.)
     // uses host, user, and password values if provided over login_info_loc
-    // Logs error and panics if above conditions are not met
+    // Adds a ConfigError to errors for each problem
 
-    //check if database provided.  Log error and panic if not.
+    //check if database provided.
     if mysql_conf.database.is_empty() {
-        error!("MySql configuration file parameter database is missing or empty");
-        panic!("Missing MySql Config Parameter database");      
+        errors.push( ConfigError::missing("mysql.database", "") );
+    }
+    if mysql_conf.max_connections == 0 {
+        errors.push( ConfigError::invalid("mysql.max_connections", mysql_conf.max_connections, "Must be at least 1.") );
+    }
+    if !mysql_conf.port.is_empty() && mysql_conf.port.parse::<u16>().is_err() {
+        errors.push( ConfigError::invalid("mysql.port", &mysql_conf.port, "Must be a port number.") );
     }
     //check if host, user, and password provided.  If so, return conf file and be done
     if !mysql_conf.host.is_empty() && !mysql_conf.user.is_empty() && !mysql_conf.password.is_empty() && !mysql_conf.port.is_empty() {
         return mysql_conf
     } 
 
-    // if login_info_loc or login_path are empty, log error and crash
+    // if login_info_loc or login_path are empty, missing settings can't be read from .cnf file
     if mysql_conf.login_info_loc.is_empty() || mysql_conf.login_path.is_empty() {
-        error!("MySql configuration file parameter login_info_loc or login_path is missing or empty and at least one of host, 
-                user, passowrd or port is empty.");
-        panic!("Missing MySql Config Parameters. MySql configuration file parameter login_info_loc or login_path is missing or 
-                empty and at least one of host, user, passowrd or port is empty.");     
+        for (key, value) in [ ("mysql.host", &mysql_conf.host), ("mysql.port", &mysql_conf.port), ("mysql.user", &mysql_conf.user),
+            ("mysql.password", &mysql_conf.password) ] {
+            if value.is_empty() {
+                errors.push( ConfigError::missing(key, "Set it, or set login_info_loc and login_path to read it from a .cnf file.") );
+            }
+        }
+        return mysql_conf
     }
-    //create mutable Pvs6Conf variable to save values from 
+    //create mutable MySqlConf variable to save values from 
     let mut conf = mysql_conf;
     
-    //try to parse login_info_loc file.  If can't parse, log error and panic. 
+    //try to parse login_info_loc file.
     let my_login_file_path = PathBuf::from( &conf.login_info_loc );
 
     match fs::exists(&my_login_file_path) {
-        Ok(file_exists) => {
-            if file_exists == true {
-                let mysql_login = myloginrs_parse(
-                    &conf.login_path, 
-                    Some(&my_login_file_path)
-                );
-                if conf.host.is_empty() {
-                    match mysql_login.get("host") {
-                        Some(h) => conf.host = h.to_owned(),
-                        None => {
-                            error!("MySql configuration file parameter host is missing and not included in .cnf file.");
-                            panic!("MySql configuration file parameter host is missing and not included in .cnf file.");
-                        },
-                    }
-                }
-                if conf.port.is_empty() {
-                    match mysql_login.get("port") {
-                        Some(p) => conf.port = p.to_owned(),
-                        None => {
-                            error!("MySql configuration file parameter port is missing and not included in .cnf file.");
-                            panic!("MySql configuration file parameter port is missing and not included in .cnf file.");
-                        },
-                    }
-                }
-                if conf.user.is_empty() {
-                    match mysql_login.get("user") {
-                        Some(u) => conf.user = u.to_owned(),
-                        None => {
-                            error!("MySql configuration file parameter user is missing and not included in .cnf file.");
-                            panic!("MySql configuration file parameter user is missing and not included in .cnf file.");
-                        },
-                    }
-                }
-                if conf.password.is_empty() {
-                    match mysql_login.get("password") {
-                        Some(pw) => conf.password = pw.to_owned(),
-                        None => {
-                            error!("MySql configuration file parameter password is missing and not included in .cnf file.");
-                            panic!("MySql configuration file parameter password is missing and not included in .cnf file.");
-                        },
+        Ok(true) => {
+            let mysql_login = myloginrs_parse(
+                &conf.login_path, 
+                Some(&my_login_file_path)
+            );
+            for (key, value) in [ ("host", &mut conf.host), ("port", &mut conf.port), ("user", &mut conf.user), ("password", &mut conf.password) ] {
+                if value.is_empty() {
+                    match mysql_login.get(key) {
+                        Some(login_value) => *value = login_value.to_owned(),
+                        None => errors.push( ConfigError::missing( &format!("mysql.{}", key), "Not in config and not in .cnf file." ) ),
                     }
                 }
             }
-            else {
-                error!("Error: {} Login Credential file does not exist", &conf.login_info_loc);
-                panic!("Error: {} Login Credential file does not exist", &conf.login_info_loc)
-            }
         },
-        Err(fp_eff) => {
-            error!("Error: Mysql login file exists but can't be accessed.  
-            May have incorrect permissions. Err: {}",fp_eff);
-            panic!("Error: Mysql login file exists but can't be accessed.  
-            May have incorrect permissions. Err: {}",fp_eff);
-        }
+        Ok(false) => errors.push( ConfigError::file("mysql.login_info_loc", &conf.login_info_loc, "Login credential file does not exist.") ),
+        Err(fp_eff) => errors.push( ConfigError::file("mysql.login_info_loc", &conf.login_info_loc,
+            format!("File can't be accessed.  May have incorrect permissions. {}", fp_eff) ) ),
     }
     conf
 }
@@ -0,0 +1,115 @@
+/*
+Expected output and performance ratio for each production meter reading.
+Expected power comes from clear-sky irradiance on the plane of the array (site lat / long, tilt and azimuth) scaled by the
+array capacity and system losses.  Performance ratio is actual production meter power / expected power.  A clear day should
+be close to 1.0.  Slow downward trends over months show soiling or degradation.
+With a personal weather station (pws section), measured performance ratio uses measured solar radiation instead of clear sky:
+expected power is scaled by measured / clear-sky GHI, so cloudy days are close to 1.0 as well.
+Results are stored in production_performance table, keyed by the same serial / data_time as production_meters_data.
+*/
+
+// USE STATEMENTS
+    use chrono::{ DateTime, Utc };
+    use log::{ debug, error };
+
+    use crate::{ ProductionMeter, SiteConf, pws::PwsData, solar_position };
+
+// CONSTANTS
+    // performance ratio is only stored when expected power is at least this fraction of capacity.  Near sunrise / sunset
+    // small differences in expected power give meaningless ratios.
+    const MIN_EXPECTED_CAPACITY_FRACTION: f64 = 0.05;
+    const INSERT_PRODUCTION_PERFORMANCE_QUERY: &str =
+    r#"
+        INSERT INTO production_performance
+            ( serial, data_time, sun_zenith_deg, sun_azimuth_deg, clear_sky_ghi_w_m2, poa_irradiance_w_m2,
+                expected_p_kw, performance_ratio, measured_ghi_w_m2, measured_performance_ratio )
+            VALUES ( ?, ?, ?, ?, ?, ?, ?, ?, ?, ? )
+    "#;
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+#[derive(Clone, Copy, Debug)]
+pub struct ExpectedOutput {
+    pub sun_zenith_deg: f64,
+    pub sun_azimuth_deg: f64,
+    pub clear_sky_ghi_w_m2: f64,
+    pub poa_irradiance_w_m2: f64,
+    pub expected_p_kw: f64,
+}
+
+// FUNCTIONS
+
+pub fn expected_output(site_conf: &SiteConf, time: DateTime<Utc>) -> ExpectedOutput {
+    // Expected instantaneous AC power (kW) of the array at time under clear sky.
+    let (lat, long) = site_conf.location();
+    let position = solar_position::solar_position(lat, long, time);
+    let irradiance = solar_position::clear_sky_irradiance(&position, time);
+    let poa = solar_position::plane_of_array_irradiance(&position, &irradiance, site_conf.tilt, site_conf.azimuth);
+
+    ExpectedOutput {
+        sun_zenith_deg: position.zenith_deg,
+        sun_azimuth_deg: position.azimuth_deg,
+        clear_sky_ghi_w_m2: irradiance.ghi,
+        poa_irradiance_w_m2: poa,
+        expected_p_kw: site_conf.capacity_kw * poa / 1000.0 * ( 1.0 - site_conf.losses ),
+    }
+}
+
+pub fn performance_ratio(site_conf: &SiteConf, actual_p_kw: f64, expected_p_kw: f64) -> Option<f64> {
+    if expected_p_kw < site_conf.capacity_kw * MIN_EXPECTED_CAPACITY_FRACTION {
+        None
+    } else {
+        Some( actual_p_kw / expected_p_kw )
+    }
+}
+
+pub fn measured_expected_p_kw(expected: &ExpectedOutput, measured_ghi_w_m2: f64) -> Option<f64> {
+    // Expected power for measured horizontal irradiance.  Plane of array irradiance is assumed to scale with GHI.
+    ( expected.clear_sky_ghi_w_m2 > 0.0 ).then( || expected.expected_p_kw * measured_ghi_w_m2 / expected.clear_sky_ghi_w_m2 )
+}
+
+pub async fn insert_production_performance_to_mysql(prod_meter: &ProductionMeter, site_conf: &SiteConf, measured: Option<&PwsData>,
+    sql_pool_opt: &Option<sqlx::Pool<sqlx::MySql>>) {
+    // Calculates expected output and performance ratio for production meter reading and uploads to production_performance table.
+    // measured is the personal weather station data aligned to the reading, if there is any.
+    // Readings without data (already uploaded and cleared by update_pvs6_old_responses) are skipped.
+
+    let (Some(data_time), Some(p_3phsum_kw)) = (prod_meter.data_time, prod_meter.p_3phsum_kw) else {
+        debug!("Production meter reading has no new data.  Performance ratio not calculated.");
+        return
+    };
+    let Some(sql_pool) = sql_pool_opt else {
+        error!("Couldn't get sql pool");
+        return
+    };
+
+    let expected = expected_output(site_conf, data_time);
+    let ratio = performance_ratio(site_conf, p_3phsum_kw, expected.expected_p_kw);
+    let measured_ghi = measured.and_then( |measured| measured.reading.solar_radiation_w_m2 );
+    let measured_ratio = measured_ghi.and_then( |ghi| measured_expected_p_kw(&expected, ghi) )
+        .and_then( |measured_expected_p_kw| performance_ratio(site_conf, p_3phsum_kw, measured_expected_p_kw) );
+
+    let perf_result = sqlx::query(INSERT_PRODUCTION_PERFORMANCE_QUERY)
+        .bind(&prod_meter.serial)
+        .bind(data_time)
+        .bind(expected.sun_zenith_deg)
+        .bind(expected.sun_azimuth_deg)
+        .bind(expected.clear_sky_ghi_w_m2)
+        .bind(expected.poa_irradiance_w_m2)
+        .bind(expected.expected_p_kw)
+        .bind(ratio)
+        .bind(measured_ghi)
+        .bind(measured_ratio)
+        .execute(sql_pool).await;
+
+    match perf_result {
+        Ok(_) => debug!(
+            "Production performance: {} @ {} expected {:.3} kW, actual {:.3} kW, ratio {:?}, measured ratio {:?} uploaded to Mysql solar database",
+            prod_meter.serial, data_time.format("%Y-%m-%d %H:%M:%S"), expected.expected_p_kw, p_3phsum_kw, ratio, measured_ratio
+        ),
+        Err(perf_eff) => error!(
+            "Production performance: {} @ {} failed to upload to Mysql solar database. Error: {}",
+            prod_meter.serial, data_time.format("%Y-%m-%d %H:%M:%S"), perf_eff
+        ),
+    }
+}
@@ -0,0 +1,232 @@
+/*
+Plausibility checks for PVS6 meter and inverter readings (plausibility section of config.yml).  Each new reading's values are
+checked against a range per device type and field.  Built in ranges (DEFAULT_RANGES) can be changed or added to in config,
+and an empty list removes one.  Values that aren't finite numbers (NaN / inf) are always out of range.
+Out of range values are logged and counted (in /healthz with the api), and with action:
+    quarantine  value is set aside in quarantined_readings (with the range it failed) and stored as NULL, so analytics skip it
+                and the rest of the reading is kept.
+    log         value is stored as is.  Only logged and counted.
+Checked after repeated readings (already in the solar db) are cleared, so each reading is only counted once.
+*/
+
+// USE STATEMENTS
+    use std::collections::BTreeMap;
+    use chrono::{ DateTime, Utc };
+    use log::{ debug, error, warn };
+    use serde::Deserialize;
+
+    use crate::{ ConsumptionMeter, Inverter, ProductionMeter, Pvs6DevicesResponse, validation::ConfigError };
+
+// CONSTANTS
+    // checked fields of each device type.  Keys of plausibility settings
+    pub const PRODUCTION_METER_FIELDS: [&str; 8] = [ "freq_hz", "i_a", "net_ltea_3phsum_kwh", "p_3phsum_kw", "q_3phsum_kvar",
+        "s_3phsum_kva", "tot_pf_rto", "v12_v" ];
+    pub const CONSUMPTION_METER_FIELDS: [&str; 15] = [ "freq_hz", "i1_a", "i2_a", "neg_ltea_3phsum_kwh", "net_ltea_3phsum_kwh",
+        "p_3phsum_kw", "p1_kw", "p2_kw", "pos_ltea_3phsum_kwh", "q_3phsum_kvar", "s_3phsum_kva", "tot_pf_rto", "v12_v", "v1n_v", "v2n_v" ];
+    pub const INVERTER_FIELDS: [&str; 10] = [ "freq_hz", "i_3phsum_a", "i_mppt1_a", "ltea_3phsum_kwh", "p_3phsum_kw", "p_mppt1_kw",
+        "stat_ind", "t_htsnk_degc", "v_mppt1_v", "vln_3phavg_v" ];
+    // ( device, field, min, max ).  Wide enough for 50 / 60 Hz split phase and 230 V services
+    const DEFAULT_RANGES: [(&str, &str, f64, f64); 19] = [
+        ( "production_meter", "freq_hz", 45.0, 65.0 ),
+        ( "production_meter", "v12_v", 100.0, 300.0 ),
+        ( "production_meter", "tot_pf_rto", -1.0, 1.0 ),
+        ( "production_meter", "p_3phsum_kw", -100.0, 100.0 ),
+        ( "production_meter", "i_a", -500.0, 500.0 ),
+        ( "consumption_meter", "freq_hz", 45.0, 65.0 ),
+        ( "consumption_meter", "v12_v", 100.0, 300.0 ),
+        ( "consumption_meter", "v1n_v", 50.0, 150.0 ),
+        ( "consumption_meter", "v2n_v", 50.0, 150.0 ),
+        ( "consumption_meter", "tot_pf_rto", -1.0, 1.0 ),
+        ( "consumption_meter", "p_3phsum_kw", -100.0, 100.0 ),
+        ( "consumption_meter", "i1_a", -500.0, 500.0 ),
+        ( "consumption_meter", "i2_a", -500.0, 500.0 ),
+        ( "inverter", "freq_hz", 45.0, 65.0 ),
+        ( "inverter", "vln_3phavg_v", 100.0, 300.0 ),
+        ( "inverter", "v_mppt1_v", 0.0, 150.0 ),
+        ( "inverter", "t_htsnk_degc", -40.0, 125.0 ),
+        ( "inverter", "p_3phsum_kw", -0.1, 2.0 ),
+        ( "inverter", "p_mppt1_kw", -0.1, 2.0 ),
+    ];
+    const ACTIONS: [&str; 2] = [ "quarantine", "log" ];
+    const INSERT_QUARANTINED_READING_QUERY: &str =
+    r#"
+        INSERT IGNORE INTO quarantined_readings
+            ( serial, data_time, device, field, value, min_value, max_value )
+            VALUES ( ?, ?, ?, ?, ?, ?, ? )
+    "#;
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+#[derive(Debug, Deserialize, Clone )]
+pub struct PlausibilityConf {
+    // "quarantine" or "log"
+    #[serde( default = "default_plausibility_action" )]
+    pub action: String,
+    // field: [ min, max ].  Changes or adds to DEFAULT_RANGES.  [] removes a default range
+    #[serde( default )]
+    pub production_meter: BTreeMap<String, Vec<f64>>,
+    #[serde( default )]
+    pub consumption_meter: BTreeMap<String, Vec<f64>>,
+    #[serde( default )]
+    pub inverter: BTreeMap<String, Vec<f64>>,
+}
+fn default_plausibility_action() -> String {
+    "quarantine".to_string()
+}
+
+// ranges by ( device, field ) and action, from PlausibilityConf
+#[derive(Clone, Debug)]
+pub struct Plausibility {
+    quarantine: bool,
+    ranges: BTreeMap<(&'static str, &'static str), (f64, f64)>,
+}
+impl Plausibility {
+    pub fn from_conf(conf: &PlausibilityConf) -> Result<Self, Vec<ConfigError>> {
+        let mut errors = Vec::new();
+        if !ACTIONS.contains( &conf.action.as_str() ) {
+            errors.push( ConfigError::invalid("plausibility.action", &conf.action, &format!("Must be one of: {}", ACTIONS.join(", "))) );
+        }
+        let mut ranges: BTreeMap<(&'static str, &'static str), (f64, f64)> = DEFAULT_RANGES.iter()
+            .map( |(device, field, min, max)| ( (*device, *field), (*min, *max) ) )
+            .collect();
+        for (device, fields, device_ranges) in [
+            ( "production_meter", &PRODUCTION_METER_FIELDS[..], &conf.production_meter ),
+            ( "consumption_meter", &CONSUMPTION_METER_FIELDS[..], &conf.consumption_meter ),
+            ( "inverter", &INVERTER_FIELDS[..], &conf.inverter ),
+        ] {
+            for (field, range) in device_ranges {
+                let key = format!("plausibility.{}.{}", device, field);
+                let Some(field) = fields.iter().find( |known| *known == field ) else {
+                    errors.push( ConfigError::invalid(&key, field, &format!("Must be one of: {}", fields.join(", "))) );
+                    continue
+                };
+                match range.as_slice() {
+                    [] => { ranges.remove( &(device, *field) ); },
+                    [min, max] if min <= max => { ranges.insert( (device, *field), (*min, *max) ); },
+                    _ => errors.push( ConfigError::invalid(&key, format!("{:?}", range), "Must be [ min, max ] with min <= max, or [] for no range.") ),
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok( Self { quarantine: conf.action == "quarantine", ranges } )
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn check(&self, data: &mut Pvs6DevicesResponse) -> Vec<OutOfRange> {
+        // out of range values of a poll's readings.  Quarantined values are set to None
+        let mut out_of_range = Vec::new();
+        let mut devices = vec![
+            ( "production_meter", data.prod_meter.serial.clone(), data.prod_meter.data_time, production_meter_values(&mut data.prod_meter) ),
+            ( "consumption_meter", data.cons_meter.serial.clone(), data.cons_meter.data_time, consumption_meter_values(&mut data.cons_meter) ),
+        ];
+        for inv in data.inverters.iter_mut() {
+            devices.push( ( "inverter", inv.serial.clone(), inv.data_time, inverter_values(inv) ) );
+        }
+        for (device, serial, data_time, values) in devices {
+            for (field, value) in values {
+                let Some(reading) = *value else { continue };
+                let (min, max) = self.ranges.get( &(device, field) ).copied().unwrap_or( (f64::NEG_INFINITY, f64::INFINITY) );
+                if reading.is_finite() && reading >= min && reading <= max {
+                    continue
+                }
+                out_of_range.push( OutOfRange { device, serial: serial.clone(), data_time, field, value: reading, min, max } );
+                if self.quarantine {
+                    *value = None;
+                }
+            }
+        }
+        out_of_range
+    }
+
+    pub fn quarantine(&self) -> bool {
+        self.quarantine
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OutOfRange {
+    pub device: &'static str,
+    pub serial: String,
+    pub data_time: Option<DateTime<Utc>>,
+    pub field: &'static str,
+    pub value: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+// FUNCTIONS
+
+fn production_meter_values(pm: &mut ProductionMeter) -> Vec<(&'static str, &mut Option<f64>)> {
+    // same order as PRODUCTION_METER_FIELDS
+    PRODUCTION_METER_FIELDS.into_iter().zip([ &mut pm.freq_hz, &mut pm.i_a, &mut pm.net_ltea_3phsum_kwh, &mut pm.p_3phsum_kw,
+        &mut pm.q_3phsum_kvar, &mut pm.s_3phsum_kva, &mut pm.tot_pf_rto, &mut pm.v12_v ]).collect()
+}
+
+fn consumption_meter_values(cm: &mut ConsumptionMeter) -> Vec<(&'static str, &mut Option<f64>)> {
+    // same order as CONSUMPTION_METER_FIELDS
+    CONSUMPTION_METER_FIELDS.into_iter().zip([ &mut cm.freq_hz, &mut cm.i1_a, &mut cm.i2_a, &mut cm.neg_ltea_3phsum_kwh,
+        &mut cm.net_ltea_3phsum_kwh, &mut cm.p_3phsum_kw, &mut cm.p1_kw, &mut cm.p2_kw, &mut cm.pos_ltea_3phsum_kwh,
+        &mut cm.q_3phsum_kvar, &mut cm.s_3phsum_kva, &mut cm.tot_pf_rto, &mut cm.v12_v, &mut cm.v1n_v, &mut cm.v2n_v ]).collect()
+}
+
+fn inverter_values(inv: &mut Inverter) -> Vec<(&'static str, &mut Option<f64>)> {
+    // same order as INVERTER_FIELDS
+    INVERTER_FIELDS.into_iter().zip([ &mut inv.freq_hz, &mut inv.i_3phsum_a, &mut inv.i_mppt1_a, &mut inv.ltea_3phsum_kwh,
+        &mut inv.p_3phsum_kw, &mut inv.p_mppt1_kw, &mut inv.stat_ind, &mut inv.t_htsnk_degc, &mut inv.v_mppt1_v,
+        &mut inv.vln_3phavg_v ]).collect()
+}
+
+pub fn out_of_range_counts(out_of_range: &[OutOfRange]) -> BTreeMap<String, u64> {
+    // count per "device.field"
+    let mut counts: BTreeMap<String, u64> = BTreeMap::new();
+    for value in out_of_range {
+        *counts.entry( format!("{}.{}", value.device, value.field) ).or_insert(0) += 1;
+    }
+    counts
+}
+
+pub async fn insert_quarantined_readings_to_mysql(out_of_range: &[OutOfRange], plausibility: &Plausibility,
+    sql_pool_opt: &Option<sqlx::Pool<sqlx::MySql>>) {
+    // logs out of range values and, with quarantine action, uploads them to quarantined_readings table
+    if out_of_range.is_empty() {
+        return
+    }
+    let values: Vec<String> = out_of_range.iter().map( |value| format!("{} {} {} = {} (range {} to {})", value.device, value.serial,
+        value.field, value.value, value.min, value.max) ).collect();
+    warn!("Plausibility: {} value(s) out of range{}: {}", out_of_range.len(),
+        if plausibility.quarantine() { " quarantined" } else { "" }, values.join(", "));
+    if !plausibility.quarantine() {
+        return
+    }
+    let Some(sql_pool) = sql_pool_opt else {
+        error!("Couldn't get sql pool");
+        return
+    };
+    for value in out_of_range {
+        // NaN / inf can't be stored in DOUBLE columns, stored as NULL
+        let quarantine_result = sqlx::query(INSERT_QUARANTINED_READING_QUERY)
+            .bind(&value.serial)
+            .bind(value.data_time)
+            .bind(value.device)
+            .bind(value.field)
+            .bind( Some(value.value).filter( |reading| reading.is_finite() ) )
+            .bind( Some(value.min).filter( |min| min.is_finite() ) )
+            .bind( Some(value.max).filter( |max| max.is_finite() ) )
+            .execute(sql_pool).await;
+
+        match quarantine_result {
+            Ok(_) => debug!("Quarantined reading: {} {} @ {:?} uploaded to Mysql solar database", value.serial, value.field, value.data_time),
+            Err(quarantine_eff) => error!("Quarantined reading: {} {} @ {:?} failed to upload to Mysql solar database. Error: {}",
+                value.serial, value.field, value.data_time, quarantine_eff),
+        }
+    }
+}
+
+pub fn verify_plausibility_conf(plausibility_conf: &PlausibilityConf, errors: &mut Vec<ConfigError>) {
+    // verifies action, fields and ranges.  Adds a ConfigError to errors for each problem
+    if let Err(plausibility_errors) = Plausibility::from_conf(plausibility_conf) {
+        errors.extend(plausibility_errors);
+    }
+}
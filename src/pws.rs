@@ -0,0 +1,365 @@
+/*
+Personal weather station on site (pws section of config.yml).  Measured solar radiation, temperature and wind, for a
+performance ratio from what the array actually received rather than from modelled weather.
+    push    station uploads to bind.  Ecowitt "customized" upload with the Ecowitt or Wunderground protocol (imperial units).
+            Any path is accepted, eg Ecowitt "/data/report/" or Wunderground "/weatherstation/updateweatherstation.php".
+    poll    url is read every interval.  Each setting in fields is a JSON pointer to the value in the response, as wx_station.
+Readings are kept in memory until the next new production meter reading, then averaged and stored in pws_data with the
+production meter data_time, so each row lines up with the PVS6 readings of that poll.  If no reading arrived since the last
+poll, the last reading is used while it is newer than max_age.
+Values are stored in W/m², °C and m/s.  Humidity is a fraction 0 to 1.  Push readings are timed when received.
+Measured solar radiation is also used for measured_performance_ratio in production_performance (see performance.rs).
+*/
+
+// USE STATEMENTS
+    use std::{ collections::{ BTreeMap, HashMap }, sync::{ Arc, Mutex, PoisonError } };
+    use axum::{ Form, Router, extract::State, http::StatusCode };
+    use chrono::{ DateTime, TimeDelta, Utc };
+    use log::{ debug, error, info, warn };
+    use serde::Deserialize;
+
+    use crate::{ scheduler::Job, shutdown::Shutdown, validation::ConfigError,
+        weather::{ self, WX_UNITS, default_string, default_wx_interval_unit, default_wx_offset } };
+
+// CONSTANTS
+    // reading values a polled station can report.  Keys of pws fields
+    pub const PWS_FIELDS: [&str; 8] = [ "time", "solar_radiation", "temperature", "humidity", "wind_speed", "wind_gust",
+        "wind_bearing", "uv_index" ];
+    const PWS_MODES: [&str; 2] = [ "push", "poll" ];
+    // Wunderground protocol value for no reading
+    const NO_READING: f64 = -9999.0;
+    const METERS_PER_SECOND_PER_MPH: f64 = 0.44704;
+    const INSERT_PWS_DATA_QUERY: &str =
+    r#"
+        INSERT INTO pws_data
+            ( data_time, samples, first_reading_time, last_reading_time, solar_radiation_w_m2, temperature_c, humidity,
+                wind_speed_m_s, wind_gust_m_s, wind_bearing, uv_index )
+            VALUES ( ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ? )
+    "#;
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+#[derive(Debug, Deserialize, Clone )]
+pub struct PwsConf {
+    // "push" (station uploads to bind) or "poll" (url is read every interval)
+    #[serde( default = "default_pws_mode" )]
+    pub mode: String,
+    // push: address to listen on for station uploads
+    #[serde( default = "default_pws_bind" )]
+    pub bind: String,
+    // push: only uploads with this PASSKEY (Ecowitt) or ID (Wunderground) are stored.  Empty accepts any
+    #[serde( default = "default_string" )]
+    pub passkey: String,
+    // poll: station JSON url, eg "http://192.168.1.20/get_livedata_info"
+    #[serde( default = "default_string" )]
+    pub url: String,
+    // poll: reading value (PWS_FIELDS) to JSON pointer of value in station response.  time is unix seconds (default now)
+    #[serde( default )]
+    pub fields: BTreeMap<String, String>,
+    // poll: units of station values ("us", "ca", "uk" or "si", as weather units).  Solar radiation is always W/m²
+    #[serde( default = "default_pws_units" )]
+    pub units: String,
+    #[serde( default = "default_pws_interval" )]
+    pub interval: u64,
+    #[serde( default = "default_wx_interval_unit" )]
+    pub interval_unit: char,
+    #[serde( with = "crate::integer_to_chrono_time_delta_ms", default = "default_wx_offset" )]
+    pub offset: TimeDelta,
+    // cron expression or "every N<unit> [at <offset>]".  Used instead of interval / interval_unit / offset if provided
+    #[serde( default = "default_string" )]
+    pub schedule: String,
+    #[serde( with = "crate::integer_to_chrono_time_delta_ms", default = "default_wx_offset" )]
+    pub jitter: TimeDelta,
+    // readings older than this (minutes) at a production meter reading aren't stored with it
+    #[serde( with = "crate::integer_to_chrono_time_delta_minutes", default = "default_pws_max_age" )]
+    pub max_age: TimeDelta,
+}
+fn default_pws_mode() -> String {
+    "push".to_string()
+}
+fn default_pws_bind() -> String {
+    "0.0.0.0:8090".to_string()
+}
+fn default_pws_units() -> String {
+    "us".to_string()
+}
+fn default_pws_interval() -> u64 {
+    1
+}
+fn default_pws_max_age() -> TimeDelta {
+    TimeDelta::minutes(10)
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PwsReading {
+    pub time: DateTime<Utc>,
+    pub solar_radiation_w_m2: Option<f64>,
+    pub temperature_c: Option<f64>,
+    pub humidity: Option<f64>,
+    pub wind_speed_m_s: Option<f64>,
+    pub wind_gust_m_s: Option<f64>,
+    pub wind_bearing: Option<f64>,
+    pub uv_index: Option<f64>,
+}
+
+// readings averaged for one production meter reading.  reading.time is the last reading time
+#[derive(Clone, Copy, Debug)]
+pub struct PwsData {
+    pub samples: usize,
+    pub first_reading_time: DateTime<Utc>,
+    pub reading: PwsReading,
+}
+
+// readings waiting for the next production meter reading, shared by the pws task and the pvs6 poll loop
+#[derive(Clone, Debug, Default)]
+pub struct PwsReadings {
+    buffer: Arc<Mutex<PwsBuffer>>,
+}
+#[derive(Debug, Default)]
+struct PwsBuffer {
+    max_age: TimeDelta,
+    new: Vec<PwsReading>,
+    last: Option<PwsReading>,
+}
+impl PwsReadings {
+    fn start(&self, max_age: TimeDelta) {
+        // readings from before a restart (eg config change) are dropped, as they may be from another station
+        let mut buffer = self.buffer.lock().unwrap_or_else(PoisonError::into_inner);
+        *buffer = PwsBuffer { max_age, ..Default::default() };
+    }
+
+    fn push(&self, reading: PwsReading) {
+        // readings past max_age are dropped, so the buffer doesn't grow while PVS6 isn't polled
+        let mut buffer = self.buffer.lock().unwrap_or_else(PoisonError::into_inner);
+        let max_age = buffer.max_age;
+        buffer.new.retain( |buffered| reading.time - buffered.time <= max_age );
+        buffer.new.push(reading);
+    }
+
+    pub fn align(&self, data_time: DateTime<Utc>) -> Option<PwsData> {
+        // average of readings since the last call, or the last reading if none arrived.  None if there are no readings
+        // newer than max_age.
+        let mut buffer = self.buffer.lock().unwrap_or_else(PoisonError::into_inner);
+        let new = std::mem::take(&mut buffer.new);
+        if let Some(newest) = new.iter().max_by_key( |reading| reading.time ) {
+            buffer.last = Some(*newest);
+        }
+        let max_age = buffer.max_age;
+        let fresh = |reading: &PwsReading| data_time - reading.time <= max_age;
+        let mut readings: Vec<PwsReading> = new.into_iter().filter(fresh).collect();
+        if readings.is_empty() {
+            readings.extend( buffer.last.filter(fresh) );
+        }
+        average(&readings)
+    }
+}
+
+#[derive(Clone)]
+struct PushState {
+    passkey: String,
+    readings: PwsReadings,
+}
+
+// conversions of polled station values to stored units
+struct PwsUnits {
+    fahrenheit: bool,
+    wind_speed_scale: f64,
+}
+impl PwsUnits {
+    fn new(units: &str) -> Self {
+        // units verified at startup by verify_pws_conf
+        let wind_speed_scale = match units {
+            "us" | "uk" => METERS_PER_SECOND_PER_MPH,
+            "ca" => 1.0 / 3.6,
+            _ => 1.0,
+        };
+        Self { fahrenheit: units == "us", wind_speed_scale }
+    }
+
+    fn temperature(&self, temperature: f64) -> f64 {
+        if self.fahrenheit { fahrenheit_to_celsius(temperature) } else { temperature }
+    }
+}
+
+// FUNCTIONS
+
+pub async fn receive_pws_push(pws_conf: PwsConf, readings: PwsReadings, stop: Shutdown) {
+    // bind verified at startup by verify_pws_conf
+    readings.start(pws_conf.max_age);
+    let state = PushState { passkey: pws_conf.passkey, readings };
+    let router = Router::new().fallback(receive_upload).with_state(state);
+
+    let listener = match tokio::net::TcpListener::bind(&pws_conf.bind).await {
+        Ok(listener) => listener,
+        Err(bind_eff) => {
+            error!("Personal weather station receiver couldn't listen on {}. Error: {}", pws_conf.bind, bind_eff);
+            return
+        },
+    };
+    info!("Personal weather station receiver listening on {}", pws_conf.bind);
+    let serve = axum::serve(listener, router).with_graceful_shutdown( async move { stop.wait().await } );
+    if let Err(serve_eff) = serve.await {
+        error!("Personal weather station receiver stopped. Error: {}", serve_eff);
+    }
+}
+
+async fn receive_upload(State(state): State<PushState>, Form(upload): Form<HashMap<String, String>>) -> (StatusCode, &'static str) {
+    // Ecowitt posts a form, Wunderground sends a GET query.  Form reads either.
+    let station = upload.get("PASSKEY").or_else( || upload.get("ID") );
+    if !state.passkey.is_empty() && station != Some(&state.passkey) {
+        warn!("Personal weather station upload with unknown PASSKEY / ID ignored.");
+        return ( StatusCode::UNAUTHORIZED, "unauthorized\n" )
+    }
+    let reading = upload_reading(&upload);
+    debug!("Personal weather station upload: {:?}", reading);
+    state.readings.push(reading);
+    // Wunderground protocol stations expect "success"
+    ( StatusCode::OK, "success\n" )
+}
+
+fn upload_reading(upload: &HashMap<String, String>) -> PwsReading {
+    // Ecowitt and Wunderground protocol fields are the same, except Wunderground UV
+    let number = |keys: &[&str]| keys.iter()
+        .find_map( |key| upload.get(*key)?.trim().parse::<f64>().ok() )
+        .filter( |value| *value != NO_READING );
+    PwsReading {
+        time: Utc::now(),
+        solar_radiation_w_m2: number(&["solarradiation"]),
+        temperature_c: number(&["tempf"]).map(fahrenheit_to_celsius),
+        humidity: number(&["humidity"]).map(percent),
+        wind_speed_m_s: number(&["windspeedmph"]).map( |speed| speed * METERS_PER_SECOND_PER_MPH ),
+        wind_gust_m_s: number(&["windgustmph"]).map( |speed| speed * METERS_PER_SECOND_PER_MPH ),
+        wind_bearing: number(&["winddir"]),
+        uv_index: number(&["uv", "UV"]),
+    }
+}
+
+pub async fn poll_pws(pws_conf: PwsConf, readings: PwsReadings, mut get_pws_job: Job) {
+    readings.start(pws_conf.max_age);
+    let units = PwsUnits::new(&pws_conf.units);
+    info!("Personal weather station polling {}", pws_conf.url);
+    while get_pws_job.tick().await.is_some() {
+        let Some(station) = weather::get_station_json( &pws_conf.url, "Personal weather station" ).await else {
+            continue
+        };
+        let value = |field: &str| weather::json_number( &station, pws_conf.fields.get(field)? );
+        let missing: Vec<&String> = pws_conf.fields.keys().filter( |field| value(field).is_none() ).collect();
+        if !missing.is_empty() {
+            debug!("Personal weather station response has no number for {:?}", missing);
+        }
+        let reading = PwsReading {
+            time: value("time").and_then( |time| DateTime::from_timestamp(time as i64, 0) ).unwrap_or( Utc::now() ),
+            solar_radiation_w_m2: value("solar_radiation"),
+            temperature_c: value("temperature").map( |temperature| units.temperature(temperature) ),
+            humidity: value("humidity").map(percent),
+            wind_speed_m_s: value("wind_speed").map( |speed| speed * units.wind_speed_scale ),
+            wind_gust_m_s: value("wind_gust").map( |speed| speed * units.wind_speed_scale ),
+            wind_bearing: value("wind_bearing"),
+            uv_index: value("uv_index"),
+        };
+        debug!("Personal weather station reading: {:?}", reading);
+        readings.push(reading);
+    }
+}
+
+fn average(readings: &[PwsReading]) -> Option<PwsData> {
+    // mean of each value over the readings that have it.  Wind bearing is the mean direction
+    let first_reading_time = readings.iter().map( |reading| reading.time ).min()?;
+    let last_reading_time = readings.iter().map( |reading| reading.time ).max()?;
+    let mean = |value: fn(&PwsReading) -> Option<f64>| {
+        let values: Vec<f64> = readings.iter().filter_map(value).collect();
+        ( !values.is_empty() ).then( || values.iter().sum::<f64>() / values.len() as f64 )
+    };
+    let bearings: Vec<f64> = readings.iter().filter_map( |reading| reading.wind_bearing.map(f64::to_radians) ).collect();
+    let wind_bearing = ( !bearings.is_empty() ).then( || {
+        let sin: f64 = bearings.iter().map( |bearing| bearing.sin() ).sum();
+        let cos: f64 = bearings.iter().map( |bearing| bearing.cos() ).sum();
+        sin.atan2(cos).to_degrees().rem_euclid(360.0)
+    });
+    Some( PwsData {
+        samples: readings.len(),
+        first_reading_time,
+        reading: PwsReading {
+            time: last_reading_time,
+            solar_radiation_w_m2: mean( |reading| reading.solar_radiation_w_m2 ),
+            temperature_c: mean( |reading| reading.temperature_c ),
+            humidity: mean( |reading| reading.humidity ),
+            wind_speed_m_s: mean( |reading| reading.wind_speed_m_s ),
+            wind_gust_m_s: mean( |reading| reading.wind_gust_m_s ),
+            wind_bearing,
+            uv_index: mean( |reading| reading.uv_index ),
+        },
+    })
+}
+
+fn fahrenheit_to_celsius(temperature: f64) -> f64 {
+    ( temperature - 32.0 ) * 5.0 / 9.0
+}
+
+fn percent(value: f64) -> f64 {
+    value / 100.0
+}
+
+pub async fn insert_pws_data_to_mysql(data_time: DateTime<Utc>, pws_data: &PwsData, sql_pool_opt: &Option<sqlx::Pool<sqlx::MySql>>) {
+    // uploads station readings aligned to production meter reading at data_time to pws_data table
+    let Some(sql_pool) = sql_pool_opt else {
+        error!("Couldn't get sql pool");
+        return
+    };
+    let reading = &pws_data.reading;
+    let pws_result = sqlx::query(INSERT_PWS_DATA_QUERY)
+        .bind(data_time)
+        .bind(pws_data.samples as u32)
+        .bind(pws_data.first_reading_time)
+        .bind(reading.time)
+        .bind(reading.solar_radiation_w_m2)
+        .bind(reading.temperature_c)
+        .bind(reading.humidity)
+        .bind(reading.wind_speed_m_s)
+        .bind(reading.wind_gust_m_s)
+        .bind(reading.wind_bearing)
+        .bind(reading.uv_index)
+        .execute(sql_pool).await;
+
+    match pws_result {
+        Ok(_) => debug!("Personal weather station data @ {} ({} readings) uploaded to Mysql solar database",
+            data_time.format("%Y-%m-%d %H:%M:%S"), pws_data.samples),
+        Err(pws_eff) => error!("Personal weather station data @ {} failed to upload to Mysql solar database. Error: {}",
+            data_time.format("%Y-%m-%d %H:%M:%S"), pws_eff),
+    }
+}
+
+pub fn verify_pws_conf(pws_conf: &PwsConf, errors: &mut Vec<ConfigError>) {
+    // verifies mode and the settings it uses.  Adds a ConfigError to errors for each problem
+    match pws_conf.mode.as_str() {
+        "push" => {
+            if pws_conf.bind.parse::<std::net::SocketAddr>().is_err() {
+                errors.push( ConfigError::invalid("pws.bind", &pws_conf.bind, "Must be an address and port, eg \"0.0.0.0:8090\".") );
+            }
+        },
+        "poll" => {
+            if !reqwest::Url::parse(&pws_conf.url).is_ok_and( |url| matches!( url.scheme(), "http" | "https" ) ) {
+                errors.push( ConfigError::invalid("pws.url", &pws_conf.url, "Must be an http:// url of the station's JSON.") );
+            }
+            if !pws_conf.fields.contains_key("solar_radiation") {
+                errors.push( ConfigError::missing("pws.fields.solar_radiation", "Map the station's solar radiation (W/m²), eg solar_radiation: \"/solar/radiation\".") );
+            }
+            for (field, pointer) in &pws_conf.fields {
+                let key = format!("pws.fields.{}", field);
+                if !PWS_FIELDS.contains( &field.as_str() ) {
+                    errors.push( ConfigError::invalid( &key, field, &format!("Must be one of: {}", PWS_FIELDS.join(", ")) ) );
+                } else if !pointer.starts_with('/') {
+                    errors.push( ConfigError::invalid( &key, pointer, "Must be a JSON pointer, eg \"/solar/radiation\"." ) );
+                }
+            }
+            if !WX_UNITS.contains( &pws_conf.units.as_str() ) {
+                errors.push( ConfigError::invalid("pws.units", &pws_conf.units, "Must be 'us', 'ca', 'uk' or 'si'.") );
+            }
+            weather::verify_schedule("pws", &pws_conf.schedule, pws_conf.interval, pws_conf.interval_unit, pws_conf.offset, errors);
+        },
+        _ => errors.push( ConfigError::invalid("pws.mode", &pws_conf.mode, &format!("Must be one of: {}", PWS_MODES.join(", "))) ),
+    }
+    if pws_conf.max_age <= TimeDelta::zero() {
+        errors.push( ConfigError::invalid("pws.max_age", pws_conf.max_age.num_minutes(), "Must be at least 1 minute.") );
+    }
+}
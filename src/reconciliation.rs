@@ -0,0 +1,258 @@
+/*
+Energy reconciliation (reconciliation section of config.yml).  Scheduled job that checks the PVS6 lifetime energy counters agree
+with each other for each local day:
+1)  production  sum of inverter ltea_3phsum_kwh against the production meter net_ltea_3phsum_kwh.  A difference usually means
+                dropped inverter data (an inverter not reporting) or a production CT problem.
+2)  consumption consumption meter pos_ltea_3phsum_kwh - neg_ltea_3phsum_kwh against its net_ltea_3phsum_kwh.  A difference
+                usually means a consumption CT problem.
+Day energy is from the meter reads (last reading at or before local midnight) at each end of the day, so short gaps in data
+don't count as a difference.  Net counters go down while exporting, so their signed change is used.  A day is flagged when the
+difference is more than tolerance_kwh and more than tolerance_pct of the larger total (production) or of import + export
+(consumption).  Results go to energy_reconciliation, flagged days are logged as warnings.
+Each run checks the last days (setting) complete local days, up to yesterday.
+*/
+
+// USE STATEMENTS
+    use std::collections::BTreeMap;
+    use chrono::{ DateTime, NaiveDate, TimeDelta, Utc };
+    use chrono_tz::Tz;
+    use log::{ error, info, warn };
+    use serde::Deserialize;
+
+    use crate::{ energy, scheduler::Job, validation::ConfigError, weather::verify_schedule };
+
+// CONSTANTS
+    // longest time before local midnight a counter reading is used as the meter read at midnight
+    const METER_READ_LOOKBACK: TimeDelta = TimeDelta::days(1);
+    const REPLACE_ENERGY_RECONCILIATION_QUERY: &str =
+    r#"
+        REPLACE INTO energy_reconciliation
+            ( date, inverters, inverter_kwh, production_kwh, production_difference_kwh, production_flagged, import_kwh, export_kwh,
+                net_kwh, consumption_difference_kwh, consumption_flagged, calc_time )
+            VALUES ( ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ? )
+    "#;
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+#[derive(Debug, Deserialize, Clone )]
+pub struct ReconciliationConf {
+    // timezone of the days checked, eg "America/Los_Angeles"
+    #[serde( default = "default_reconciliation_timezone" )]
+    pub timezone: String,
+    // differences up to the larger of tolerance_kwh and tolerance_pct (percent of the day's total) aren't flagged
+    #[serde( default = "default_reconciliation_tolerance_pct" )]
+    pub tolerance_pct: f64,
+    #[serde( default = "default_reconciliation_tolerance_kwh" )]
+    pub tolerance_kwh: f64,
+    #[serde( default = "default_reconciliation_interval" )]
+    pub interval: u64,
+    #[serde( default = "default_reconciliation_interval_unit" )]
+    pub interval_unit: char,
+    #[serde( with = "crate::integer_to_chrono_time_delta_ms", default = "default_reconciliation_offset" )]
+    pub offset: TimeDelta,
+    // cron expression or "every N<unit> [at <offset>]".  Used instead of interval / interval_unit / offset if provided
+    #[serde( default = "default_reconciliation_schedule" )]
+    pub schedule: String,
+    #[serde( with = "crate::integer_to_chrono_time_delta_ms", default = "default_reconciliation_jitter" )]
+    pub jitter: TimeDelta,
+    // complete days checked each run.  Set higher once to check history
+    #[serde( default = "default_reconciliation_days" )]
+    pub days: i64,
+}
+fn default_reconciliation_timezone() -> String {
+    "UTC".to_string()
+}
+fn default_reconciliation_tolerance_pct() -> f64 {
+    5.0
+}
+fn default_reconciliation_tolerance_kwh() -> f64 {
+    0.5
+}
+fn default_reconciliation_interval() -> u64 {
+    1
+}
+fn default_reconciliation_interval_unit() -> char {
+    'd'
+}
+fn default_reconciliation_offset() -> TimeDelta {
+    TimeDelta::hours(1)
+}
+fn default_reconciliation_schedule() -> String {
+    String::new()
+}
+fn default_reconciliation_jitter() -> TimeDelta {
+    TimeDelta::zero()
+}
+fn default_reconciliation_days() -> i64 {
+    1
+}
+
+// energy::meter_read_energy or energy::counter_change
+type DeviceEnergy = fn(&[(DateTime<Utc>, f64)], DateTime<Utc>, DateTime<Utc>) -> Option<f64>;
+
+// one day's totals.  None where a device type has no readings
+#[derive(Clone, Debug, Default)]
+pub struct DayReconciliation {
+    pub date: NaiveDate,
+    pub inverters: u32,
+    pub inverter_kwh: Option<f64>,
+    pub production_kwh: Option<f64>,
+    pub import_kwh: Option<f64>,
+    pub export_kwh: Option<f64>,
+    pub net_kwh: Option<f64>,
+}
+impl DayReconciliation {
+    pub fn production_difference_kwh(&self) -> Option<f64> {
+        Some( self.inverter_kwh? - self.production_kwh? )
+    }
+    pub fn consumption_difference_kwh(&self) -> Option<f64> {
+        Some( self.import_kwh? - self.export_kwh? - self.net_kwh? )
+    }
+    pub fn production_flagged(&self, conf: &ReconciliationConf) -> Option<bool> {
+        let total = self.inverter_kwh?.abs().max( self.production_kwh?.abs() );
+        Some( beyond_tolerance(self.production_difference_kwh()?, total, conf) )
+    }
+    pub fn consumption_flagged(&self, conf: &ReconciliationConf) -> Option<bool> {
+        let total = self.import_kwh? + self.export_kwh?;
+        Some( beyond_tolerance(self.consumption_difference_kwh()?, total, conf) )
+    }
+}
+
+// FUNCTIONS
+
+pub async fn reconciliation_to_mysql(solar_pool: Option<sqlx::Pool<sqlx::MySql>>, reconciliation_conf: ReconciliationConf,
+    mut reconciliation_job: Job) {
+    // timezone verified at startup by verify_reconciliation_conf
+    let Ok(tz) = reconciliation_conf.timezone.parse::<Tz>() else {
+        error!("Reconciliation not started. Unknown timezone: {}", reconciliation_conf.timezone);
+        return
+    };
+    while reconciliation_job.tick().await.is_some() {
+        match &solar_pool {
+            Some(sql_pool) => {
+                let now = Utc::now();
+                let today = now.with_timezone(&tz).date_naive();
+                reconcile_days(sql_pool, &reconciliation_conf, tz, today - TimeDelta::days(reconciliation_conf.days), today, now).await;
+            },
+            None => error!("Couldn't get sql pool"),
+        }
+    }
+}
+
+async fn reconcile_days(sql_pool: &sqlx::Pool<sqlx::MySql>, conf: &ReconciliationConf, tz: Tz, from: NaiveDate, to: NaiveDate,
+    now: DateTime<Utc>) {
+    // checks local days from from up to (not including) to
+    let start = energy::local_midnight_utc(from, tz);
+    let end = energy::local_midnight_utc(to, tz);
+    let mut counters = Vec::new();
+    for (table, counter) in [
+        ("inverters_data", "ltea_3phsum_kwh"),
+        ("production_meters_data", "net_ltea_3phsum_kwh"),
+        ("consumption_meters_data", "pos_ltea_3phsum_kwh"),
+        ("consumption_meters_data", "neg_ltea_3phsum_kwh"),
+        ("consumption_meters_data", "net_ltea_3phsum_kwh"),
+    ] {
+        match energy::get_counter_readings(sql_pool, table, counter, start - METER_READ_LOOKBACK, end).await {
+            Ok(readings) => counters.push(readings),
+            Err(read_eff) => {
+                error!("Reconciliation: couldn't read {} {} from {}. Error: {}", table, counter, start, read_eff);
+                return
+            },
+        }
+    }
+
+    let mut flagged = 0;
+    let mut failed = 0;
+    let mut date = from;
+    while date < to {
+        let day_start = energy::local_midnight_utc(date, tz);
+        let day_end = energy::local_midnight_utc(date + TimeDelta::days(1), tz);
+        let inverter_energy: Vec<f64> = counters[0].values()
+            .filter_map( |readings| energy::meter_read_energy(readings, day_start, day_end) ).collect();
+        let day = DayReconciliation {
+            date,
+            inverters: inverter_energy.len() as u32,
+            inverter_kwh: if inverter_energy.is_empty() { None } else { Some( inverter_energy.iter().sum() ) },
+            production_kwh: total(&counters[1], day_start, day_end, energy::counter_change),
+            import_kwh: total(&counters[2], day_start, day_end, energy::meter_read_energy),
+            export_kwh: total(&counters[3], day_start, day_end, energy::meter_read_energy),
+            net_kwh: total(&counters[4], day_start, day_end, energy::counter_change),
+        };
+        if warn_flagged(&day, conf) {
+            flagged += 1;
+        }
+        if let Err(replace_eff) = replace_energy_reconciliation(sql_pool, &day, conf, now).await {
+            error!("Reconciliation: couldn't write {} to Mysql solar db. Error: {}", date, replace_eff);
+            failed += 1;
+        }
+        date += TimeDelta::days(1);
+    }
+    info!("Reconciliation: {} days from {} checked, {} flagged, {} failed to write.", ( to - from ).num_days(), from, flagged, failed);
+}
+
+fn total(readings: &BTreeMap<String, Vec<(DateTime<Utc>, f64)>>, start: DateTime<Utc>, end: DateTime<Utc>,
+    device_energy: DeviceEnergy) -> Option<f64> {
+    // device_energy summed over all devices.  None if no device has readings
+    readings.values().filter_map( |device_readings| device_energy(device_readings, start, end) )
+        .fold( None, |total, kwh| Some( total.unwrap_or(0.0) + kwh ) )
+}
+
+fn beyond_tolerance(difference: f64, total: f64, conf: &ReconciliationConf) -> bool {
+    difference.abs() > conf.tolerance_kwh.max( total * conf.tolerance_pct / 100.0 )
+}
+
+fn warn_flagged(day: &DayReconciliation, conf: &ReconciliationConf) -> bool {
+    // logs a warning for each flagged check.  Returns true if the day is flagged
+    let mut flagged = false;
+    if day.production_flagged(conf) == Some(true) {
+        warn!("Reconciliation: {} inverters ({}) {:.2} kWh, production meter {:.2} kWh, difference {:.2} kWh.  Check for dropped \
+            inverter data or a production CT problem.", day.date, day.inverters, day.inverter_kwh.unwrap_or_default(),
+            day.production_kwh.unwrap_or_default(), day.production_difference_kwh().unwrap_or_default());
+        flagged = true;
+    }
+    if day.consumption_flagged(conf) == Some(true) {
+        warn!("Reconciliation: {} consumption meter import {:.2} kWh - export {:.2} kWh, net {:.2} kWh, difference {:.2} kWh.  \
+            Check for a consumption CT problem.", day.date, day.import_kwh.unwrap_or_default(), day.export_kwh.unwrap_or_default(),
+            day.net_kwh.unwrap_or_default(), day.consumption_difference_kwh().unwrap_or_default());
+        flagged = true;
+    }
+    flagged
+}
+
+async fn replace_energy_reconciliation(sql_pool: &sqlx::Pool<sqlx::MySql>, day: &DayReconciliation, conf: &ReconciliationConf,
+    calc_time: DateTime<Utc>) -> Result<(), sqlx::Error> {
+    sqlx::query(REPLACE_ENERGY_RECONCILIATION_QUERY)
+        .bind(day.date)
+        .bind(day.inverters)
+        .bind(day.inverter_kwh)
+        .bind(day.production_kwh)
+        .bind(day.production_difference_kwh())
+        .bind(day.production_flagged(conf))
+        .bind(day.import_kwh)
+        .bind(day.export_kwh)
+        .bind(day.net_kwh)
+        .bind(day.consumption_difference_kwh())
+        .bind(day.consumption_flagged(conf))
+        .bind(calc_time)
+        .execute(sql_pool).await?;
+    Ok(())
+}
+
+pub fn verify_reconciliation_conf(reconciliation_conf: &ReconciliationConf, errors: &mut Vec<ConfigError>) {
+    // verifies timezone, tolerances, schedule and days.  Adds a ConfigError to errors for each problem
+    if reconciliation_conf.timezone.parse::<Tz>().is_err() {
+        errors.push( ConfigError::invalid("reconciliation.timezone", &reconciliation_conf.timezone,
+            "Must be a valid timezone (eg America/Los_Angeles).") );
+    }
+    if reconciliation_conf.tolerance_pct < 0.0 {
+        errors.push( ConfigError::invalid("reconciliation.tolerance_pct", reconciliation_conf.tolerance_pct, "Must not be negative.") );
+    }
+    if reconciliation_conf.tolerance_kwh < 0.0 {
+        errors.push( ConfigError::invalid("reconciliation.tolerance_kwh", reconciliation_conf.tolerance_kwh, "Must not be negative.") );
+    }
+    verify_schedule("reconciliation", &reconciliation_conf.schedule, reconciliation_conf.interval, reconciliation_conf.interval_unit,
+        reconciliation_conf.offset, errors);
+    if reconciliation_conf.days < 1 {
+        errors.push( ConfigError::invalid("reconciliation.days", reconciliation_conf.days, "Must be at least 1.") );
+    }
+}
@@ -0,0 +1,135 @@
+/*
+Config reload.  config.yml is read again when the file changes (watched with notify) or on SIGHUP.
+Settings are flattened to "section.key" = value (eg "pvs6.get_device_interval" = "5") so a reload can log what changed and
+restart only the tasks whose settings changed.  Secret values (passwords, api keys, webhook urls, station passkeys) are logged as "***".
+*/
+
+// USE STATEMENTS
+    use std::{ collections::BTreeMap, path::Path, time::Duration };
+    use log::{ debug, error };
+    use notify::{ EventKind, RecommendedWatcher, RecursiveMode, Watcher };
+    use tokio::{ signal::unix::{ Signal, SignalKind, signal }, sync::mpsc, time::sleep };
+
+// CONSTANTS
+    // editors often write a file in several steps.  Changes within this time are read as one.
+    const DEBOUNCE: Duration = Duration::from_millis(500);
+    // settings (last part of key) logged as "***"
+    const SECRET_KEYS: [&str; 4] = [ "password", "api_key", "alert_webhook_url", "passkey" ];
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+pub struct ConfigWatcher {
+    // watcher stops when dropped
+    _watcher: Option<RecommendedWatcher>,
+    changes: mpsc::UnboundedReceiver<()>,
+    sighup: Option<Signal>,
+}
+
+impl ConfigWatcher {
+    pub fn new(config_name: &str) -> Self {
+        // watches the working directory for changes to config_name.* (config.yml).  The directory is watched, not the file, so
+        // editors that save by replacing the file are seen too.
+        let (sender, changes) = mpsc::unbounded_channel();
+        let name = config_name.to_string();
+        let watcher = notify::recommended_watcher( move |event: notify::Result<notify::Event>| {
+            if let Ok(event) = event && !matches!(event.kind, EventKind::Access(_))
+                && event.paths.iter().any( |path| path.file_stem().is_some_and( |stem| stem == name.as_str() ) ) {
+                let _ = sender.send(());
+            }
+        });
+        let watcher = match watcher.and_then( |mut watcher| watcher.watch( Path::new("."), RecursiveMode::NonRecursive ).map( |_| watcher ) ) {
+            Ok(watcher) => Some(watcher),
+            Err(watch_eff) => {
+                error!("Couldn't watch {} for changes.  Config reloads on SIGHUP only. Error: {}", config_name, watch_eff);
+                None
+            },
+        };
+        let sighup = match signal( SignalKind::hangup() ) {
+            Ok(sighup) => Some(sighup),
+            Err(signal_eff) => {
+                error!("Couldn't listen for SIGHUP. Error: {}", signal_eff);
+                None
+            },
+        };
+        Self { _watcher: watcher, changes, sighup }
+    }
+
+    pub async fn changed(&mut self) -> &'static str {
+        // waits for a config file change or SIGHUP and returns which it was
+        let reason = tokio::select! {
+            Some(()) = self.changes.recv() => "config file change",
+            Some(()) = recv_signal(&mut self.sighup) => "SIGHUP",
+            else => std::future::pending().await,
+        };
+        sleep(DEBOUNCE).await;
+        while self.changes.try_recv().is_ok() {}
+        debug!("Config reload triggered by {}", reason);
+        reason
+    }
+}
+
+// FUNCTIONS
+
+async fn recv_signal(signal: &mut Option<Signal>) -> Option<()> {
+    match signal {
+        Some(signal) => signal.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+pub fn flatten_settings(settings: &serde_json::Value) -> BTreeMap<String, String> {
+    // "section.key" = value for every setting.  Array items are numbered, eg "retention.tables.0.keep_days".
+    let mut flat = BTreeMap::new();
+    flatten_value("", settings, &mut flat);
+    flat
+}
+
+fn flatten_value(key: &str, value: &serde_json::Value, flat: &mut BTreeMap<String, String>) {
+    let child_key = |child: &str| if key.is_empty() { child.to_string() } else { format!("{}.{}", key, child) };
+    match value {
+        serde_json::Value::Object(map) if !map.is_empty() => {
+            for (child, child_value) in map {
+                flatten_value(&child_key(child), child_value, flat);
+            }
+        },
+        serde_json::Value::Array(items) if !items.is_empty() => {
+            for (index, item) in items.iter().enumerate() {
+                flatten_value(&child_key(&index.to_string()), item, flat);
+            }
+        },
+        serde_json::Value::String(string) => {
+            flat.insert( key.to_string(), string.clone() );
+        },
+        other => {
+            flat.insert( key.to_string(), other.to_string() );
+        },
+    }
+}
+
+pub fn changed_keys(old: &BTreeMap<String, String>, new: &BTreeMap<String, String>) -> Vec<String> {
+    // keys added, removed or changed
+    let mut keys: Vec<String> = old.iter().filter( |(key, value)| new.get(*key) != Some(*value) ).map( |(key, _)| key.clone() ).collect();
+    keys.extend( new.keys().filter( |key| !old.contains_key(*key) ).cloned() );
+    keys.sort();
+    keys
+}
+
+pub fn settings_diff(old: &BTreeMap<String, String>, new: &BTreeMap<String, String>) -> String {
+    // one line per changed key: "+ key = value", "- key = value" or "~ key: old -> new"
+    changed_keys(old, new).iter().map( |key| match ( old.get(key), new.get(key) ) {
+        (Some(old_value), Some(new_value)) => format!("  ~ {}: {} -> {}", key, shown(key, old_value), shown(key, new_value)),
+        (Some(old_value), None) => format!("  - {} = {}", key, shown(key, old_value)),
+        (None, Some(new_value)) => format!("  + {} = {}", key, shown(key, new_value)),
+        (None, None) => String::new(),
+    }).collect::<Vec<String>>().join("\n")
+}
+
+fn shown<'a>(key: &str, value: &'a str) -> &'a str {
+    let name = key.rsplit('.').next().unwrap_or(key);
+    if SECRET_KEYS.contains(&name) && !value.is_empty() { "***" } else { value }
+}
+
+pub fn key_in_sections(key: &str, sections: &[&str]) -> bool {
+    // true if key is one of sections or a setting under one, eg "pvs6.host" is in "pvs6"
+    sections.iter().any( |section| key == *section || key.strip_prefix(section).is_some_and( |rest| rest.starts_with('.') ) )
+}
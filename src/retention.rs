@@ -0,0 +1,371 @@
+/*
+Data retention.  Scheduled job that keeps raw rows for keep_days per table, then for rows older than that:
+1)  Downsamples (PVS6 meter and inverter tables only) to hourly rows in <table>_hourly: average of each reading, last reading of
+    each lifetime energy counter (with its time) and energy (kWh) produced in the hour from the counters.
+2)  Archives the raw rows to a file per table per day in archive_dir (gzip compressed CSV or Parquet).
+3)  Deletes the raw rows.
+Rows are processed one UTC day at a time, oldest first.  If downsampling or archiving a day fails, its rows are not deleted
+and the table is retried on the next run.
+*/
+
+// USE STATEMENTS
+    use std::path::PathBuf;
+    use chrono::{ DateTime, DurationRound, TimeDelta, Utc };
+    use log::{ debug, error, info, warn };
+    use serde::Deserialize;
+
+    use crate::{ archive::{ self, ArchiveFormat }, energy, scheduler::{ Job, Schedule }, validation::ConfigError };
+
+// CONSTANTS
+    // counter readings further apart than this are not used for hourly energy
+    const MAX_ENERGY_GAP: TimeDelta = TimeDelta::hours(1);
+    // tables retention can be configured for.  Table and column names are only ever taken from this list, never from config.
+    const RETAINED_TABLES: &[RetainedTable] = &[
+        RetainedTable { table: "supervisors_data", time_column: "data_time", average_columns: &[], counter_columns: &[] },
+        RetainedTable {
+            table: "production_meters_data", time_column: "data_time",
+            average_columns: &["freq_hz", "i_a", "p_3phsum_kw", "q_3phsum_kvar", "s_3phsum_kva", "tot_pf_rto", "v12_v"],
+            counter_columns: &["net_ltea_3phsum_kwh"],
+        },
+        RetainedTable {
+            table: "consumption_meters_data", time_column: "data_time",
+            average_columns: &["freq_hz", "i1_a", "i2_a", "p_3phsum_kw", "p1_kw", "p2_kw", "q_3phsum_kvar", "s_3phsum_kva", "tot_pf_rto",
+                "v12_v", "v1n_v", "v2n_v"],
+            counter_columns: &["pos_ltea_3phsum_kwh", "neg_ltea_3phsum_kwh", "net_ltea_3phsum_kwh"],
+        },
+        RetainedTable {
+            table: "inverters_data", time_column: "data_time",
+            average_columns: &["freq_hz", "i_3phsum_a", "i_mppt1_a", "p_3phsum_kw", "p_mppt1_kw", "t_htsnk_degc", "v_mppt1_v", "vln_3phavg_v"],
+            counter_columns: &["ltea_3phsum_kwh"],
+        },
+        RetainedTable { table: "production_performance", time_column: "data_time", average_columns: &[], counter_columns: &[] },
+        RetainedTable { table: "pws_data", time_column: "data_time", average_columns: &[], counter_columns: &[] },
+        RetainedTable { table: "pvs6_poll_timing", time_column: "scheduled_time", average_columns: &[], counter_columns: &[] },
+        RetainedTable { table: "tariff_interval", time_column: "time", average_columns: &[], counter_columns: &[] },
+        RetainedTable { table: "current_wx", time_column: "time", average_columns: &[], counter_columns: &[] },
+        RetainedTable { table: "hourly_wx_forecast", time_column: "issueTime", average_columns: &[], counter_columns: &[] },
+        RetainedTable { table: "minutely_wx_forecast", time_column: "issueTime", average_columns: &[], counter_columns: &[] },
+        RetainedTable { table: "daily_wx_forecast", time_column: "issueTime", average_columns: &[], counter_columns: &[] },
+        RetainedTable { table: "production_forecast_hourly", time_column: "issueTime", average_columns: &[], counter_columns: &[] },
+        RetainedTable { table: "production_forecast_daily", time_column: "issueTime", average_columns: &[], counter_columns: &[] },
+    ];
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+#[derive(Debug, Deserialize, Clone )]
+pub struct RetentionConf {
+    // cron expression or "every N<unit> [at <offset>]".  Default is daily at 03:30 UTC
+    #[serde( default = "default_retention_schedule" )]
+    pub schedule: String,
+    #[serde( with = "crate::integer_to_chrono_time_delta_ms", default = "default_retention_jitter" )]
+    pub jitter: TimeDelta,
+    // directory archive files are written to.  Empty to delete old rows without archiving
+    #[serde( default = "default_retention_archive_dir" )]
+    pub archive_dir: String,
+    // "parquet" or "csv" (gzip compressed)
+    #[serde( default = "default_retention_archive_format" )]
+    pub archive_format: String,
+    #[serde( default = "default_retention_tables" )]
+    pub tables: Vec<TableRetentionConf>,
+}
+fn default_retention_schedule() -> String {
+    "30 3 * * *".to_string()
+}
+fn default_retention_jitter() -> TimeDelta {
+    TimeDelta::zero()
+}
+fn default_retention_archive_dir() -> String {
+    String::new()
+}
+fn default_retention_archive_format() -> String {
+    "parquet".to_string()
+}
+fn default_retention_tables() -> Vec<TableRetentionConf> {
+    Vec::new()
+}
+
+#[derive(Debug, Deserialize, Clone )]
+pub struct TableRetentionConf {
+    pub table: String,
+    // days of raw rows kept
+    pub keep_days: i64,
+    // store hourly averages and energy in <table>_hourly before deleting
+    #[serde( default = "default_table_downsample" )]
+    pub downsample: bool,
+    // write rows to archive_dir before deleting (if archive_dir is set)
+    #[serde( default = "default_table_archive" )]
+    pub archive: bool,
+}
+fn default_table_downsample() -> bool {
+    false
+}
+fn default_table_archive() -> bool {
+    true
+}
+
+#[derive(Debug)]
+struct RetainedTable {
+    table: &'static str,
+    time_column: &'static str,
+    // columns averaged per hour when downsampled
+    average_columns: &'static [&'static str],
+    // lifetime energy counters.  Hourly rows store the last reading in the hour and energy in the hour (<counter>_delta)
+    counter_columns: &'static [&'static str],
+}
+impl RetainedTable {
+    fn find(table: &str) -> Option<&'static RetainedTable> {
+        RETAINED_TABLES.iter().find( |retained| retained.table == table )
+    }
+    fn can_downsample(&self) -> bool {
+        !self.average_columns.is_empty() || !self.counter_columns.is_empty()
+    }
+}
+
+// FUNCTIONS
+
+pub async fn retention_to_mysql(solar_pool: Option<sqlx::Pool<sqlx::MySql>>, retention_conf: RetentionConf, mut retention_job: Job) {
+    // archive format verified at startup by verify_retention_conf
+    let format = ArchiveFormat::parse(&retention_conf.archive_format).unwrap_or(ArchiveFormat::Parquet);
+
+    while retention_job.tick().await.is_some() {
+
+        let Some(sql_pool) = &solar_pool else {
+            error!("Couldn't get sql pool");
+            continue;
+        };
+        for table_conf in &retention_conf.tables {
+            let Some(retained) = RetainedTable::find(&table_conf.table) else { continue };
+            apply_table_retention(sql_pool, &retention_conf, table_conf, retained, format, &retention_job).await;
+        }
+    }
+}
+
+async fn apply_table_retention(sql_pool: &sqlx::Pool<sqlx::MySql>, retention_conf: &RetentionConf, table_conf: &TableRetentionConf,
+    retained: &RetainedTable, format: ArchiveFormat, retention_job: &Job) {
+    // processes rows older than keep_days one day at a time, oldest first.  Stops at the first day that fails, or on shutdown
+    // after the day in progress.
+    let now = Utc::now();
+    let cutoff = match ( now - TimeDelta::days(table_conf.keep_days) ).duration_trunc(TimeDelta::days(1)) {
+        Ok(cutoff) => cutoff,
+        Err(_) => return,
+    };
+    let oldest_query = format!("SELECT MIN({}) FROM {}", retained.time_column, retained.table);
+    let mut days: u32 = 0;
+
+    while !retention_job.stopping() {
+        let oldest = sqlx::query_scalar::<_, Option<DateTime<Utc>>>(&oldest_query).fetch_one(sql_pool).await;
+        let oldest = match oldest {
+            Ok(Some(oldest)) if oldest <= cutoff => oldest,
+            Ok(_) => break,
+            Err(oldest_eff) => {
+                error!("Retention: couldn't get oldest row of {}. Error: {}", retained.table, oldest_eff);
+                return
+            },
+        };
+        // window is ( start, end ].  A row exactly at midnight belongs to the day before, same as hourly energy buckets.
+        let Ok(start) = ( oldest - TimeDelta::seconds(1) ).duration_trunc(TimeDelta::days(1)) else { return };
+        let end = ( start + TimeDelta::days(1) ).min(cutoff);
+
+        if table_conf.downsample && retained.can_downsample() && !downsample_window(sql_pool, retained, start, end).await {
+            return
+        }
+        if table_conf.archive && !retention_conf.archive_dir.is_empty()
+            && !archive_window(sql_pool, retention_conf, retained, start, end, format).await {
+            return
+        }
+        let delete_query = format!("DELETE FROM {} WHERE {} > ? AND {} <= ?", retained.table, retained.time_column, retained.time_column);
+        match sqlx::query(&delete_query).bind(start).bind(end).execute(sql_pool).await {
+            Ok(deleted) => debug!("Retention: {} rows of {} from {} to {} deleted.", deleted.rows_affected(), retained.table, start, end),
+            Err(delete_eff) => {
+                error!("Retention: rows of {} from {} to {} failed to delete. Error: {}", retained.table, start, end, delete_eff);
+                return
+            },
+        }
+        days += 1;
+    }
+    if days > 0 {
+        info!("Retention: {} day(s) of {} older than {} processed.", days, retained.table, cutoff);
+    }
+}
+
+async fn downsample_window(sql_pool: &sqlx::Pool<sqlx::MySql>, retained: &RetainedTable, start: DateTime<Utc>, end: DateTime<Utc>) -> bool {
+    // Stores hourly averages and last counter readings in <table>_hourly, then energy per hour from the counters.
+    // Hourly rows are keyed by serial and data_time (start of hour).  Returns false if anything failed.
+    let hour = format!("DATE_FORMAT({} - INTERVAL 1 SECOND, '%Y-%m-%d %H:00:00')", retained.time_column);
+    let mut columns: Vec<String> = vec![ "serial".to_string(), "data_time".to_string(), "samples".to_string() ];
+    let mut selects: Vec<String> = vec![ "serial".to_string(), hour.clone(), "COUNT(*)".to_string() ];
+    for column in retained.average_columns {
+        columns.push(column.to_string());
+        selects.push( format!("AVG({})", column) );
+    }
+    let rollup_query = format!(
+        "REPLACE INTO {}_hourly ( {} ) SELECT {} FROM {} WHERE {} > ? AND {} <= ? GROUP BY serial, {}",
+        retained.table, columns.join(", "), selects.join(", "), retained.table, retained.time_column, retained.time_column, hour
+    );
+    if let Err(rollup_eff) = sqlx::query(&rollup_query).bind(start).bind(end).execute(sql_pool).await {
+        error!("Retention: {} hourly rollup from {} to {} failed. Error: {}", retained.table, start, end, rollup_eff);
+        return false
+    }
+    // last reading of each counter in the hour and its time.  Not MAX: net counters go down while exporting
+    for counter in retained.counter_columns {
+        let last_reading_query = format!(
+            "UPDATE {table}_hourly hourly JOIN ( \
+                SELECT serial, {hour} AS hour_start, {counter} AS kwh, {time} AS reading_time, \
+                    ROW_NUMBER() OVER ( PARTITION BY serial, {hour} ORDER BY {time} DESC ) AS row_num \
+                FROM {table} WHERE {time} > ? AND {time} <= ? AND {counter} IS NOT NULL \
+            ) last_reading ON hourly.serial = last_reading.serial AND hourly.data_time = last_reading.hour_start \
+                AND last_reading.row_num = 1 \
+            SET hourly.{counter} = last_reading.kwh, hourly.{counter}_time = last_reading.reading_time",
+            table = retained.table, hour = hour, counter = counter, time = retained.time_column
+        );
+        if let Err(last_eff) = sqlx::query(&last_reading_query).bind(start).bind(end).execute(sql_pool).await {
+            error!("Retention: {} hourly {} from {} to {} failed. Error: {}", retained.table, counter, start, end, last_eff);
+            return false
+        }
+    }
+
+    let serial_query = format!("SELECT DISTINCT serial FROM {} WHERE {} > ? AND {} <= ?", retained.table, retained.time_column, retained.time_column);
+    let serials = match sqlx::query_scalar::<_, String>(&serial_query).bind(start).bind(end).fetch_all(sql_pool).await {
+        Ok(serials) => serials,
+        Err(serial_eff) => {
+            error!("Retention: couldn't get serials of {} from {} to {}. Error: {}", retained.table, start, end, serial_eff);
+            return false
+        },
+    };
+    for serial in &serials {
+        for counter in retained.counter_columns {
+            if !update_hourly_energy(sql_pool, retained, counter, serial, start, end).await {
+                return false
+            }
+        }
+    }
+    true
+}
+
+async fn update_hourly_energy(sql_pool: &sqlx::Pool<sqlx::MySql>, retained: &RetainedTable, counter: &str, serial: &str,
+    start: DateTime<Utc>, end: DateTime<Utc>) -> bool {
+    // Energy per hour for one device counter.  The first hour of the window needs the reading before the window: the last raw
+    // row before start if it still exists, otherwise the last counter reading (and its time) of the previous hourly row (already
+    // downsampled).
+    let previous_raw_query = format!(
+        "SELECT {}, {} FROM {} WHERE serial = ? AND {} <= ? AND {} IS NOT NULL ORDER BY {} DESC LIMIT 1",
+        retained.time_column, counter, retained.table, retained.time_column, counter, retained.time_column
+    );
+    let previous_hourly_query = format!(
+        "SELECT {}_time, {} FROM {}_hourly WHERE serial = ? AND data_time < ? AND {} IS NOT NULL AND {}_time IS NOT NULL \
+            ORDER BY data_time DESC LIMIT 1",
+        counter, counter, retained.table, counter, counter
+    );
+    let readings_query = format!(
+        "SELECT {}, {} FROM {} WHERE serial = ? AND {} > ? AND {} <= ? AND {} IS NOT NULL ORDER BY {}",
+        retained.time_column, counter, retained.table, retained.time_column, retained.time_column, counter, retained.time_column
+    );
+
+    let previous = match sqlx::query_as::<_, (DateTime<Utc>, f64)>(&previous_raw_query).bind(serial).bind(start).fetch_optional(sql_pool).await {
+        Ok(Some(previous)) => Ok(Some(previous)),
+        Ok(None) => sqlx::query_as::<_, (DateTime<Utc>, f64)>(&previous_hourly_query).bind(serial).bind(start).fetch_optional(sql_pool).await,
+        Err(previous_eff) => Err(previous_eff),
+    };
+    let readings = sqlx::query_as::<_, (DateTime<Utc>, f64)>(&readings_query).bind(serial).bind(start).bind(end).fetch_all(sql_pool).await;
+    let mut readings = match (previous, readings) {
+        (Ok(previous), Ok(readings)) => previous.into_iter().chain(readings).collect::<Vec<(DateTime<Utc>, f64)>>(),
+        (Err(read_eff), _) | (_, Err(read_eff)) => {
+            error!("Retention: couldn't read {} {} readings for {}. Error: {}", retained.table, counter, serial, read_eff);
+            return false
+        },
+    };
+    // counter resets / jumps are left out of the hourly energy
+    if let Some(&(first_time, _)) = readings.first() {
+        match energy::get_counter_events(sql_pool, counter, first_time, end).await {
+            Ok(events) => if let Some(device_events) = events.get(serial) {
+                energy::stitch_counter_events(&mut readings, device_events);
+            },
+            Err(events_eff) => {
+                error!("Retention: couldn't read {} counter events for {}. Error: {}", counter, serial, events_eff);
+                return false
+            },
+        }
+    }
+
+    // net counters go down while exporting, so their delta is the signed change in the hour
+    let hourly = if counter.starts_with("net_") {
+        energy::bucket_change(&readings, TimeDelta::hours(1), MAX_ENERGY_GAP)
+    } else {
+        energy::bucket_energy(&readings, TimeDelta::hours(1), MAX_ENERGY_GAP)
+    };
+    let update_query = format!("UPDATE {}_hourly SET {}_delta = ? WHERE serial = ? AND data_time = ?", retained.table, counter);
+    for (hour_start, kwh) in hourly {
+        if hour_start < start {
+            continue;
+        }
+        let updated = sqlx::query(&update_query).bind(kwh).bind(serial).bind(hour_start).execute(sql_pool).await;
+        if let Err(update_eff) = updated {
+            error!("Retention: {}_hourly {} energy for {} @ {} failed to update. Error: {}", retained.table, counter, serial, hour_start, update_eff);
+            return false
+        }
+    }
+    true
+}
+
+async fn archive_window(sql_pool: &sqlx::Pool<sqlx::MySql>, retention_conf: &RetentionConf, retained: &RetainedTable,
+    start: DateTime<Utc>, end: DateTime<Utc>, format: ArchiveFormat) -> bool {
+    // Writes raw rows in window to <archive_dir>/<table>/<table>_<date>.<ext>.  Returns false if rows couldn't be read or written.
+    let rows_query = format!("SELECT * FROM {} WHERE {} > ? AND {} <= ? ORDER BY {}", retained.table, retained.time_column,
+        retained.time_column, retained.time_column);
+    let rows = match sqlx::query(&rows_query).bind(start).bind(end).fetch_all(sql_pool).await {
+        Ok(rows) => rows,
+        Err(rows_eff) => {
+            error!("Retention: couldn't read {} rows from {} to {} for archive. Error: {}", retained.table, start, end, rows_eff);
+            return false
+        },
+    };
+    if rows.is_empty() {
+        return true
+    }
+
+    let path: PathBuf = [ retention_conf.archive_dir.as_str(), retained.table,
+        &format!("{}_{}.{}", retained.table, start.format("%Y-%m-%d"), format.extension()) ].iter().collect();
+    let table = archive::rows_to_table(&rows);
+    match archive::write_table(&table, &path, format) {
+        Ok(()) => {
+            debug!("Retention: {} rows of {} archived to {}", table.rows.len(), retained.table, path.display());
+            true
+        },
+        Err(archive_eff) => {
+            error!("Retention: {} rows from {} to {} failed to archive to {}. {}", retained.table, start, end, path.display(), archive_eff);
+            false
+        },
+    }
+}
+
+pub fn verify_retention_conf(retention_conf: &RetentionConf, errors: &mut Vec<ConfigError>) {
+    // verifies schedule, archive format and that each table is one retention supports.  Adds a ConfigError to errors for each problem
+    if let Err(schedule_eff) = Schedule::parse(&retention_conf.schedule) {
+        errors.push( ConfigError::invalid("retention.schedule", &retention_conf.schedule, &schedule_eff.to_string()) );
+    }
+    if ArchiveFormat::parse(&retention_conf.archive_format).is_none() {
+        errors.push( ConfigError::invalid("retention.archive_format", &retention_conf.archive_format, "Must be 'parquet' or 'csv'.") );
+    }
+    for (index, table_conf) in retention_conf.tables.iter().enumerate() {
+        let key = |setting: &str| format!("retention.tables.{}.{}", index, setting);
+        match RetainedTable::find(&table_conf.table) {
+            None => {
+                let tables: Vec<&str> = RETAINED_TABLES.iter().map( |retained| retained.table ).collect();
+                errors.push( ConfigError::invalid( &key("table"), &table_conf.table, &format!("Must be one of: {}", tables.join(", ")) ) );
+            },
+            Some(retained) => {
+                if table_conf.keep_days < 1 {
+                    errors.push( ConfigError::invalid( &key("keep_days"), table_conf.keep_days, "Must be at least 1." ) );
+                }
+                if table_conf.downsample && !retained.can_downsample() {
+                    errors.push( ConfigError::invalid( &key("downsample"), table_conf.downsample,
+                        &format!("Table {} can't be downsampled.", table_conf.table) ) );
+                }
+                if table_conf.archive && retention_conf.archive_dir.is_empty() {
+                    warn!("Retention archive_dir is empty.  {} rows older than {} days will be deleted without archiving.",
+                        table_conf.table, table_conf.keep_days);
+                }
+            },
+        }
+    }
+}
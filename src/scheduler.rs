@@ -0,0 +1,422 @@
+/*
+Wall-clock job scheduler.  Each named job (PVS6 poll, weather, forecast, ...) has a schedule, either:
+    - "every N<unit> [at <offset>]"  eg "every 5m", "every 7m at 30s", "every 1h at -200ms".  Units ms, s, m, h, d.
+      Ticks are aligned to multiples of N since the unix epoch (so every 5m ticks at :00, :05, ... and every 7m is aligned the
+      same way after every restart), then shifted by offset.
+    - a cron expression, evaluated in UTC.  5 fields (min hour day month weekday) or 6/7 fields with seconds (and year).
+Jobs sleep in short steps and re-check the wall clock, so a tick is not missed or run at the wrong time after the system sleeps
+or the clock is changed.  Ticks that were missed (eg system asleep) are handled with tokio's MissedTickBehavior semantics:
+    - Skip (default): missed ticks are dropped and counted.  The job waits for the next tick on schedule.
+    - Burst: every missed tick fires immediately, one after another, each returning its own scheduled time.
+    - Delay: the latest missed tick fires immediately, the rest are dropped and counted, then the schedule continues.
+Optional jitter delays each tick by a random amount up to jitter, to spread out requests to shared services.
+All jobs made by one Scheduler share a status registry (schedule, next / last run, missed ticks).  Each job has a stop signal
+(from the task supervisor, triggered on shutdown or task restart).  Once stopped tick returns None, so job loops end after the
+work of their current tick.
+*/
+
+// USE STATEMENTS
+    use std::{ collections::BTreeMap, error, fmt, str::FromStr, sync::{ Arc, Mutex } };
+    use chrono::{ DateTime, TimeDelta, Utc };
+    use log::{ debug, warn };
+    use rand::Rng;
+    use tokio::time::{ MissedTickBehavior, sleep };
+
+    use crate::shutdown::Shutdown;
+
+// CONSTANTS
+    // longest a job sleeps before re-checking the wall clock
+    const MAX_SLEEP: TimeDelta = TimeDelta::seconds(15);
+    // a tick that fires later than this after its scheduled time (plus jitter) counts as missed
+    const MISSED_TICK_GRACE: TimeDelta = TimeDelta::seconds(5);
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+#[derive(Debug)]
+pub enum ScheduleError {
+    Unit(char),
+    Every(String),
+    Cron(String),
+}
+impl error::Error for ScheduleError {}
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ScheduleError::*;
+        match self {
+            Unit(unit) => write!(f, "Invalid time unit: {}. Use d, h, m, or s", unit),
+            Every(expr) => write!(f, "Invalid schedule \"{}\". Expected \"every N<unit> [at <offset><unit>]\" eg \"every 5m at -200ms\"", expr),
+            Cron(err) => write!(f, "Invalid cron schedule: {}", err),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Schedule {
+    Every { period: TimeDelta, offset: TimeDelta },
+    Cron(Box<cron::Schedule>),
+}
+
+impl Schedule {
+    pub fn parse(expr: &str) -> Result<Self, ScheduleError> {
+        // parses "every N<unit> [at <offset>]" or a cron expression
+        let expr = expr.trim();
+        if let Some(every) = expr.strip_prefix("every") {
+            let mut parts = every.split_whitespace();
+            let period = parts.next().and_then(parse_duration).ok_or( ScheduleError::Every(expr.to_string()) )?;
+            let offset = match ( parts.next(), parts.next(), parts.next() ) {
+                (None, _, _) => TimeDelta::zero(),
+                (Some("at"), Some(offset), None) => parse_duration(offset).ok_or( ScheduleError::Every(expr.to_string()) )?,
+                _ => return Err( ScheduleError::Every(expr.to_string()) ),
+            };
+            if period <= TimeDelta::zero() {
+                return Err( ScheduleError::Every(expr.to_string()) )
+            }
+            return Ok( Schedule::Every { period, offset } )
+        }
+        // cron crate needs a seconds field.  Add one to standard 5 field expressions
+        let cron_expr = if expr.split_whitespace().count() == 5 { format!("0 {}", expr) } else { expr.to_string() };
+        cron::Schedule::from_str(&cron_expr)
+            .map( |schedule| Schedule::Cron( Box::new(schedule) ) )
+            .map_err( |cron_eff| ScheduleError::Cron(cron_eff.to_string()) )
+    }
+
+    pub fn from_interval(interval: u64, units: char, offset: TimeDelta) -> Result<Self, ScheduleError> {
+        // schedule from config interval, interval units ('d', 'h', 'm', 's') and offset
+        let seconds = crate::interval_seconds(interval, units).ok_or( ScheduleError::Unit(units) )?;
+        if seconds == 0 {
+            return Err( ScheduleError::Every(format!("every {}{}", interval, units)) )
+        }
+        Ok( Schedule::Every { period: TimeDelta::seconds(seconds as i64), offset } )
+    }
+
+    pub fn from_conf(schedule: &str, interval: u64, units: char, offset: TimeDelta) -> Result<Self, ScheduleError> {
+        // schedule expression takes priority over interval / units / offset if provided
+        if schedule.trim().is_empty() {
+            Self::from_interval(interval, units, offset)
+        } else {
+            Self::parse(schedule)
+        }
+    }
+
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        // first tick strictly after `after`
+        match self {
+            Schedule::Every { period, offset } => {
+                let period_ms = period.num_milliseconds();
+                let shifted_ms = ( after - *offset ).timestamp_millis();
+                let next_ms = ( shifted_ms.div_euclid(period_ms) + 1 ) * period_ms;
+                DateTime::from_timestamp_millis(next_ms).map( |next| next + *offset )
+            },
+            Schedule::Cron(schedule) => schedule.after(&after).next(),
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Schedule::Every { period, offset } => write!(f, "every {}s at {}ms", period.num_seconds(), offset.num_milliseconds()),
+            Schedule::Cron(schedule) => write!(f, "cron {}", schedule.source()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct JobStatus {
+    pub schedule: String,
+    pub next_run: Option<DateTime<Utc>>,
+    pub last_run: Option<DateTime<Utc>>,
+    pub missed_ticks: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct Scheduler {
+    jobs: Arc<Mutex<BTreeMap<String, JobStatus>>>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self { jobs: Arc::new( Mutex::new( BTreeMap::new() ) ) }
+    }
+
+    pub fn add_job(&self, name: &str, schedule: Schedule, jitter: TimeDelta, stop: Shutdown) -> Job {
+        let job = Job {
+            name: name.to_string(),
+            schedule,
+            jitter: jitter.max(TimeDelta::zero()),
+            missed_tick_behavior: MissedTickBehavior::Skip,
+            next: None,
+            last_tick: None,
+            jobs: self.jobs.clone(),
+            stop,
+        };
+        // register job so it shows in statuses before its first tick.  A job added again (task restart) keeps its run history.
+        let schedule = job.schedule.to_string();
+        let next_run = job.schedule.next_after( Utc::now() );
+        job.update_status( |status| {
+            status.schedule = schedule;
+            status.next_run = next_run;
+        });
+        job
+    }
+
+    pub fn remove_job(&self, name: &str) {
+        // removes status of a job that is no longer configured
+        if let Ok(mut jobs) = self.jobs.lock() {
+            jobs.remove(name);
+        }
+    }
+
+    pub fn job_statuses(&self) -> BTreeMap<String, JobStatus> {
+        match self.jobs.lock() {
+            Ok(jobs) => jobs.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+}
+
+pub struct Job {
+    name: String,
+    schedule: Schedule,
+    jitter: TimeDelta,
+    missed_tick_behavior: MissedTickBehavior,
+    // ( scheduled tick time, time tick fires including jitter )
+    next: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    // ( scheduled time, offset ) of the last tick fired.  Offset is the one the tick was planned with (None for cron schedules)
+    last_tick: Option<(DateTime<Utc>, Option<TimeDelta>)>,
+    jobs: Arc<Mutex<BTreeMap<String, JobStatus>>>,
+    stop: Shutdown,
+}
+
+impl Job {
+    pub fn with_missed_tick_behavior(mut self, behavior: MissedTickBehavior) -> Self {
+        self.missed_tick_behavior = behavior;
+        self
+    }
+
+    pub fn offset(&self) -> Option<TimeDelta> {
+        // offset of "every" schedules.  Cron schedules have no offset
+        match &self.schedule {
+            Schedule::Every { offset, .. } => Some(*offset),
+            Schedule::Cron(_) => None,
+        }
+    }
+
+    pub fn tick_offset(&self) -> Option<TimeDelta> {
+        // offset the last tick returned by tick was planned with.  Differs from offset once set_offset is called after it fired
+        self.last_tick.and_then( |(_, offset)| offset )
+    }
+
+    pub fn set_offset(&mut self, new_offset: TimeDelta) -> bool {
+        // changes offset of "every" schedules, starting with the next tick.  Returns false for cron schedules.
+        let Schedule::Every { offset, .. } = &mut self.schedule else {
+            return false
+        };
+        *offset = new_offset;
+        let schedule = self.schedule.to_string();
+        self.update_status( |status| status.schedule = schedule );
+        // the planned tick is re-planned in the interval after the last tick fired, so the interval isn't ticked twice (offset
+        // raised) or seen as the clock moving backwards (offset lowered).  Without a fired tick it is planned from now.
+        if self.next.is_some() {
+            self.next = match self.last_tick {
+                Some( (scheduled, Some(old_offset)) ) => self.plan_after( scheduled - old_offset + new_offset ),
+                _ => None,
+            };
+        }
+        true
+    }
+
+    pub fn stopping(&self) -> bool {
+        // true once the job's stop signal is triggered.  Long running job work can check this to stop early.
+        self.stop.is_triggered()
+    }
+
+    pub async fn tick(&mut self) -> Option<DateTime<Utc>> {
+        // waits for the next tick and returns the time it was scheduled for (without jitter).  None once stopped.
+        loop {
+            if self.stopping() {
+                debug!("Job {} stopped.", self.name);
+                return None
+            }
+            let now = Utc::now();
+            let (scheduled, fire_at) = match self.next {
+                Some(next) => next,
+                None => match self.plan_after(now) {
+                    Some(next) => next,
+                    None => {
+                        warn!("Job {} schedule {} has no upcoming ticks.", self.name, self.schedule);
+                        self.sleep_or_stop(MAX_SLEEP).await;
+                        continue;
+                    },
+                },
+            };
+
+            // clock moved backwards past the planned tick (earlier tick is now due first).  Re-plan from now.  An earlier tick in
+            // the interval of the last tick fired (offset raised since) is not due, so the clock must be before the last tick too.
+            if let Some(replanned) = self.schedule.next_after(now) && replanned < scheduled
+                && self.last_tick.is_none_or( |(last, _)| now < last ) {
+                warn!("Job {}: clock moved backwards.  Next tick moved from {} to {}.", self.name, scheduled, replanned);
+                self.next = None;
+                continue;
+            }
+
+            if now >= fire_at {
+                if now - fire_at > MISSED_TICK_GRACE {
+                    // system slept or clock jumped forward.
+                    let mut missed: u64 = 1;
+                    let mut latest = scheduled;
+                    let mut following = self.schedule.next_after(scheduled);
+                    while let Some(next) = following {
+                        if next > now { break }
+                        missed += 1;
+                        latest = next;
+                        following = self.schedule.next_after(next);
+                    }
+                    match self.missed_tick_behavior {
+                        MissedTickBehavior::Burst => {
+                            // fire this tick now, following missed ticks fire on the next calls
+                            debug!("Job {}: tick scheduled {} fired late at {}.  {} tick(s) to catch up.", self.name, scheduled, now, missed - 1);
+                        },
+                        MissedTickBehavior::Delay => {
+                            // fire latest missed tick now and drop the others
+                            warn!("Job {}: {} tick(s) missed (scheduled {}, now {}).  Running latest missed tick now.", 
+                                self.name, missed - 1, scheduled, now);
+                            self.update_status( |status| status.missed_ticks += missed - 1 );
+                            self.next = self.plan_after(now);
+                            self.fired(latest, now);
+                            return Some(latest)
+                        },
+                        _ => {
+                            // Skip missed ticks and wait for the next one on schedule.
+                            warn!("Job {}: {} tick(s) missed (scheduled {}, now {}).  Resyncing to schedule.", self.name, missed, scheduled, now);
+                            self.update_status( |status| status.missed_ticks += missed );
+                            self.next = None;
+                            continue;
+                        },
+                    }
+                }
+                self.next = self.plan_after(scheduled);
+                self.fired(scheduled, now);
+                return Some(scheduled)
+            }
+
+            self.next = Some( (scheduled, fire_at) );
+            self.sleep_or_stop( ( fire_at - now ).min(MAX_SLEEP) ).await;
+        }
+    }
+
+    async fn sleep_or_stop(&self, wait: TimeDelta) {
+        // sleeps for wait or until stopped
+        tokio::select! {
+            _ = sleep( wait.to_std().unwrap_or_default() ) => {},
+            _ = self.stop.wait() => {},
+        }
+    }
+
+    fn fired(&mut self, scheduled: DateTime<Utc>, now: DateTime<Utc>) {
+        // records a tick that fired in job status.  self.next is already planned.  Ticks are always planned with the current
+        // offset (set_offset re-plans), so it is the fired tick's offset
+        self.last_tick = Some( (scheduled, self.offset()) );
+        let next_run = self.next.map( |next| next.0 );
+        self.update_status( |status| {
+            status.last_run = Some(now);
+            status.next_run = next_run;
+        });
+        debug!("Job {} tick scheduled {} fired {}", self.name, scheduled, now);
+    }
+
+    fn plan_after(&self, after: DateTime<Utc>) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let scheduled = self.schedule.next_after(after)?;
+        let jitter_ms = self.jitter.num_milliseconds();
+        let jitter = if jitter_ms > 0 { TimeDelta::milliseconds( rand::thread_rng().gen_range(0..=jitter_ms) ) } else { TimeDelta::zero() };
+        self.update_status( |status| status.next_run = Some(scheduled) );
+        Some( (scheduled, scheduled + jitter) )
+    }
+
+    fn update_status<F: FnOnce(&mut JobStatus)>(&self, update: F) {
+        if let Ok(mut jobs) = self.jobs.lock() {
+            let status = jobs.entry(self.name.clone()).or_insert( JobStatus {
+                schedule: self.schedule.to_string(),
+                next_run: None,
+                last_run: None,
+                missed_ticks: 0,
+            });
+            update(status);
+        }
+    }
+}
+
+// FUNCTIONS
+
+pub fn parse_missed_tick_behavior(behavior: &str) -> Option<MissedTickBehavior> {
+    // config value ("skip", "burst" or "delay") to tokio MissedTickBehavior
+    match behavior.trim().to_lowercase().as_str() {
+        "skip" => Some( MissedTickBehavior::Skip ),
+        "burst" => Some( MissedTickBehavior::Burst ),
+        "delay" => Some( MissedTickBehavior::Delay ),
+        _ => None,
+    }
+}
+
+pub fn parse_duration(s: &str) -> Option<TimeDelta> {
+    // parses signed duration with unit suffix, eg "5m", "-200ms", "1h", "30s", "1d"
+    let s = s.trim();
+    let split = s.find( |c: char| c.is_ascii_alphabetic() )?;
+    let (number, unit) = s.split_at(split);
+    let value: i64 = number.parse().ok()?;
+    match unit {
+        "ms" => Some( TimeDelta::milliseconds(value) ),
+        "s" => Some( TimeDelta::seconds(value) ),
+        "m" => Some( TimeDelta::minutes(value) ),
+        "h" => Some( TimeDelta::hours(value) ),
+        "d" => Some( TimeDelta::days(value) ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn every(period: TimeDelta, offset: TimeDelta) -> Job {
+        Scheduler::new().add_job( "test", Schedule::Every { period, offset }, TimeDelta::zero(), Shutdown::default() )
+    }
+
+    #[test]
+    fn set_offset_replans_next_tick_in_following_interval() {
+        // 5 minute interval, tick at boundary - 500 ms just fired.  Next tick moves with the offset, in the next interval
+        let period = TimeDelta::minutes(5);
+        let boundary = DateTime::from_timestamp(1_750_000_200, 0).unwrap();
+        let mut job = every( period, TimeDelta::milliseconds(-500) );
+        let fired = boundary - TimeDelta::milliseconds(500);
+        job.last_tick = Some( (fired, job.offset()) );
+        job.next = job.plan_after(fired);
+        assert_eq!( job.next.map( |next| next.0 ), Some( boundary + period - TimeDelta::milliseconds(500) ) );
+
+        assert!( job.set_offset( TimeDelta::milliseconds(-400) ) );
+        assert_eq!( job.next.map( |next| next.0 ), Some( boundary + period - TimeDelta::milliseconds(400) ) );
+        assert!( job.set_offset( TimeDelta::milliseconds(-600) ) );
+        assert_eq!( job.next.map( |next| next.0 ), Some( boundary + period - TimeDelta::milliseconds(600) ) );
+        // offset of the tick that fired is unchanged
+        assert_eq!( job.tick_offset(), Some( TimeDelta::milliseconds(-500) ) );
+    }
+
+    #[tokio::test]
+    async fn set_offset_between_ticks_ticks_once_per_interval() {
+        let period = TimeDelta::milliseconds(200);
+        let mut job = every( period, TimeDelta::milliseconds(-50) );
+        let mut boundaries = Vec::new();
+        let mut offset = TimeDelta::milliseconds(-50);
+        for new_offset in [ -20, -80, 30, -60, 0 ] {
+            let scheduled = job.tick().await.unwrap();
+            assert_eq!( job.tick_offset(), Some(offset) );
+            boundaries.push( scheduled - offset );
+            offset = TimeDelta::milliseconds(new_offset);
+            assert!( job.set_offset(offset) );
+        }
+        for pair in boundaries.windows(2) {
+            assert_eq!( pair[1] - pair[0], period, "ticks {:?}", boundaries );
+        }
+        assert_eq!( job.jobs.lock().unwrap()["test"].missed_ticks, 0 );
+    }
+}
@@ -0,0 +1,79 @@
+/*
+Layered configuration settings.  Later layers override earlier ones:
+    1)  config file (config.yml).  Optional, so the program can run from environment variables only (docker / kubernetes).
+    2)  environment variables PVS6__<SECTION>__<KEY>, eg PVS6__MYSQL__PASSWORD, PVS6__PVS6__GET_DEVICE_INTERVAL=5.
+        List settings are comma separated, eg PVS6__PIRATE_WX__ALERT_NOTIFY_SEVERITIES=warning,watch
+    3)  secret files PVS6__<SECTION>__<KEY>_FILE naming a file that holds the value, eg PVS6__MYSQL__PASSWORD_FILE=/run/secrets/db.
+        A trailing newline in the file is ignored.
+Values from the environment are strings and are converted to the type of the setting (number, bool, ...) when Conf is read.
+*/
+
+// USE STATEMENTS
+    use std::{ env, fs };
+    use config::{ Config, ConfigBuilder, ConfigError, Environment, File, Map, Value, builder::DefaultState };
+    use log::warn;
+
+// CONSTANTS
+    const ENV_PREFIX: &str = "PVS6";
+    const ENV_SEPARATOR: &str = "__";
+    const SECRET_FILE_SUFFIX: &str = "_FILE";
+    // settings that are lists.  Comma separated in environment variables and secret files.
+    const LIST_KEYS: [&str; 2] = [ "pirate_wx.alert_notify_severities", "pirate_wx.alert_notify_keywords" ];
+    // sections that must exist in Conf.  Defaulted to empty so they can come from the environment only.
+    const REQUIRED_SECTIONS: [&str; 1] = [ "mysql" ];
+
+// FUNCTIONS
+
+pub fn read_settings(config_name: &str) -> Result<Config, ConfigError> {
+    // reads config file, environment variables and secret files into one set of settings
+    let prefix = format!("{}{}", ENV_PREFIX, ENV_SEPARATOR);
+    let mut plain = Map::new();
+    let mut lists = Vec::new();
+    let mut secrets = Vec::new();
+    for (name, value) in env::vars() {
+        let Some(setting) = name.strip_prefix(&prefix) else { continue };
+        match setting.strip_suffix(SECRET_FILE_SUFFIX) {
+            Some(setting) => secrets.push( (env_key(setting), name.clone(), value) ),
+            None if LIST_KEYS.contains( &env_key(setting).as_str() ) => lists.push( (env_key(setting), value) ),
+            None => {
+                plain.insert(name, value);
+            },
+        }
+    }
+
+    let mut builder = Config::builder();
+    for section in REQUIRED_SECTIONS {
+        builder = builder.set_default( section, Map::<String, Value>::new() )?;
+    }
+    builder = builder
+        .add_source( File::with_name(config_name).required(false) )
+        .add_source( Environment::with_prefix(ENV_PREFIX).separator(ENV_SEPARATOR).ignore_empty(true).source( Some(plain) ) );
+    for (key, value) in lists {
+        builder = set_env_override(builder, &key, value)?;
+    }
+    // secret files are set last, so they win over a plain environment variable for the same setting
+    for (key, name, path) in secrets {
+        let secret = fs::read_to_string(&path)
+            .map_err( |read_eff| ConfigError::Message( format!("Couldn't read secret file {} from {}. {}", path, name, read_eff) ) )?;
+        if env::var_os( format!("{}{}", prefix, key.to_uppercase().replace('.', ENV_SEPARATOR)) ).is_some() {
+            warn!("Setting {} is in both an environment variable and a secret file.  Using the secret file.", key);
+        }
+        builder = set_env_override(builder, &key, secret.trim_end_matches( ['\r', '\n'] ).to_string())?;
+    }
+    builder.build()
+}
+
+fn set_env_override(builder: ConfigBuilder<DefaultState>, key: &str, value: String) -> Result<ConfigBuilder<DefaultState>, ConfigError> {
+    // list settings are split on commas
+    if LIST_KEYS.contains(&key) {
+        let items: Vec<String> = value.split(',').map( |item| item.trim().to_string() ).filter( |item| !item.is_empty() ).collect();
+        builder.set_override(key, items)
+    } else {
+        builder.set_override(key, value)
+    }
+}
+
+fn env_key(setting: &str) -> String {
+    // MYSQL__PASSWORD -> mysql.password
+    setting.to_lowercase().replace(ENV_SEPARATOR, ".")
+}
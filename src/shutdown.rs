@@ -0,0 +1,63 @@
+/*
+Coordinated shutdown on SIGTERM / SIGINT.
+Shutdown is shared by the scheduler, task supervisor and api.  Once triggered, jobs stop ticking (no new polls), the supervisor
+stops restarting tasks and the api stops accepting connections.  Work already started (PVS6 / weather requests, db writes)
+runs to the end of its loop, so main waits for tasks to end before closing the db pool.
+There is no on-disk spool to flush: writes are made directly to the solar db, and failed writes are logged and dropped.
+*/
+
+// USE STATEMENTS
+    use std::sync::Arc;
+    use log::error;
+    use tokio::{ signal, sync::watch };
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self { sender: Arc::new( watch::channel(false).0 ) }
+    }
+}
+
+impl Shutdown {
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.sender.subscribe()
+    }
+
+    pub async fn wait(&self) {
+        // returns once shutdown is triggered (immediately if it already was)
+        let mut receiver = self.sender.subscribe();
+        let _ = receiver.wait_for( |triggered| *triggered ).await;
+    }
+}
+
+// FUNCTIONS
+
+pub async fn wait_for_signal() -> &'static str {
+    // waits for SIGTERM (systemctl stop) or SIGINT (ctrl-c) and returns its name
+    let mut sigterm = match signal::unix::signal( signal::unix::SignalKind::terminate() ) {
+        Ok(sigterm) => sigterm,
+        Err(signal_eff) => {
+            error!("Couldn't listen for SIGTERM. Error: {}", signal_eff);
+            let _ = signal::ctrl_c().await;
+            return "SIGINT"
+        },
+    };
+    tokio::select! {
+        _ = sigterm.recv() => "SIGTERM",
+        _ = signal::ctrl_c() => "SIGINT",
+    }
+}
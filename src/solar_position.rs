@@ -0,0 +1,213 @@
+/*
+Sun position and clear-sky irradiance calculations for a site (latitude, longitude).
+Sun position uses the NOAA solar calculator equations (accurate to well under a degree, which is plenty for irradiance estimates).
+Clear-sky global horizontal irradiance uses the Haurwitz model, which only needs sun zenith angle so it can run fully offline.
+Clear-sky direct normal irradiance uses the Meinel air mass model, with diffuse as the remainder of global horizontal.
+Plane of array (tilted panel) irradiance uses the isotropic sky model.
+*/
+
+// USE STATEMENTS
+    use chrono::{ DateTime, Datelike, NaiveDate, TimeDelta, Utc };
+
+// CONSTANTS
+    // Julian day of unix epoch (1970-01-01T00:00:00Z)
+    const JULIAN_DAY_UNIX_EPOCH: f64 = 2440587.5;
+    // Julian day of J2000 epoch
+    const JULIAN_DAY_J2000: f64 = 2451545.0;
+    // Solar constant (W/m²)
+    const SOLAR_CONSTANT: f64 = 1361.0;
+    // Ground reflectance used for reflected irradiance on tilted panels
+    const GROUND_ALBEDO: f64 = 0.2;
+    // Sun zenith at sunrise / sunset.  90° plus refraction and sun radius
+    const SUNRISE_ZENITH_DEG: f64 = 90.833;
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+#[derive(Clone, Copy, Debug)]
+pub struct SolarPosition {
+    // angle between sun and straight up, in degrees.  > 90 when sun is below horizon
+    pub zenith_deg: f64,
+    // compass bearing of sun, in degrees clockwise from north
+    pub azimuth_deg: f64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SunTimes {
+    // None when sun doesn't rise or set that day (polar day / night)
+    pub sunrise: Option<DateTime<Utc>>,
+    pub solar_noon: DateTime<Utc>,
+    pub sunset: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ClearSkyIrradiance {
+    // global horizontal irradiance (W/m²)
+    pub ghi: f64,
+    // direct normal irradiance (W/m²)
+    pub dni: f64,
+    // diffuse horizontal irradiance (W/m²)
+    pub dhi: f64,
+}
+
+// FUNCTIONS
+
+pub fn solar_position( lat: f64, long: f64, time: DateTime<Utc> ) -> SolarPosition {
+    // Returns sun zenith and azimuth at lat / long (degrees, north and east positive) for time.
+
+    let julian_century = julian_century(time);
+    let (declination, eq_of_time_min) = declination_and_equation_of_time(julian_century);
+
+    // true solar time in minutes, from utc minutes of the day, equation of time and longitude (4 minutes per degree)
+    let utc_minutes = ( time.timestamp().rem_euclid(86400) as f64 + time.timestamp_subsec_millis() as f64 / 1000.0 ) / 60.0;
+    let true_solar_time = ( utc_minutes + eq_of_time_min + 4.0 * long ).rem_euclid(1440.0);
+    let hour_angle = true_solar_time / 4.0 - 180.0;
+
+    let lat_rad = lat.to_radians();
+    let cos_zenith = ( lat_rad.sin() * declination.sin() + lat_rad.cos() * declination.cos() * hour_angle.to_radians().cos() )
+        .clamp(-1.0, 1.0);
+    let zenith = cos_zenith.acos();
+
+    // azimuth undefined at the poles / sun directly overhead.  Report due south (north in southern hemisphere) in those cases
+    let az_denominator = lat_rad.cos() * zenith.sin();
+    let azimuth_deg = if az_denominator.abs() < 1e-9 {
+        if lat >= 0.0 { 180.0 } else { 0.0 }
+    } else {
+        let az_cos = ( ( lat_rad.sin() * cos_zenith - declination.sin() ) / az_denominator ).clamp(-1.0, 1.0);
+        if hour_angle > 0.0 {
+            ( az_cos.acos().to_degrees() + 180.0 ).rem_euclid(360.0)
+        } else {
+            ( 540.0 - az_cos.acos().to_degrees() ).rem_euclid(360.0)
+        }
+    };
+
+    SolarPosition { zenith_deg: zenith.to_degrees(), azimuth_deg }
+}
+
+pub fn sun_times( lat: f64, long: f64, date: NaiveDate ) -> SunTimes {
+    // Sunrise, solar noon and sunset at lat / long on date (date at the site, ie date of that day's solar noon).
+    let utc_midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+    // approximate solar noon first, then recalculate equation of time and declination at that time
+    let approx_noon = utc_midnight + TimeDelta::minutes( ( 720.0 - 4.0 * long ) as i64 );
+    let (declination, eq_of_time_min) = declination_and_equation_of_time( julian_century(approx_noon) );
+
+    let noon_min = 720.0 - 4.0 * long - eq_of_time_min;
+    let solar_noon = utc_midnight + TimeDelta::milliseconds( ( noon_min * 60000.0 ) as i64 );
+
+    let lat_rad = lat.to_radians();
+    let cos_hour_angle = SUNRISE_ZENITH_DEG.to_radians().cos() / ( lat_rad.cos() * declination.cos() )
+        - lat_rad.tan() * declination.tan();
+    if !( -1.0..=1.0 ).contains(&cos_hour_angle) {
+        return SunTimes { sunrise: None, solar_noon, sunset: None }
+    }
+    let half_day_min = 4.0 * cos_hour_angle.acos().to_degrees();
+    SunTimes {
+        sunrise: Some( solar_noon - TimeDelta::milliseconds( ( half_day_min * 60000.0 ) as i64 ) ),
+        solar_noon,
+        sunset: Some( solar_noon + TimeDelta::milliseconds( ( half_day_min * 60000.0 ) as i64 ) ),
+    }
+}
+
+pub fn solar_date( long: f64, time: DateTime<Utc> ) -> NaiveDate {
+    // date at the site by longitude (mean solar time), ie day that sunrise and sunset at time belong to.
+    ( time + TimeDelta::minutes( ( 4.0 * long ) as i64 ) ).date_naive()
+}
+
+pub fn is_daylight( lat: f64, long: f64, time: DateTime<Utc>, margin: TimeDelta ) -> bool {
+    // True if sun is up at any point within margin of time (ie from margin before sunrise to margin after sunset).
+    [ time - margin, time, time + margin ].iter()
+        .any( |t| solar_position( lat, long, *t ).zenith_deg < SUNRISE_ZENITH_DEG )
+}
+
+pub fn clear_sky_irradiance( position: &SolarPosition, time: DateTime<Utc> ) -> ClearSkyIrradiance {
+    // Clear-sky global, direct normal and diffuse irradiance (W/m²) for sun position at time.  All 0 when sun is below horizon.
+    let cos_zenith = position.zenith_deg.to_radians().cos();
+    if cos_zenith <= 0.0 {
+        return ClearSkyIrradiance { ghi: 0.0, dni: 0.0, dhi: 0.0 }
+    }
+    let ghi = 1098.0 * cos_zenith * ( -0.059 / cos_zenith ).exp();
+
+    // Kasten-Young relative air mass and Meinel direct beam attenuation
+    let air_mass = 1.0 / ( cos_zenith + 0.50572 * ( 96.07995 - position.zenith_deg ).powf(-1.6364) );
+    let dni = ( extraterrestrial_irradiance(time) * 0.7_f64.powf( air_mass.powf(0.678) ) ).min( ghi / cos_zenith );
+    let dhi = ( ghi - dni * cos_zenith ).max(0.0);
+
+    ClearSkyIrradiance { ghi, dni, dhi }
+}
+
+pub fn plane_of_array_irradiance( position: &SolarPosition, irradiance: &ClearSkyIrradiance, tilt_deg: f64, azimuth_deg: f64 ) -> f64 {
+    // Irradiance (W/m²) on a panel tilted tilt_deg from horizontal, facing azimuth_deg (degrees clockwise from north).
+    // Sum of direct beam on panel, isotropic sky diffuse and ground reflected irradiance.
+    let zenith = position.zenith_deg.to_radians();
+    let tilt = tilt_deg.to_radians();
+    let cos_aoi = zenith.cos() * tilt.cos()
+        + zenith.sin() * tilt.sin() * ( position.azimuth_deg - azimuth_deg ).to_radians().cos();
+
+    let beam = irradiance.dni * cos_aoi.max(0.0);
+    let sky_diffuse = irradiance.dhi * ( 1.0 + tilt.cos() ) / 2.0;
+    let ground_reflected = irradiance.ghi * GROUND_ALBEDO * ( 1.0 - tilt.cos() ) / 2.0;
+    beam + sky_diffuse + ground_reflected
+}
+
+pub fn extraterrestrial_irradiance( time: DateTime<Utc> ) -> f64 {
+    // Solar irradiance (W/m²) at top of atmosphere, normal to the sun.  Varies ~3% over the year with earth-sun distance.
+    let day_of_year = time.ordinal() as f64;
+    SOLAR_CONSTANT * ( 1.0 + 0.033 * ( 2.0 * std::f64::consts::PI * day_of_year / 365.0 ).cos() )
+}
+
+pub fn clear_sky_ghi( lat: f64, long: f64, time: DateTime<Utc> ) -> f64 {
+    // Clear-sky global horizontal irradiance (W/m²) using the Haurwitz model.  0 when sun is below horizon.
+    clear_sky_irradiance( &solar_position( lat, long, time ), time ).ghi
+}
+
+pub fn clear_sky_ghi_energy( lat: f64, long: f64, start: DateTime<Utc>, period: TimeDelta ) -> f64 {
+    // Clear-sky horizontal irradiation (kWh/m²) over period starting at start.  Integrated with 5 minute midpoint steps
+    // (or a single step for periods shorter than 5 minutes).
+    let period_s = period.num_seconds().max(0);
+    if period_s == 0 {
+        return 0.0
+    }
+    let steps = ( period_s / 300 ).max(1);
+    let step_s = period_s as f64 / steps as f64;
+
+    let mut wh_per_m2 = 0.0;
+    for step in 0..steps {
+        let midpoint = start + TimeDelta::milliseconds( ( ( step as f64 + 0.5 ) * step_s * 1000.0 ) as i64 );
+        wh_per_m2 += clear_sky_ghi( lat, long, midpoint ) * step_s / 3600.0;
+    }
+    wh_per_m2 / 1000.0
+}
+
+fn julian_century( time: DateTime<Utc> ) -> f64 {
+    let julian_day = time.timestamp() as f64 / 86400.0 + JULIAN_DAY_UNIX_EPOCH;
+    ( julian_day - JULIAN_DAY_J2000 ) / 36525.0
+}
+
+fn declination_and_equation_of_time( jc: f64 ) -> (f64, f64) {
+    // Returns sun declination (radians) and equation of time (minutes) for julian century jc.
+    let geom_mean_long = ( 280.46646 + jc * ( 36000.76983 + jc * 0.0003032 ) ).rem_euclid(360.0);
+    let geom_mean_anom = 357.52911 + jc * ( 35999.05029 - 0.0001537 * jc );
+    let eccent = 0.016708634 - jc * ( 0.000042037 + 0.0000001267 * jc );
+
+    let m = geom_mean_anom.to_radians();
+    let eq_of_ctr = m.sin() * ( 1.914602 - jc * ( 0.004817 + 0.000014 * jc ) )
+        + ( 2.0 * m ).sin() * ( 0.019993 - 0.000101 * jc )
+        + ( 3.0 * m ).sin() * 0.000289;
+    let true_long = geom_mean_long + eq_of_ctr;
+    let omega = ( 125.04 - 1934.136 * jc ).to_radians();
+    let apparent_long = true_long - 0.00569 - 0.00478 * omega.sin();
+
+    let mean_obliq = 23.0 + ( 26.0 + ( 21.448 - jc * ( 46.815 + jc * ( 0.00059 - jc * 0.001813 ) ) ) / 60.0 ) / 60.0;
+    let obliq_corr = ( mean_obliq + 0.00256 * omega.cos() ).to_radians();
+
+    let declination = ( obliq_corr.sin() * apparent_long.to_radians().sin() ).asin();
+
+    let y = ( obliq_corr / 2.0 ).tan().powi(2);
+    let l0 = geom_mean_long.to_radians();
+    let eq_of_time = 4.0 * ( y * ( 2.0 * l0 ).sin()
+        - 2.0 * eccent * m.sin()
+        + 4.0 * eccent * y * m.sin() * ( 2.0 * l0 ).cos()
+        - 0.5 * y * y * ( 4.0 * l0 ).sin()
+        - 1.25 * eccent * eccent * ( 2.0 * m ).sin() ).to_degrees();
+
+    (declination, eq_of_time)
+}
@@ -0,0 +1,192 @@
+/*
+Supervisor for long running tasks (collectors, forecast, retention, api).
+Each task is spawned from a function that builds it, so a task that panics or exits is built again and restarted after a
+backoff.  Backoff doubles with each failure (BACKOFF_MIN to BACKOFF_MAX) and is reset once a task has run for STABLE_RUN.
+A task with DEGRADED_FAILURES or more failures in DEGRADED_WINDOW is degraded (shown in api /healthz).
+Each run of a task gets its own stop signal (passed to the build function), triggered on shutdown or when a restart is
+requested (config reload).  A task stopped for restart is built again right away, with no backoff.  Tasks that end after
+shutdown is triggered are not restarted.
+*/
+
+// USE STATEMENTS
+    use std::{ any::Any, collections::{ BTreeMap, VecDeque }, future::Future, sync::{ Arc, Mutex } };
+    use chrono::{ DateTime, TimeDelta, Utc };
+    use log::{ error, info };
+    use tokio::{ sync::Notify, task::JoinHandle, time::sleep };
+
+    use crate::shutdown::Shutdown;
+
+// CONSTANTS
+    const BACKOFF_MIN: TimeDelta = TimeDelta::seconds(1);
+    const BACKOFF_MAX: TimeDelta = TimeDelta::minutes(5);
+    // a task that ran this long before failing starts again at BACKOFF_MIN
+    const STABLE_RUN: TimeDelta = TimeDelta::minutes(10);
+    const DEGRADED_FAILURES: usize = 3;
+    const DEGRADED_WINDOW: TimeDelta = TimeDelta::hours(1);
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+#[derive(Clone, Debug)]
+pub struct TaskStatus {
+    pub started: DateTime<Utc>,
+    pub restarts: u64,
+    pub last_failure: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    // failure times within DEGRADED_WINDOW
+    recent_failures: VecDeque<DateTime<Utc>>,
+}
+impl TaskStatus {
+    pub fn degraded(&self, now: DateTime<Utc>) -> bool {
+        self.recent_failures.iter().filter( |failure| now - **failure <= DEGRADED_WINDOW ).count() >= DEGRADED_FAILURES
+    }
+}
+
+#[derive(Clone)]
+pub struct TaskSupervisor {
+    tasks: Arc<Mutex<BTreeMap<String, TaskStatus>>>,
+    restart_signals: Arc<Mutex<BTreeMap<String, Arc<Notify>>>>,
+    shutdown: Shutdown,
+}
+
+impl TaskSupervisor {
+    pub fn new(shutdown: Shutdown) -> Self {
+        Self { tasks: Arc::new( Mutex::new( BTreeMap::new() ) ), restart_signals: Arc::new( Mutex::new( BTreeMap::new() ) ),
+            shutdown }
+    }
+
+    pub fn spawn<F, Fut>(&self, name: &str, make_task: F) -> JoinHandle<bool>
+    where
+        F: Fn(Shutdown) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        // first task is built now, so anything it registers (eg scheduler jobs) exists before spawn returns.
+        // Returned handle ends on shutdown (or abort) with true if the task stopped cleanly.
+        let mut stop = Shutdown::default();
+        let mut task = make_task( stop.clone() );
+        self.update_status( name, |_| {} );
+        let restart = Arc::new( Notify::new() );
+        if let Ok(mut restart_signals) = self.restart_signals.lock() {
+            restart_signals.insert( name.to_string(), restart.clone() );
+        }
+        let supervisor = self.clone();
+        let name = name.to_string();
+        tokio::spawn( async move {
+            let mut backoff = BACKOFF_MIN;
+            loop {
+                let started = Utc::now();
+                supervisor.update_status( &name, |status| status.started = started );
+                let mut handle = tokio::spawn(task);
+                // task ends by itself, or is stopped (and allowed to finish its work) for shutdown or restart
+                let mut restarting = false;
+                let result = tokio::select! {
+                    result = &mut handle => result,
+                    _ = supervisor.shutdown.wait() => {
+                        stop.trigger();
+                        handle.await
+                    },
+                    _ = restart.notified() => {
+                        restarting = true;
+                        stop.trigger();
+                        handle.await
+                    },
+                };
+                let reason = match result {
+                    Ok(()) if supervisor.shutdown.is_triggered() => {
+                        info!("Task {} stopped for shutdown.", name);
+                        return true
+                    },
+                    Ok(()) if restarting => {
+                        info!("Task {} restarting.", name);
+                        stop = Shutdown::default();
+                        task = make_task( stop.clone() );
+                        continue;
+                    },
+                    Ok(()) => "exited".to_string(),
+                    Err(join_eff) if join_eff.is_panic() => format!("panicked: {}", panic_message( join_eff.into_panic() )),
+                    Err(join_eff) => {
+                        // cancelled.  Task was aborted on purpose, so it isn't restarted
+                        info!("Task {} stopped. {}", name, join_eff);
+                        return false
+                    },
+                };
+
+                if supervisor.shutdown.is_triggered() {
+                    error!("Task {} {} during shutdown.", name, reason);
+                    return false
+                }
+                let now = Utc::now();
+                if now - started >= STABLE_RUN {
+                    backoff = BACKOFF_MIN;
+                }
+                error!("Task {} {}.  Restarting in {}s.", name, reason, backoff.num_seconds());
+                supervisor.update_status( &name, |status| {
+                    status.restarts += 1;
+                    status.last_failure = Some(now);
+                    status.last_error = Some(reason);
+                    status.recent_failures.push_back(now);
+                    while status.recent_failures.front().is_some_and( |failure| now - *failure > DEGRADED_WINDOW ) {
+                        status.recent_failures.pop_front();
+                    }
+                });
+
+                // a restart request (new configuration) ends the backoff early
+                tokio::select! {
+                    _ = sleep( backoff.to_std().unwrap_or_default() ) => {},
+                    _ = restart.notified() => {},
+                    _ = supervisor.shutdown.wait() => return true,
+                }
+                backoff = ( backoff * 2 ).min(BACKOFF_MAX);
+                stop = Shutdown::default();
+                task = make_task( stop.clone() );
+            }
+        })
+    }
+
+    pub fn restart(&self, name: &str) {
+        // stops the task once its current work is done and builds it again (eg with new configuration)
+        if let Some(restart) = self.restart_signals.lock().ok().and_then( |signals| signals.get(name).cloned() ) {
+            restart.notify_one();
+        }
+    }
+
+    pub fn task_statuses(&self) -> BTreeMap<String, TaskStatus> {
+        match self.tasks.lock() {
+            Ok(tasks) => tasks.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    fn update_status<U: FnOnce(&mut TaskStatus)>(&self, name: &str, update: U) {
+        if let Ok(mut tasks) = self.tasks.lock() {
+            let status = tasks.entry(name.to_string()).or_insert( TaskStatus {
+                started: Utc::now(),
+                restarts: 0,
+                last_failure: None,
+                last_error: None,
+                recent_failures: VecDeque::new(),
+            });
+            update(status);
+        }
+    }
+}
+
+// FUNCTIONS
+
+pub async fn run_or_idle<Fut: Future<Output = ()>>(task: Option<Fut>, stop: Shutdown) {
+    // runs an optional task.  A task that isn't configured waits until stopped instead, so it can be started by a restart.
+    match task {
+        Some(task) => task.await,
+        None => stop.wait().await,
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    // panic payload is a &str or String for panic!( ... ) and unwrap / expect
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
@@ -0,0 +1,58 @@
+/*
+systemd service notifications for Type=notify units.
+READY=1 is sent once collectors are started, STOPPING=1 on shutdown and WATCHDOG=1 every PVS6 poll loop (or from an idle loop
+if PVS6 isn't configured).  With WatchdogSec
+set in the unit, systemd restarts the service when the PVS6 collector stops looping (panicked or hung on a request).
+All functions do nothing when not started by systemd (NOTIFY_SOCKET not set).
+*/
+
+// USE STATEMENTS
+    use chrono::TimeDelta;
+    use log::{ debug, warn };
+    use sd_notify::NotifyState;
+
+    use crate::shutdown::Shutdown;
+
+// FUNCTIONS
+
+pub fn notify_ready(status: &str) {
+    if let Err(notify_eff) = sd_notify::notify( false, &[ NotifyState::Ready, NotifyState::Status(status) ] ) {
+        warn!("Couldn't send ready notification to systemd. Error: {}", notify_eff);
+    }
+}
+
+pub fn notify_stopping() {
+    if let Err(notify_eff) = sd_notify::notify( false, &[ NotifyState::Stopping ] ) {
+        warn!("Couldn't send stopping notification to systemd. Error: {}", notify_eff);
+    }
+}
+
+pub fn notify_watchdog() {
+    if let Err(notify_eff) = sd_notify::notify( false, &[ NotifyState::Watchdog ] ) {
+        debug!("Couldn't send watchdog notification to systemd. Error: {}", notify_eff);
+    }
+}
+
+pub fn watchdog_timeout() -> Option<TimeDelta> {
+    // WatchdogSec of the unit, if the watchdog is enabled for this process
+    let mut usec: u64 = 0;
+    if sd_notify::watchdog_enabled(false, &mut usec) {
+        Some( TimeDelta::microseconds( usec as i64 ) )
+    } else {
+        None
+    }
+}
+
+pub async fn watchdog_while_idle(stop: Shutdown) {
+    // pings the watchdog at half its timeout until stopped.  Used while the pvs6 collector, which normally pings it, isn't configured
+    let Some(interval) = watchdog_timeout().and_then( |timeout| ( timeout / 2 ).to_std().ok() ).filter( |interval| !interval.is_zero() ) else {
+        return stop.wait().await
+    };
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => notify_watchdog(),
+            _ = stop.wait() => return,
+        }
+    }
+}
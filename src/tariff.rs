@@ -0,0 +1,949 @@
+/*
+Time-of-use tariff (tariff section of config.yml).  Scheduled job that prices grid energy from the consumption meter lifetime
+counters: pos_ltea_3phsum_kwh is energy imported from the grid and neg_ltea_3phsum_kwh is energy exported to the grid.
+1)  Hourly import, export and production (production meter net_ltea_3phsum_kwh) are priced at the season / period rate of the
+    local hour and stored in tariff_interval: cost of import, credit for export, and savings against the same home load
+    (import - export + production) bought from the grid without solar.
+2)  Hours are totalled per billing period in tariff_billing_period with fixed charges and netting:
+        tou_period  net metering (NEM 1 / 2).  Import and export are netted per season / period over the billing period.  Net
+                    import is charged at the import price, net export credited at the export price.
+        none        net billing (NEM 3).  Every hour's import is charged and export credited separately.
+    Billing periods are the utility's billing cycles: each of read_dates (meter read dates) starts a period.  Before the first
+    and after the last read date, periods are monthly from billing_day.  Each period also has meter import / export from the
+    lifetime counter readings at its start and end (as the utility reads the meter, including energy across data gaps), and
+    totals for the true-up year so far.  True-up years start on true_up_date (month-day), and a period belongs to the true-up
+    year it starts in.  Totals include earlier periods of the year already in tariff_billing_period.
+Seasons run from their start (month-day) until the next season starts.  Periods are matched by the start of each local hour,
+first matching period wins.  Holidays are priced as weekends.
+Each run recalculates from the start of the billing period containing today - days, so late PVS6 data is picked up.  Stored
+periods overlapping the recalculated days that no longer match the billing periods (read_dates or billing_day changed) are
+deleted first, so they aren't counted twice in true-up totals.  Set days higher once after changing them to rebuild history.
+*/
+
+// USE STATEMENTS
+    use std::collections::{ BTreeMap, BTreeSet };
+    use chrono::{ DateTime, Datelike, Months, NaiveDate, TimeDelta, Timelike, Utc, Weekday };
+    use chrono_tz::Tz;
+    use log::{ error, info, warn };
+    use serde::Deserialize;
+
+    use crate::{ energy, scheduler::{ Job, Schedule }, validation::ConfigError };
+
+// CONSTANTS
+    // counter readings further apart than this are not used for hourly energy
+    const MAX_ENERGY_GAP: TimeDelta = TimeDelta::hours(1);
+    // longest time before a billing period start a counter reading is used as the meter read at the start
+    const METER_READ_LOOKBACK: TimeDelta = TimeDelta::days(1);
+    const NETTING: [&str; 2] = [ "tou_period", "none" ];
+    const PERIOD_DAYS: [&str; 3] = [ "all", "weekday", "weekend" ];
+    const REPLACE_TARIFF_INTERVAL_QUERY: &str =
+    r#"
+        REPLACE INTO tariff_interval
+            ( time, season, period, import_kwh, export_kwh, production_kwh, load_kwh, import_price, export_price, cost, credit,
+                no_solar_cost, savings )
+            VALUES ( ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ? )
+    "#;
+    const REPLACE_TARIFF_BILLING_PERIOD_QUERY: &str =
+    r#"
+        REPLACE INTO tariff_billing_period
+            ( period_start, period_end, days, complete, import_kwh, export_kwh, production_kwh, load_kwh, energy_cost, energy_credit,
+                fixed_charges, net_cost, no_solar_cost, savings, true_up_start, meter_import_kwh, meter_export_kwh, meter_net_kwh,
+                true_up_import_kwh, true_up_export_kwh, true_up_net_kwh, true_up_net_cost, true_up_savings, calc_time )
+            VALUES ( ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ? )
+    "#;
+    // sql query to get totals of a true-up year's billing periods ending on or before a date
+    const QUERY_GET_TRUE_UP_TOTALS: &str =
+    r#"
+        SELECT COALESCE( SUM(meter_import_kwh), 0 ), COALESCE( SUM(meter_export_kwh), 0 ), COALESCE( SUM(net_cost), 0 ),
+            COALESCE( SUM(savings), 0 )
+        FROM tariff_billing_period
+        WHERE true_up_start = ? AND period_end <= ?
+    "#;
+    // billing periods ending after a date that don't start on one of the current period starts (placeholders added per start)
+    const DELETE_STALE_BILLING_PERIODS_QUERY: &str = "DELETE FROM tariff_billing_period WHERE period_end > ? AND period_start NOT IN";
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+#[derive(Debug, Deserialize, Clone )]
+pub struct TariffConf {
+    // timezone of the tariff's hours and billing dates, eg "America/Los_Angeles"
+    #[serde( default = "default_tariff_timezone" )]
+    pub timezone: String,
+    // day of month (1 to 28) each billing period starts, outside read_dates
+    #[serde( default = "default_tariff_billing_day" )]
+    pub billing_day: u32,
+    // utility meter read dates (YYYY-MM-DD).  Each starts a billing period (billing cycle)
+    #[serde( default )]
+    pub read_dates: Vec<String>,
+    // first day of each true-up year, "MM-DD"
+    #[serde( default = "default_tariff_true_up_date" )]
+    pub true_up_date: String,
+    // "tou_period" (net metering) or "none" (net billing)
+    #[serde( default = "default_tariff_netting" )]
+    pub netting: String,
+    // fixed charge per day and per billing period
+    #[serde( default )]
+    pub daily_charge: f64,
+    #[serde( default )]
+    pub monthly_charge: f64,
+    // dates (YYYY-MM-DD) priced as weekends
+    #[serde( default )]
+    pub holidays: Vec<String>,
+    pub seasons: Vec<SeasonConf>,
+    #[serde( default = "default_tariff_interval" )]
+    pub interval: u64,
+    #[serde( default = "default_tariff_interval_unit" )]
+    pub interval_unit: char,
+    #[serde( with = "crate::integer_to_chrono_time_delta_ms", default = "default_tariff_offset" )]
+    pub offset: TimeDelta,
+    // cron expression or "every N<unit> [at <offset>]".  Used instead of interval / interval_unit / offset if provided
+    #[serde( default = "default_tariff_schedule" )]
+    pub schedule: String,
+    #[serde( with = "crate::integer_to_chrono_time_delta_ms", default = "default_tariff_jitter" )]
+    pub jitter: TimeDelta,
+    // days recalculated each run, from the start of the billing period containing today - days.  Set higher once to fill history
+    #[serde( default = "default_tariff_days" )]
+    pub days: i64,
+}
+fn default_tariff_timezone() -> String {
+    "UTC".to_string()
+}
+fn default_tariff_billing_day() -> u32 {
+    1
+}
+fn default_tariff_true_up_date() -> String {
+    "01-01".to_string()
+}
+fn default_tariff_netting() -> String {
+    "tou_period".to_string()
+}
+fn default_tariff_interval() -> u64 {
+    1
+}
+fn default_tariff_interval_unit() -> char {
+    'h'
+}
+fn default_tariff_offset() -> TimeDelta {
+    TimeDelta::minutes(5)
+}
+fn default_tariff_schedule() -> String {
+    String::new()
+}
+fn default_tariff_jitter() -> TimeDelta {
+    TimeDelta::zero()
+}
+fn default_tariff_days() -> i64 {
+    2
+}
+
+#[derive(Debug, Deserialize, Clone )]
+pub struct SeasonConf {
+    pub name: String,
+    // first day of season, "MM-DD"
+    pub start: String,
+    pub periods: Vec<PeriodConf>,
+}
+
+#[derive(Debug, Deserialize, Clone )]
+pub struct PeriodConf {
+    pub name: String,
+    // "all", "weekday" or "weekend"
+    #[serde( default = "default_period_days" )]
+    pub days: String,
+    // local hours start (0 to 23) to end (1 to 24, exclusive).  Wraps past midnight if end is before start
+    #[serde( default )]
+    pub start: u32,
+    #[serde( default = "default_period_end" )]
+    pub end: u32,
+    // price per kWh imported, and credit per kWh exported (default is import_price)
+    pub import_price: f64,
+    #[serde( default )]
+    pub export_price: Option<f64>,
+}
+fn default_period_days() -> String {
+    "all".to_string()
+}
+fn default_period_end() -> u32 {
+    24
+}
+
+// TariffConf with dates parsed.  Built from a verified conf
+#[derive(Clone, Debug)]
+pub struct Tariff {
+    conf: TariffConf,
+    tz: Tz,
+    holidays: BTreeSet<NaiveDate>,
+    read_dates: BTreeSet<NaiveDate>,
+    // ( month, day ) true-up years start
+    true_up: (u32, u32),
+    // ( month, day ) each season starts, same order as conf seasons
+    season_starts: Vec<(u32, u32)>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Rate<'a> {
+    pub season: &'a str,
+    pub period: &'a str,
+    pub import_price: f64,
+    pub export_price: f64,
+}
+
+#[derive(Clone, Debug)]
+struct IntervalCost {
+    time: DateTime<Utc>,
+    season: String,
+    period: String,
+    import_kwh: f64,
+    export_kwh: f64,
+    production_kwh: f64,
+    load_kwh: f64,
+    import_price: f64,
+    export_price: f64,
+}
+impl IntervalCost {
+    fn cost(&self) -> f64 {
+        self.import_kwh * self.import_price
+    }
+    fn credit(&self) -> f64 {
+        self.export_kwh * self.export_price
+    }
+    fn no_solar_cost(&self) -> f64 {
+        self.load_kwh * self.import_price
+    }
+    fn savings(&self) -> f64 {
+        self.no_solar_cost() - ( self.cost() - self.credit() )
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PeriodCost {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub days: i64,
+    pub complete: bool,
+    pub import_kwh: f64,
+    pub export_kwh: f64,
+    pub production_kwh: f64,
+    pub load_kwh: f64,
+    pub energy_cost: f64,
+    pub energy_credit: f64,
+    pub fixed_charges: f64,
+    pub no_solar_cost: f64,
+    pub true_up_start: NaiveDate,
+    // from lifetime counter readings at period start and end.  None without readings
+    pub meter_import_kwh: Option<f64>,
+    pub meter_export_kwh: Option<f64>,
+    // true-up year to the end of this period
+    pub true_up_import_kwh: f64,
+    pub true_up_export_kwh: f64,
+    pub true_up_net_cost: f64,
+    pub true_up_savings: f64,
+}
+impl PeriodCost {
+    pub fn net_cost(&self) -> f64 {
+        self.energy_cost - self.energy_credit + self.fixed_charges
+    }
+    pub fn savings(&self) -> f64 {
+        self.no_solar_cost - self.net_cost()
+    }
+    pub fn meter_net_kwh(&self) -> Option<f64> {
+        Some( self.meter_import_kwh? - self.meter_export_kwh? )
+    }
+}
+
+// tariff_billing_period row, for export
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct BillingPeriodRow {
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub days: i32,
+    pub complete: bool,
+    pub import_kwh: f64,
+    pub export_kwh: f64,
+    pub production_kwh: f64,
+    pub load_kwh: f64,
+    pub energy_cost: f64,
+    pub energy_credit: f64,
+    pub fixed_charges: f64,
+    pub net_cost: f64,
+    pub no_solar_cost: f64,
+    pub savings: f64,
+    pub true_up_start: NaiveDate,
+    pub meter_import_kwh: Option<f64>,
+    pub meter_export_kwh: Option<f64>,
+    pub meter_net_kwh: Option<f64>,
+    pub true_up_import_kwh: f64,
+    pub true_up_export_kwh: f64,
+    pub true_up_net_kwh: f64,
+    pub true_up_net_cost: f64,
+    pub true_up_savings: f64,
+    pub calc_time: DateTime<Utc>,
+}
+
+impl Tariff {
+    pub fn from_conf(conf: &TariffConf) -> Result<Self, Vec<ConfigError>> {
+        // parses and checks every tariff setting, including that each season prices every hour of weekdays and weekends
+        let mut errors = Vec::new();
+        let tz = conf.timezone.parse::<Tz>().unwrap_or_else( |_| {
+            errors.push( ConfigError::invalid("tariff.timezone", &conf.timezone, "Must be a valid timezone (eg America/Los_Angeles).") );
+            Tz::UTC
+        });
+        if !( 1..=28 ).contains(&conf.billing_day) {
+            errors.push( ConfigError::invalid("tariff.billing_day", conf.billing_day, "Must be 1 to 28.") );
+        }
+        if !NETTING.contains( &conf.netting.as_str() ) {
+            errors.push( ConfigError::invalid("tariff.netting", &conf.netting, &format!("Must be one of: {}", NETTING.join(", "))) );
+        }
+        if conf.daily_charge < 0.0 {
+            errors.push( ConfigError::invalid("tariff.daily_charge", conf.daily_charge, "Must not be negative.") );
+        }
+        if conf.monthly_charge < 0.0 {
+            errors.push( ConfigError::invalid("tariff.monthly_charge", conf.monthly_charge, "Must not be negative.") );
+        }
+        let holidays = parse_dates("tariff.holidays", &conf.holidays, &mut errors);
+        let read_dates = parse_dates("tariff.read_dates", &conf.read_dates, &mut errors);
+        let true_up = month_day(&conf.true_up_date).unwrap_or_else( |_| {
+            errors.push( ConfigError::invalid("tariff.true_up_date", &conf.true_up_date, "Must be month-day, eg \"03-14\".") );
+            (1, 1)
+        });
+        if conf.seasons.is_empty() {
+            errors.push( ConfigError::missing("tariff.seasons", "Add at least one season with its periods and prices.") );
+        }
+        let mut season_starts = Vec::new();
+        for (index, season) in conf.seasons.iter().enumerate() {
+            let key = format!("tariff.seasons.{}", index);
+            match month_day(&season.start) {
+                Ok(start) if season_starts.contains(&start) =>
+                    errors.push( ConfigError::invalid( &format!("{}.start", key), &season.start, "Another season starts on the same day." ) ),
+                Ok(start) => season_starts.push(start),
+                Err(_) => {
+                    errors.push( ConfigError::invalid( &format!("{}.start", key), &season.start, "Must be month-day, eg \"06-01\"." ) );
+                    season_starts.push( (1, 1) );
+                },
+            }
+            verify_season(&key, season, &mut errors);
+        }
+        if errors.is_empty() {
+            Ok( Self { conf: conf.clone(), tz, holidays, read_dates, true_up, season_starts } )
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn rate(&self, hour: DateTime<Utc>) -> Option<Rate<'_>> {
+        // season / period rate of the local hour starting at hour
+        let local = hour.with_timezone(&self.tz);
+        let date = local.date_naive();
+        let season = self.season(date)?;
+        let weekend = matches!( date.weekday(), Weekday::Sat | Weekday::Sun ) || self.holidays.contains(&date);
+        let period = season.periods.iter().find( |period| period_matches(period, weekend, local.hour()) )?;
+        Some( Rate {
+            season: &season.name,
+            period: &period.name,
+            import_price: period.import_price,
+            export_price: period.export_price.unwrap_or(period.import_price),
+        })
+    }
+
+    fn season(&self, date: NaiveDate) -> Option<&SeasonConf> {
+        // latest season start on or before date.  Before the first start of the year it is the last season of the year before
+        let day = (date.month(), date.day());
+        let started = self.season_starts.iter().enumerate().filter( |(_, start)| **start <= day ).max_by_key( |(_, start)| **start );
+        let (index, _) = started.or( self.season_starts.iter().enumerate().max_by_key( |(_, start)| **start ) )?;
+        self.conf.seasons.get(index)
+    }
+
+    pub fn billing_period(&self, date: NaiveDate) -> (NaiveDate, NaiveDate) {
+        // billing period ( start, end ) containing date.  end is the start of the next period.  Read dates are period starts,
+        // billing_day is used before the first and after the last.
+        let previous_read = self.read_dates.range(..=date).next_back().copied();
+        let next_read = self.read_dates.range( date + TimeDelta::days(1).. ).next().copied();
+        match ( previous_read, next_read ) {
+            ( Some(start), Some(end) ) => ( start, end ),
+            ( Some(last), None ) => {
+                let (_, end) = self.monthly_billing_period(last);
+                if date < end { ( last, end ) } else { self.monthly_billing_period(date) }
+            },
+            ( None, Some(first) ) => {
+                let (start, end) = self.monthly_billing_period(date);
+                ( start, end.min(first) )
+            },
+            ( None, None ) => self.monthly_billing_period(date),
+        }
+    }
+
+    fn monthly_billing_period(&self, date: NaiveDate) -> (NaiveDate, NaiveDate) {
+        // period from billing_day containing date
+        let month_start = date.with_day(1).unwrap_or(date);
+        let period_month = if date.day() >= self.conf.billing_day {
+            month_start
+        } else {
+            month_start.checked_sub_months( Months::new(1) ).unwrap_or(month_start)
+        };
+        let start = period_month.with_day(self.conf.billing_day).unwrap_or(period_month);
+        ( start, start.checked_add_months( Months::new(1) ).unwrap_or(start) )
+    }
+
+    pub fn true_up_start(&self, date: NaiveDate) -> NaiveDate {
+        // start of the true-up year containing date
+        let (month, day) = self.true_up;
+        let this_year = NaiveDate::from_ymd_opt(date.year(), month, day).unwrap_or(date);
+        if this_year <= date {
+            this_year
+        } else {
+            NaiveDate::from_ymd_opt(date.year() - 1, month, day).unwrap_or(date)
+        }
+    }
+}
+
+// FUNCTIONS
+
+pub async fn tariff_to_mysql(solar_pool: Option<sqlx::Pool<sqlx::MySql>>, tariff_conf: TariffConf, mut tariff_job: Job) {
+    // tariff verified at startup by verify_tariff_conf
+    let tariff = match Tariff::from_conf(&tariff_conf) {
+        Ok(tariff) => tariff,
+        Err(errors) => {
+            error!("Tariff not started. {}", crate::validation::report(&errors));
+            return
+        },
+    };
+    while tariff_job.tick().await.is_some() {
+        match &solar_pool {
+            Some(sql_pool) => {
+                let now = Utc::now();
+                let today = now.with_timezone(&tariff.tz).date_naive();
+                let (from, _) = tariff.billing_period( today - TimeDelta::days(tariff_conf.days) );
+                calculate_tariff(sql_pool, &tariff, from, now).await;
+            },
+            None => error!("Couldn't get sql pool"),
+        }
+    }
+}
+
+async fn calculate_tariff(sql_pool: &sqlx::Pool<sqlx::MySql>, tariff: &Tariff, from: NaiveDate, now: DateTime<Utc>) {
+    // prices every hour from the start of local day from to now, and totals the billing periods they are in
+    let start = energy::local_midnight_utc(from, tariff.tz);
+    let mut hourly = Vec::new();
+    let mut counters = Vec::new();
+    for (table, counter) in [
+        ("consumption_meters_data", "pos_ltea_3phsum_kwh"),
+        ("consumption_meters_data", "neg_ltea_3phsum_kwh"),
+        ("production_meters_data", "net_ltea_3phsum_kwh"),
+    ] {
+        // reading before start is needed for the first hour and the meter read at start
+        match energy::get_counter_readings(sql_pool, table, counter, start - METER_READ_LOOKBACK, now).await {
+            Ok(readings) => {
+                let mut energy = energy::total_bucket_energy(&readings, TimeDelta::hours(1), MAX_ENERGY_GAP);
+                energy.retain( |hour_start, _| *hour_start >= start );
+                hourly.push(energy);
+                counters.push(readings);
+            },
+            Err(read_eff) => {
+                error!("Tariff: couldn't read {} {} from {}. Error: {}", table, counter, start, read_eff);
+                return
+            },
+        }
+    }
+    let (imports, exports, production) = ( &hourly[0], &hourly[1], &hourly[2] );
+
+    // hours with consumption meter data.  Hours without production data have no production
+    let hours: BTreeSet<DateTime<Utc>> = imports.keys().chain( exports.keys() ).copied().collect();
+    let mut intervals = Vec::new();
+    for hour in hours {
+        let Some(rate) = tariff.rate(hour) else {
+            warn!("Tariff: no rate for {}.  Hour not priced.", hour);
+            continue
+        };
+        let import_kwh = imports.get(&hour).copied().unwrap_or(0.0);
+        let export_kwh = exports.get(&hour).copied().unwrap_or(0.0);
+        let production_kwh = production.get(&hour).copied().unwrap_or(0.0);
+        intervals.push( IntervalCost {
+            time: hour,
+            season: rate.season.to_string(),
+            period: rate.period.to_string(),
+            import_kwh,
+            export_kwh,
+            production_kwh,
+            load_kwh: ( import_kwh - export_kwh + production_kwh ).max(0.0),
+            import_price: rate.import_price,
+            export_price: rate.export_price,
+        });
+    }
+
+    let mut failed = 0;
+    for interval in &intervals {
+        if let Err(interval_eff) = replace_tariff_interval(sql_pool, interval).await {
+            error!("Tariff interval {} failed to upload to Mysql solar db. Error: {}", interval.time, interval_eff);
+            failed += 1;
+        }
+    }
+    let today = now.with_timezone(&tariff.tz).date_naive();
+    if let Err(delete_eff) = delete_stale_billing_periods(sql_pool, tariff, from, today).await {
+        error!("Tariff: couldn't delete old billing periods from Mysql solar db. Error: {}", delete_eff);
+        return
+    }
+    let mut periods = billing_period_costs(tariff, &intervals, today);
+    for period in periods.iter_mut() {
+        let period_start = energy::local_midnight_utc(period.start, tariff.tz);
+        let period_end = energy::local_midnight_utc(period.end, tariff.tz).min(now);
+        period.meter_import_kwh = meter_read_energy(&counters[0], period_start, period_end);
+        period.meter_export_kwh = meter_read_energy(&counters[1], period_start, period_end);
+    }
+    if let Err(true_up_eff) = add_true_up_totals(sql_pool, &mut periods).await {
+        error!("Tariff: couldn't read true-up totals from Mysql solar db. Error: {}", true_up_eff);
+        return
+    }
+    for period in &periods {
+        if let Err(period_eff) = replace_tariff_billing_period(sql_pool, period, now).await {
+            error!("Tariff billing period {} failed to upload to Mysql solar db. Error: {}", period.start, period_eff);
+            failed += 1;
+        }
+    }
+    match failed {
+        0 => info!("Tariff: {} hours and {} billing periods from {} uploaded to MySql solar db.", intervals.len(), periods.len(), from),
+        _ => warn!("Tariff: {} hours / billing periods failed to upload to MySql solar db.", failed),
+    }
+}
+
+fn billing_period_costs(tariff: &Tariff, intervals: &[IntervalCost], today: NaiveDate) -> Vec<PeriodCost> {
+    // totals hours per billing period.  Fixed daily charge is for days up to today in an incomplete period.
+    let mut by_period: BTreeMap<NaiveDate, Vec<&IntervalCost>> = BTreeMap::new();
+    for interval in intervals {
+        let (start, _) = tariff.billing_period( interval.time.with_timezone(&tariff.tz).date_naive() );
+        by_period.entry(start).or_default().push(interval);
+    }
+    by_period.into_iter().map( |(start, intervals)| {
+        let (_, end) = tariff.billing_period(start);
+        let complete = end <= today;
+        let days = ( end.min( today + TimeDelta::days(1) ) - start ).num_days();
+        let (energy_cost, energy_credit) = net_energy_cost(&tariff.conf.netting, &intervals);
+        PeriodCost {
+            start,
+            end,
+            days,
+            complete,
+            import_kwh: intervals.iter().map( |interval| interval.import_kwh ).sum(),
+            export_kwh: intervals.iter().map( |interval| interval.export_kwh ).sum(),
+            production_kwh: intervals.iter().map( |interval| interval.production_kwh ).sum(),
+            load_kwh: intervals.iter().map( |interval| interval.load_kwh ).sum(),
+            energy_cost,
+            energy_credit,
+            fixed_charges: tariff.conf.monthly_charge + tariff.conf.daily_charge * days as f64,
+            no_solar_cost: intervals.iter().map( |interval| interval.no_solar_cost() ).sum::<f64>()
+                + tariff.conf.monthly_charge + tariff.conf.daily_charge * days as f64,
+            true_up_start: tariff.true_up_start(start),
+            ..Default::default()
+        }
+    }).collect()
+}
+
+fn billing_period_starts(tariff: &Tariff, from: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+    // start of each billing period from the one containing from to the one containing to
+    let mut starts = Vec::new();
+    let (mut start, _) = tariff.billing_period(from);
+    while start <= to {
+        starts.push(start);
+        let (_, end) = tariff.billing_period(start);
+        start = end;
+    }
+    starts
+}
+
+async fn delete_stale_billing_periods(sql_pool: &sqlx::Pool<sqlx::MySql>, tariff: &Tariff, from: NaiveDate, today: NaiveDate)
+    -> Result<(), sqlx::Error> {
+    // deletes stored periods overlapping from to today that don't start on a current billing period start
+    let starts = billing_period_starts(tariff, from, today);
+    let query = format!("{} ( {} )", DELETE_STALE_BILLING_PERIODS_QUERY, vec!["?"; starts.len()].join(", "));
+    let mut delete = sqlx::query(&query).bind(from);
+    for start in starts {
+        delete = delete.bind(start);
+    }
+    let deleted = delete.execute(sql_pool).await?;
+    if deleted.rows_affected() > 0 {
+        info!("Tariff: {} billing period(s) from {} no longer match read_dates / billing_day and were deleted.",
+            deleted.rows_affected(), from);
+    }
+    Ok(())
+}
+
+fn meter_read_energy(readings: &BTreeMap<String, Vec<(DateTime<Utc>, f64)>>, start: DateTime<Utc>, end: DateTime<Utc>) -> Option<f64> {
+    // energy::meter_read_energy summed over all devices.  None if no device has readings
+    readings.values().filter_map( |device_readings| energy::meter_read_energy(device_readings, start, end) )
+        .fold( None, |total, kwh| Some( total.unwrap_or(0.0) + kwh ) )
+}
+
+async fn add_true_up_totals(sql_pool: &sqlx::Pool<sqlx::MySql>, periods: &mut [PeriodCost]) -> Result<(), sqlx::Error> {
+    // running totals of each true-up year, starting from its periods already stored before the first recalculated period.
+    // Periods are in date order.
+    let mut totals: Option<(NaiveDate, f64, f64, f64, f64)> = None;
+    for period in periods.iter_mut() {
+        let (_, import_kwh, export_kwh, net_cost, savings) = match totals {
+            Some(year_totals) if year_totals.0 == period.true_up_start => year_totals,
+            _ => {
+                let (import_kwh, export_kwh, net_cost, savings) = sqlx::query_as::<_, (f64, f64, f64, f64)>(QUERY_GET_TRUE_UP_TOTALS)
+                    .bind(period.true_up_start)
+                    .bind(period.start)
+                    .fetch_one(sql_pool).await?;
+                ( period.true_up_start, import_kwh, export_kwh, net_cost, savings )
+            },
+        };
+        period.true_up_import_kwh = import_kwh + period.meter_import_kwh.unwrap_or(0.0);
+        period.true_up_export_kwh = export_kwh + period.meter_export_kwh.unwrap_or(0.0);
+        period.true_up_net_cost = net_cost + period.net_cost();
+        period.true_up_savings = savings + period.savings();
+        totals = Some( ( period.true_up_start, period.true_up_import_kwh, period.true_up_export_kwh, period.true_up_net_cost,
+            period.true_up_savings ) );
+    }
+    Ok(())
+}
+
+fn net_energy_cost(netting: &str, intervals: &[&IntervalCost]) -> (f64, f64) {
+    // ( energy cost, energy credit ) of a billing period
+    if netting != "tou_period" {
+        return ( intervals.iter().map( |interval| interval.cost() ).sum(), intervals.iter().map( |interval| interval.credit() ).sum() )
+    }
+    // ( season, period ) -> ( net import kWh, import price, export price )
+    let mut net: BTreeMap<(&str, &str), (f64, f64, f64)> = BTreeMap::new();
+    for interval in intervals {
+        let entry = net.entry( (interval.season.as_str(), interval.period.as_str()) )
+            .or_insert( (0.0, interval.import_price, interval.export_price) );
+        entry.0 += interval.import_kwh - interval.export_kwh;
+    }
+    net.values().fold( (0.0, 0.0), |(cost, credit), (net_kwh, import_price, export_price)| {
+        if *net_kwh > 0.0 { ( cost + net_kwh * import_price, credit ) } else { ( cost, credit - net_kwh * export_price ) }
+    })
+}
+
+async fn replace_tariff_interval(sql_pool: &sqlx::Pool<sqlx::MySql>, interval: &IntervalCost) -> Result<(), sqlx::Error> {
+    sqlx::query(REPLACE_TARIFF_INTERVAL_QUERY)
+        .bind(interval.time)
+        .bind(&interval.season)
+        .bind(&interval.period)
+        .bind(interval.import_kwh)
+        .bind(interval.export_kwh)
+        .bind(interval.production_kwh)
+        .bind(interval.load_kwh)
+        .bind(interval.import_price)
+        .bind(interval.export_price)
+        .bind(interval.cost())
+        .bind(interval.credit())
+        .bind(interval.no_solar_cost())
+        .bind(interval.savings())
+        .execute(sql_pool).await?;
+    Ok(())
+}
+
+async fn replace_tariff_billing_period(sql_pool: &sqlx::Pool<sqlx::MySql>, period: &PeriodCost, calc_time: DateTime<Utc>)
+    -> Result<(), sqlx::Error> {
+    sqlx::query(REPLACE_TARIFF_BILLING_PERIOD_QUERY)
+        .bind(period.start)
+        .bind(period.end)
+        .bind(period.days)
+        .bind(period.complete)
+        .bind(period.import_kwh)
+        .bind(period.export_kwh)
+        .bind(period.production_kwh)
+        .bind(period.load_kwh)
+        .bind(period.energy_cost)
+        .bind(period.energy_credit)
+        .bind(period.fixed_charges)
+        .bind(period.net_cost())
+        .bind(period.no_solar_cost)
+        .bind(period.savings())
+        .bind(period.true_up_start)
+        .bind(period.meter_import_kwh)
+        .bind(period.meter_export_kwh)
+        .bind(period.meter_net_kwh())
+        .bind(period.true_up_import_kwh)
+        .bind(period.true_up_export_kwh)
+        .bind(period.true_up_import_kwh - period.true_up_export_kwh)
+        .bind(period.true_up_net_cost)
+        .bind(period.true_up_savings)
+        .bind(calc_time)
+        .execute(sql_pool).await?;
+    Ok(())
+}
+
+fn parse_dates(key: &str, dates: &[String], errors: &mut Vec<ConfigError>) -> BTreeSet<NaiveDate> {
+    let mut parsed = BTreeSet::new();
+    for (index, date) in dates.iter().enumerate() {
+        match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            Ok(date) => { parsed.insert(date); },
+            Err(_) => errors.push( ConfigError::invalid( &format!("{}.{}", key, index), date, "Must be a date, eg \"2025-12-25\"." ) ),
+        }
+    }
+    parsed
+}
+
+fn month_day(month_day: &str) -> Result<(u32, u32), chrono::ParseError> {
+    // "MM-DD" to ( month, day ).  Checked against a leap year, so "02-29" is accepted
+    NaiveDate::parse_from_str( &format!("2000-{}", month_day), "%Y-%m-%d" ).map( |date| (date.month(), date.day()) )
+}
+
+fn period_matches(period: &PeriodConf, weekend: bool, hour: u32) -> bool {
+    let day_matches = match period.days.as_str() {
+        "weekday" => !weekend,
+        "weekend" => weekend,
+        _ => true,
+    };
+    let hour_matches = if period.start < period.end {
+        ( period.start..period.end ).contains(&hour)
+    } else {
+        hour >= period.start || hour < period.end
+    };
+    day_matches && hour_matches
+}
+
+fn verify_season(key: &str, season: &SeasonConf, errors: &mut Vec<ConfigError>) {
+    if season.name.trim().is_empty() {
+        errors.push( ConfigError::missing( &format!("{}.name", key), "" ) );
+    }
+    if season.periods.is_empty() {
+        errors.push( ConfigError::missing( &format!("{}.periods", key), "Add at least one period with its prices." ) );
+    }
+    for (index, period) in season.periods.iter().enumerate() {
+        let key = format!("{}.periods.{}", key, index);
+        if period.name.trim().is_empty() {
+            errors.push( ConfigError::missing( &format!("{}.name", key), "" ) );
+        }
+        if !PERIOD_DAYS.contains( &period.days.as_str() ) {
+            errors.push( ConfigError::invalid( &format!("{}.days", key), &period.days, &format!("Must be one of: {}", PERIOD_DAYS.join(", ")) ) );
+        }
+        if period.start > 23 {
+            errors.push( ConfigError::invalid( &format!("{}.start", key), period.start, "Must be an hour 0 to 23." ) );
+        }
+        if !( 1..=24 ).contains(&period.end) || period.end == period.start {
+            errors.push( ConfigError::invalid( &format!("{}.end", key), period.end, "Must be an hour 1 to 24, not the same as start." ) );
+        }
+        if period.import_price < 0.0 {
+            errors.push( ConfigError::invalid( &format!("{}.import_price", key), period.import_price, "Must not be negative." ) );
+        }
+        if let Some(export_price) = period.export_price && export_price < 0.0 {
+            errors.push( ConfigError::invalid( &format!("{}.export_price", key), export_price, "Must not be negative." ) );
+        }
+    }
+    for (weekend, days) in [ (false, "weekdays"), (true, "weekends") ] {
+        let uncovered: Vec<String> = ( 0..24 )
+            .filter( |hour| !season.periods.iter().any( |period| period_matches(period, weekend, *hour) ) )
+            .map( |hour| hour.to_string() )
+            .collect();
+        if !season.periods.is_empty() && !uncovered.is_empty() {
+            errors.push( ConfigError::invalid( &format!("{}.periods", key), season.name.as_str(),
+                &format!("Hours {} on {} are not in any period.", uncovered.join(", "), days) ) );
+        }
+    }
+}
+
+pub fn verify_tariff_conf(tariff_conf: &TariffConf, errors: &mut Vec<ConfigError>) {
+    // verifies seasons, periods, prices, billing day, schedule and days.  Adds a ConfigError to errors for each problem
+    if let Err(tariff_errors) = Tariff::from_conf(tariff_conf) {
+        errors.extend(tariff_errors);
+    }
+    if !matches!( tariff_conf.interval_unit, 'd' | 'h' | 'm' | 's') {
+        errors.push( ConfigError::invalid("tariff.interval_unit", tariff_conf.interval_unit, "Must be 'd', 'h', 'm', or 's'.") );
+    } else if let Err(schedule_eff) = Schedule::from_conf(&tariff_conf.schedule, tariff_conf.interval, tariff_conf.interval_unit,
+        tariff_conf.offset) {
+        errors.push( ConfigError::invalid("tariff.schedule", &tariff_conf.schedule, &schedule_eff.to_string()) );
+    }
+    if tariff_conf.days < 0 {
+        errors.push( ConfigError::invalid("tariff.days", tariff_conf.days, "Must not be negative.") );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    fn tariff(read_dates: &[&str], billing_day: u32, netting: &str) -> Tariff {
+        // one season.  Weekday peak 16 to 21, off peak 21 to 16 (wraps past midnight), weekend peak hours are mid peak
+        let conf: TariffConf = serde_json::from_value( json!({
+            "timezone": "America/Los_Angeles",
+            "billing_day": billing_day,
+            "read_dates": read_dates,
+            "true_up_date": "03-14",
+            "netting": netting,
+            "holidays": ["2025-07-04"],
+            "seasons": [{
+                "name": "all_year",
+                "start": "01-01",
+                "periods": [
+                    { "name": "peak", "days": "weekday", "start": 16, "end": 21, "import_price": 0.40, "export_price": 0.10 },
+                    { "name": "off_peak", "start": 21, "end": 16, "import_price": 0.20 },
+                    { "name": "mid_peak", "start": 16, "end": 21, "import_price": 0.30 },
+                ],
+            }],
+        }) ).unwrap();
+        Tariff::from_conf(&conf).unwrap()
+    }
+
+    fn interval(period: &str, import_kwh: f64, export_kwh: f64, import_price: f64, export_price: f64) -> IntervalCost {
+        IntervalCost {
+            time: Utc::now(),
+            season: "all_year".to_string(),
+            period: period.to_string(),
+            import_kwh,
+            export_kwh,
+            production_kwh: 0.0,
+            load_kwh: 0.0,
+            import_price,
+            export_price,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!( ( actual - expected ).abs() < 1e-9, "{} != {}", actual, expected );
+    }
+
+    #[test]
+    fn billing_period_from_billing_day() {
+        let tariff = tariff(&[], 15, "tou_period");
+        assert_eq!( tariff.billing_period(date("2025-03-10")), ( date("2025-02-15"), date("2025-03-15") ) );
+        assert_eq!( tariff.billing_period(date("2025-03-15")), ( date("2025-03-15"), date("2025-04-15") ) );
+        assert_eq!( tariff.billing_period(date("2025-01-05")), ( date("2024-12-15"), date("2025-01-15") ) );
+    }
+
+    #[test]
+    fn billing_period_between_read_dates() {
+        let tariff = tariff(&["2025-01-08", "2025-02-07", "2025-03-10"], 1, "tou_period");
+        assert_eq!( tariff.billing_period(date("2025-02-07")), ( date("2025-02-07"), date("2025-03-10") ) );
+        assert_eq!( tariff.billing_period(date("2025-02-20")), ( date("2025-02-07"), date("2025-03-10") ) );
+        assert_eq!( tariff.billing_period(date("2025-03-09")), ( date("2025-02-07"), date("2025-03-10") ) );
+    }
+
+    #[test]
+    fn billing_period_before_first_read_date() {
+        // monthly from billing_day, with the period before the first read date cut short
+        let tariff = tariff(&["2025-01-08", "2025-02-07"], 1, "tou_period");
+        assert_eq!( tariff.billing_period(date("2025-01-03")), ( date("2025-01-01"), date("2025-01-08") ) );
+        assert_eq!( tariff.billing_period(date("2024-12-20")), ( date("2024-12-01"), date("2025-01-01") ) );
+    }
+
+    #[test]
+    fn billing_period_after_last_read_date() {
+        // last read date runs to the next billing_day, then monthly
+        let tariff = tariff(&["2025-01-08", "2025-03-10"], 1, "tou_period");
+        assert_eq!( tariff.billing_period(date("2025-03-10")), ( date("2025-03-10"), date("2025-04-01") ) );
+        assert_eq!( tariff.billing_period(date("2025-03-31")), ( date("2025-03-10"), date("2025-04-01") ) );
+        assert_eq!( tariff.billing_period(date("2025-04-01")), ( date("2025-04-01"), date("2025-05-01") ) );
+        assert_eq!( tariff.billing_period(date("2025-05-20")), ( date("2025-05-01"), date("2025-06-01") ) );
+    }
+
+    #[test]
+    fn billing_periods_chain() {
+        // each period ends where the next starts, across read dates and billing_day periods
+        let tariff = tariff(&["2025-01-08", "2025-02-07", "2025-03-10"], 20, "tou_period");
+        let mut start = tariff.billing_period(date("2024-11-01")).0;
+        while start < date("2025-06-01") {
+            let (period_start, end) = tariff.billing_period(start);
+            assert_eq!(period_start, start);
+            assert!(end > start);
+            assert_eq!( tariff.billing_period( end - TimeDelta::days(1) ), ( start, end ) );
+            start = end;
+        }
+    }
+
+    #[test]
+    fn billing_period_starts_in_range() {
+        let tariff = tariff(&["2025-02-07", "2025-03-10"], 1, "tou_period");
+        assert_eq!( billing_period_starts(&tariff, date("2025-01-15"), date("2025-04-02")),
+            vec![ date("2025-01-01"), date("2025-02-01"), date("2025-02-07"), date("2025-03-10"), date("2025-04-01") ] );
+        assert_eq!( billing_period_starts(&tariff, date("2025-03-12"), date("2025-03-12")), vec![ date("2025-03-10") ] );
+    }
+
+    #[test]
+    fn true_up_start_on_and_before_date() {
+        let tariff = tariff(&[], 1, "tou_period");
+        assert_eq!( tariff.true_up_start(date("2025-03-14")), date("2025-03-14") );
+        assert_eq!( tariff.true_up_start(date("2025-03-13")), date("2024-03-14") );
+        assert_eq!( tariff.true_up_start(date("2025-12-31")), date("2025-03-14") );
+    }
+
+    #[test]
+    fn period_wraps_past_midnight() {
+        let off_peak = PeriodConf { name: "off_peak".to_string(), days: "all".to_string(), start: 21, end: 6, import_price: 0.2,
+            export_price: None };
+        for hour in [21, 23, 0, 5] {
+            assert!( period_matches(&off_peak, false, hour), "hour {}", hour );
+        }
+        for hour in [6, 12, 20] {
+            assert!( !period_matches(&off_peak, false, hour), "hour {}", hour );
+        }
+    }
+
+    #[test]
+    fn period_days_and_full_day() {
+        let weekend = PeriodConf { name: "weekend".to_string(), days: "weekend".to_string(), start: 0, end: 24, import_price: 0.2,
+            export_price: None };
+        assert!( period_matches(&weekend, true, 0) );
+        assert!( period_matches(&weekend, true, 23) );
+        assert!( !period_matches(&weekend, false, 12) );
+    }
+
+    #[test]
+    fn rate_of_local_hour() {
+        let tariff = tariff(&[], 1, "tou_period");
+        // Wednesday 2025-07-02 17:00 and 23:00 PDT, Friday 2025-07-04 (holiday) 17:00 PDT
+        let rate = |time: &str| tariff.rate( DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc) ).unwrap().period;
+        assert_eq!( rate("2025-07-03T00:00:00Z"), "peak" );
+        assert_eq!( rate("2025-07-03T06:00:00Z"), "off_peak" );
+        assert_eq!( rate("2025-07-05T00:00:00Z"), "mid_peak" );
+    }
+
+    #[test]
+    fn net_metering_nets_each_period_separately() {
+        // peak: 2 kWh import, 3 kWh export -> 1 kWh net export credited at peak export price.
+        // off peak: 2 kWh import, 0.5 kWh export -> 1.5 kWh net import charged at off peak import price.
+        let intervals = [
+            interval("peak", 2.0, 0.0, 0.40, 0.10),
+            interval("peak", 0.0, 3.0, 0.40, 0.10),
+            interval("off_peak", 2.0, 0.5, 0.20, 0.20),
+        ];
+        let intervals: Vec<&IntervalCost> = intervals.iter().collect();
+        let (cost, credit) = net_energy_cost("tou_period", &intervals);
+        assert_close(cost, 1.5 * 0.20);
+        assert_close(credit, 1.0 * 0.10);
+    }
+
+    #[test]
+    fn net_billing_prices_every_interval() {
+        let intervals = [
+            interval("peak", 2.0, 0.0, 0.40, 0.10),
+            interval("peak", 0.0, 3.0, 0.40, 0.10),
+            interval("off_peak", 2.0, 0.5, 0.20, 0.20),
+        ];
+        let intervals: Vec<&IntervalCost> = intervals.iter().collect();
+        let (cost, credit) = net_energy_cost("none", &intervals);
+        assert_close(cost, 2.0 * 0.40 + 2.0 * 0.20);
+        assert_close(credit, 3.0 * 0.10 + 0.5 * 0.20);
+    }
+
+    #[test]
+    fn billing_period_costs_group_by_period() {
+        // hours on both sides of a read date (local time, PDT from 2025-03-09) go to different billing periods.  Fixed charges
+        // are per period
+        let mut tariff = tariff(&["2025-02-07", "2025-03-10"], 1, "none");
+        tariff.conf.monthly_charge = 10.0;
+        tariff.conf.daily_charge = 0.5;
+        let hour = |time: &str| DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc);
+        let intervals = [
+            IntervalCost { time: hour("2025-03-10T06:00:00Z"), ..interval("off_peak", 1.0, 0.0, 0.20, 0.20) },
+            IntervalCost { time: hour("2025-03-10T07:00:00Z"), ..interval("off_peak", 2.0, 0.0, 0.20, 0.20) },
+        ];
+        let periods = billing_period_costs(&tariff, &intervals, date("2025-03-20"));
+        assert_eq!(periods.len(), 2);
+        assert_eq!( ( periods[0].start, periods[0].end, periods[0].days ), ( date("2025-02-07"), date("2025-03-10"), 31 ) );
+        assert!(periods[0].complete);
+        assert_close(periods[0].import_kwh, 1.0);
+        assert_close(periods[0].fixed_charges, 10.0 + 0.5 * 31.0);
+        // incomplete period is charged daily up to today
+        assert_eq!( ( periods[1].start, periods[1].end, periods[1].days ), ( date("2025-03-10"), date("2025-04-01"), 11 ) );
+        assert!(!periods[1].complete);
+        assert_close(periods[1].import_kwh, 2.0);
+        assert_close(periods[1].net_cost(), 2.0 * 0.20 + 10.0 + 0.5 * 11.0);
+    }
+}
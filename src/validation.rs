@@ -0,0 +1,110 @@
+/*
+Configuration validation.
+verify_*_conf functions check every setting and add a ConfigError for each problem instead of stopping at the first one, so
+one run shows everything that needs fixing.  At startup the errors are printed as a report and the program exits with
+EXIT_CONFIG_ERROR.  On config reload the report is logged and the current config is kept.
+Settings that aren't used by any section (eg misspelled keys) are warned about, not rejected.
+*/
+
+// USE STATEMENTS
+    use std::{ collections::BTreeMap, fmt };
+
+// CONSTANTS
+    // exit code for invalid configuration.  Use RestartPreventExitStatus=2 in a systemd unit so it isn't restarted in a loop.
+    pub const EXIT_CONFIG_ERROR: i32 = 2;
+    // every setting Conf reads, by section.  Array items are "*", eg retention.tables.*.keep_days
+    const KNOWN_SETTINGS: [(&str, &[&str]); 21] = [
+        ( "pirate_wx", &[ "lat", "long", "units", "api_key_path", "api_key", "interval", "interval_unit", "offset", "schedule",
+            "jitter", "hourly_forecast_hours", "minutely_forecast", "alerts", "alert_notify_severities", "alert_notify_keywords",
+            "alert_webhook_url" ] ),
+        ( "pvs6", &[ "host", "get_device_interval", "get_device_interval_unit", "get_device_offset", "get_device_schedule",
+            "get_device_jitter", "night_mode", "night_interval", "night_interval_unit", "daylight_margin", "missed_tick_behavior",
+            "auto_tune_offset", "max_inverter_kw", "max_meter_kw" ] ),
+        ( "open_meteo", &[ "lat", "long", "units", "url", "forecast_days", "hourly_forecast_hours", "interval", "interval_unit", "offset",
+            "schedule", "jitter" ] ),
+        ( "wx_station", &[ "url", "lat", "long", "fields", "interval", "interval_unit", "offset", "schedule", "jitter" ] ),
+        ( "wx_station.fields", &crate::weather::STATION_FIELDS ),
+        ( "plausibility", &[ "action", "production_meter", "consumption_meter", "inverter" ] ),
+        ( "plausibility.production_meter", &crate::plausibility::PRODUCTION_METER_FIELDS ),
+        ( "plausibility.consumption_meter", &crate::plausibility::CONSUMPTION_METER_FIELDS ),
+        ( "plausibility.inverter", &crate::plausibility::INVERTER_FIELDS ),
+        ( "pws", &[ "mode", "bind", "passkey", "url", "fields", "units", "interval", "interval_unit", "offset", "schedule", "jitter",
+            "max_age" ] ),
+        ( "pws.fields", &crate::pws::PWS_FIELDS ),
+        ( "mysql", &[ "login_info_loc", "login_path", "host", "port", "database", "user", "password", "max_connections" ] ),
+        ( "site", &[ "lat", "long", "capacity_kw", "tilt", "azimuth", "losses" ] ),
+        ( "forecast", &[ "interval", "interval_unit", "offset", "schedule", "jitter", "history_days", "forecast_days", "timezone" ] ),
+        ( "tariff", &[ "timezone", "billing_day", "read_dates", "true_up_date", "netting", "daily_charge", "monthly_charge", "holidays",
+            "seasons", "interval", "interval_unit", "offset", "schedule", "jitter", "days" ] ),
+        ( "tariff.seasons.*", &[ "name", "start", "periods" ] ),
+        ( "tariff.seasons.*.periods.*", &[ "name", "days", "start", "end", "import_price", "export_price" ] ),
+        ( "reconciliation", &[ "timezone", "tolerance_pct", "tolerance_kwh", "interval", "interval_unit", "offset", "schedule", "jitter",
+            "days" ] ),
+        ( "retention", &[ "schedule", "jitter", "archive_dir", "archive_format", "tables" ] ),
+        ( "retention.tables.*", &[ "table", "keep_days", "downsample", "archive" ] ),
+        ( "api", &[ "bind", "timezone", "max_history_days", "health_max_pvs6_age", "health_max_wx_age" ] ),
+    ];
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    // settings couldn't be read or don't have the right type (eg text for a number)
+    Unreadable { error: String },
+    // required setting is missing or empty
+    Missing { key: String, detail: String },
+    // setting has a value that can't be used
+    Invalid { key: String, value: String, expected: String },
+    // file named by a setting (api_key_path, login_info_loc) can't be read
+    File { key: String, path: String, error: String },
+}
+
+impl ConfigError {
+    pub fn missing(key: &str, detail: &str) -> Self {
+        Self::Missing { key: key.to_string(), detail: detail.to_string() }
+    }
+
+    pub fn invalid<V: fmt::Display>(key: &str, value: V, expected: &str) -> Self {
+        Self::Invalid { key: key.to_string(), value: value.to_string(), expected: expected.to_string() }
+    }
+
+    pub fn file<E: fmt::Display>(key: &str, path: &str, error: E) -> Self {
+        Self::File { key: key.to_string(), path: path.to_string(), error: error.to_string() }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unreadable { error } => write!(f, "{}", error),
+            Self::Missing { key, detail } if detail.is_empty() => write!(f, "{}: missing or empty", key),
+            Self::Missing { key, detail } => write!(f, "{}: missing or empty. {}", key, detail),
+            Self::Invalid { key, value, expected } => write!(f, "{}: \"{}\" is not valid. {}", key, value, expected),
+            Self::File { key, path, error } => write!(f, "{}: couldn't read {}. {}", key, path, error),
+        }
+    }
+}
+
+// FUNCTIONS
+
+pub fn report(errors: &[ConfigError]) -> String {
+    // human readable list of errors, one per line
+    let lines: Vec<String> = errors.iter().map( |config_eff| format!("  - {}", config_eff) ).collect();
+    format!("Configuration has {} error(s):\n{}", errors.len(), lines.join("\n"))
+}
+
+pub fn unknown_keys(settings: &BTreeMap<String, String>) -> Vec<String> {
+    // flattened settings (see reload::flatten_settings) that Conf doesn't read
+    settings.keys().filter( |key| !is_known_key(key) ).cloned().collect()
+}
+
+fn is_known_key(key: &str) -> bool {
+    // numbered array items are "*".  Items of a list setting (alert_notify_keywords.0) are the list setting.
+    let mut parts: Vec<&str> = key.split('.').map( |part| if part.parse::<usize>().is_ok() { "*" } else { part } ).collect();
+    while parts.last() == Some(&"*") {
+        parts.pop();
+    }
+    let Some( (setting, section) ) = parts.split_last() else { return false };
+    let section = section.join(".");
+    KNOWN_SETTINGS.iter().any( |(known_section, settings)| *known_section == key || ( *known_section == section && settings.contains(setting) ) )
+}
@@ -0,0 +1,647 @@
+/*
+Weather providers.  Each provider gets current conditions (and forecast if it has one) and maps them into Wx, the Pirate
+Weather response model, so every provider is stored the same way in current_wx, daily_wx and the forecast tables.
+    pirate_wx   Pirate Weather API (api key).  Current, minutely / hourly / daily forecast and alerts.
+    open_meteo  Open-Meteo forecast API (no api key).  Current, hourly and daily forecast.
+    wx_station  local weather station JSON endpoint (eg Ecowitt, WeeWX).  Current conditions only.  Each setting in fields is a
+                JSON pointer to the value in the response, eg temperature: "/outdoor/temperature".
+Only one provider can be configured, as weather tables don't record which provider a row came from.
+Values are in units ("us", "ca", "uk" or "si", see Pirate Weather docs).  Humidity, cloud cover and precip probability are
+fractions 0 to 1.  Station values are stored as the station reports them, except humidity (percent) which is made a fraction.
+*/
+
+// USE STATEMENTS
+    use std::{ collections::BTreeMap, future::Future };
+    use chrono::{ DateTime, TimeDelta, Utc };
+    use log::{ debug, error, info };
+    use serde::Deserialize;
+
+    use crate::{ CurrentWx, DailyWx, DailyWxData, HourlyWx, HourlyWxData, PirateWxConf, Wx, WxAlert, api::Latest,
+        scheduler::{ Job, Schedule }, validation::ConfigError };
+
+// CONSTANTS
+    // weather units.  Same as Pirate Weather units
+    pub const WX_UNITS: [&str; 4] = [ "us", "ca", "uk", "si" ];
+    // config sections of weather providers
+    pub const WX_PROVIDERS: [&str; 3] = [ "pirate_wx", "open_meteo", "wx_station" ];
+    // CurrentWx values a station can report.  Keys of wx_station fields
+    pub const STATION_FIELDS: [&str; 13] = [ "time", "temperature", "apparent_temperature", "dew_point", "humidity", "pressure",
+        "wind_speed", "wind_gust", "wind_bearing", "precip_intensity", "uv_index", "visibility", "current_day_liquid" ];
+    const OPEN_METEO_CURRENT: &str = "temperature_2m,relative_humidity_2m,apparent_temperature,dew_point_2m,precipitation,snowfall,\
+        weather_code,cloud_cover,pressure_msl,wind_speed_10m,wind_direction_10m,wind_gusts_10m,uv_index,visibility,is_day";
+    const OPEN_METEO_HOURLY: &str = "temperature_2m,relative_humidity_2m,apparent_temperature,dew_point_2m,precipitation,\
+        precipitation_probability,weather_code,cloud_cover,pressure_msl,wind_speed_10m,wind_direction_10m,wind_gusts_10m,\
+        uv_index,visibility,is_day";
+    const OPEN_METEO_DAILY: &str = "weather_code,temperature_2m_max,temperature_2m_min,sunrise,sunset,uv_index_max,precipitation_sum,\
+        precipitation_probability_max,cloud_cover_mean";
+    const METERS_PER_MILE: f32 = 1609.344;
+
+// ENUMS, STRUCTURES AND IMPLEMENTATIONS
+
+pub trait WeatherProvider: Send + Sync + 'static {
+    // provider name, as its config section
+    fn name(&self) -> &'static str;
+
+    // current conditions and forecast.  None (after logging why) if they couldn't be retrieved.
+    fn fetch(&self) -> impl Future<Output = Option<Wx>> + Send;
+
+    // hours of hourly forecast stored
+    fn hourly_forecast_hours(&self) -> i64 {
+        0
+    }
+
+    // sends notifications for new alerts.  Only providers with alerts do anything.
+    fn notify_alerts(&self, _new_alerts: &[WxAlert]) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+pub struct PirateWeather {
+    conf: PirateWxConf,
+}
+impl PirateWeather {
+    pub fn new(conf: PirateWxConf) -> Self {
+        Self { conf }
+    }
+}
+impl WeatherProvider for PirateWeather {
+    fn name(&self) -> &'static str {
+        "pirate_wx"
+    }
+
+    async fn fetch(&self) -> Option<Wx> {
+        crate::get_weather(&self.conf).await
+    }
+
+    fn hourly_forecast_hours(&self) -> i64 {
+        self.conf.hourly_forecast_hours
+    }
+
+    async fn notify_alerts(&self, new_alerts: &[WxAlert]) {
+        crate::notify_wx_alerts(new_alerts, &self.conf).await;
+    }
+}
+
+#[derive(Debug, Deserialize, Clone )]
+pub struct OpenMeteoConf {
+    pub lat: f64,
+    pub long: f64,
+    #[serde( default = "default_wx_units" )]
+    pub units: String,
+    // forecast api url.  Change for a self hosted Open-Meteo server
+    #[serde( default = "default_open_meteo_url" )]
+    pub url: String,
+    // days of daily forecast, including today
+    #[serde( default = "default_open_meteo_forecast_days" )]
+    pub forecast_days: u32,
+    #[serde( default = "default_open_meteo_hourly_forecast_hours" )]
+    pub hourly_forecast_hours: i64,
+    #[serde( default = "default_open_meteo_interval" )]
+    pub interval: u64,
+    #[serde( default = "default_wx_interval_unit" )]
+    pub interval_unit: char,
+    #[serde( with = "crate::integer_to_chrono_time_delta_ms", default = "default_wx_offset" )]
+    pub offset: TimeDelta,
+    // cron expression or "every N<unit> [at <offset>]".  Used instead of interval / interval_unit / offset if provided
+    #[serde( default = "default_string" )]
+    pub schedule: String,
+    #[serde( with = "crate::integer_to_chrono_time_delta_ms", default = "default_wx_offset" )]
+    pub jitter: TimeDelta,
+}
+fn default_wx_units() -> String {
+    "us".to_string()
+}
+fn default_open_meteo_url() -> String {
+    "https://api.open-meteo.com/v1/forecast".to_string()
+}
+fn default_open_meteo_forecast_days() -> u32 {
+    7
+}
+fn default_open_meteo_hourly_forecast_hours() -> i64 {
+    48
+}
+fn default_open_meteo_interval() -> u64 {
+    // Open-Meteo current conditions are updated every 15 minutes
+    15
+}
+pub fn default_wx_interval_unit() -> char {
+    'm'
+}
+pub fn default_wx_offset() -> TimeDelta {
+    TimeDelta::zero()
+}
+pub fn default_string() -> String {
+    String::new()
+}
+
+#[derive(Debug, Deserialize, Clone )]
+pub struct WxStationConf {
+    // station JSON url, eg "http://192.168.1.20/get_livedata_info"
+    pub url: String,
+    // station location, stored with each reading
+    pub lat: f64,
+    pub long: f64,
+    // CurrentWx value (STATION_FIELDS) to JSON pointer of value in station response.  time is unix seconds (default now)
+    #[serde( default )]
+    pub fields: BTreeMap<String, String>,
+    #[serde( default = "default_wx_station_interval" )]
+    pub interval: u64,
+    #[serde( default = "default_wx_interval_unit" )]
+    pub interval_unit: char,
+    #[serde( with = "crate::integer_to_chrono_time_delta_ms", default = "default_wx_offset" )]
+    pub offset: TimeDelta,
+    // cron expression or "every N<unit> [at <offset>]".  Used instead of interval / interval_unit / offset if provided
+    #[serde( default = "default_string" )]
+    pub schedule: String,
+    #[serde( with = "crate::integer_to_chrono_time_delta_ms", default = "default_wx_offset" )]
+    pub jitter: TimeDelta,
+}
+fn default_wx_station_interval() -> u64 {
+    1
+}
+
+pub struct OpenMeteo {
+    conf: OpenMeteoConf,
+}
+impl OpenMeteo {
+    pub fn new(conf: OpenMeteoConf) -> Self {
+        Self { conf }
+    }
+
+    fn units(&self) -> OpenMeteoUnits {
+        OpenMeteoUnits::new(&self.conf.units)
+    }
+}
+impl WeatherProvider for OpenMeteo {
+    fn name(&self) -> &'static str {
+        "open_meteo"
+    }
+
+    async fn fetch(&self) -> Option<Wx> {
+        let units = self.units();
+        let mut query = vec![
+            ( "latitude", self.conf.lat.to_string() ),
+            ( "longitude", self.conf.long.to_string() ),
+            ( "current", OPEN_METEO_CURRENT.to_string() ),
+            ( "daily", OPEN_METEO_DAILY.to_string() ),
+            ( "forecast_days", self.conf.forecast_days.to_string() ),
+            ( "temperature_unit", units.temperature.to_string() ),
+            ( "wind_speed_unit", units.wind_speed.to_string() ),
+            ( "precipitation_unit", units.precipitation.to_string() ),
+            // daily times are local midnight, as Pirate Weather
+            ( "timezone", "auto".to_string() ),
+            ( "timeformat", "unixtime".to_string() ),
+        ];
+        if self.conf.hourly_forecast_hours > 0 {
+            query.push( ( "hourly", OPEN_METEO_HOURLY.to_string() ) );
+            query.push( ( "forecast_hours", self.conf.hourly_forecast_hours.to_string() ) );
+        }
+        let response = match reqwest::Client::new().get(&self.conf.url).query(&query).send().await {
+            Ok(response) => response,
+            Err(response_eff) => {
+                error!("Error in initial response from Open-Meteo. Err: {}", response_eff);
+                return None
+            },
+        };
+        if !response.status().is_success() {
+            // Open-Meteo explains errors (eg unknown variable) in a json "reason"
+            let status = response.status();
+            error!("Open-Meteo returned error code: {}. {}", status, response.text().await.unwrap_or_default());
+            return None
+        }
+        match response.json::<OpenMeteoResponse>().await {
+            Ok(open_meteo) => {
+                info!("Open-Meteo json retrieved and deserialized.");
+                Some( open_meteo.to_wx(&units) )
+            },
+            Err(json_eff) => {
+                error!("Open-Meteo Response code: OK, but unable to deserialize json response. Err: {}", json_eff);
+                None
+            },
+        }
+    }
+
+    fn hourly_forecast_hours(&self) -> i64 {
+        self.conf.hourly_forecast_hours
+    }
+}
+
+struct OpenMeteoUnits {
+    temperature: &'static str,
+    wind_speed: &'static str,
+    precipitation: &'static str,
+    // meters to visibility units (miles or km)
+    visibility_meters: f32,
+    // precipitation units (mm or inch) to accumulation units (cm or inch)
+    accumulation_scale: f32,
+}
+impl OpenMeteoUnits {
+    fn new(units: &str) -> Self {
+        // Pirate Weather units: us imperial, si metric with m/s, ca metric with km/h, uk metric with mph and miles
+        let (temperature, wind_speed, precipitation) = match units {
+            "us" => ( "fahrenheit", "mph", "inch" ),
+            "ca" => ( "celsius", "kmh", "mm" ),
+            "uk" => ( "celsius", "mph", "mm" ),
+            _ => ( "celsius", "ms", "mm" ),
+        };
+        let visibility_meters = if matches!(units, "us" | "uk") { METERS_PER_MILE } else { 1000.0 };
+        let accumulation_scale = if units == "us" { 1.0 } else { 0.1 };
+        Self { temperature, wind_speed, precipitation, visibility_meters, accumulation_scale }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenMeteoResponse {
+    latitude: f64,
+    longitude: f64,
+    #[serde( default )]
+    elevation: Option<f32>,
+    #[serde( default )]
+    timezone: Option<String>,
+    #[serde( default )]
+    utc_offset_seconds: Option<i64>,
+    current: OpenMeteoCurrent,
+    #[serde( default )]
+    hourly: Option<OpenMeteoHourly>,
+    #[serde( default )]
+    daily: Option<OpenMeteoDaily>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenMeteoCurrent {
+    time: i64,
+    // seconds precipitation is summed over
+    #[serde( default )]
+    interval: Option<i64>,
+    temperature_2m: Option<f32>,
+    relative_humidity_2m: Option<f32>,
+    apparent_temperature: Option<f32>,
+    dew_point_2m: Option<f32>,
+    precipitation: Option<f32>,
+    snowfall: Option<f32>,
+    weather_code: Option<f32>,
+    cloud_cover: Option<f32>,
+    pressure_msl: Option<f32>,
+    wind_speed_10m: Option<f32>,
+    wind_direction_10m: Option<f32>,
+    wind_gusts_10m: Option<f32>,
+    uv_index: Option<f32>,
+    visibility: Option<f32>,
+    is_day: Option<f32>,
+}
+
+// hourly and daily values are one array per variable, items matching time
+#[derive(Debug, Deserialize)]
+struct OpenMeteoHourly {
+    time: Vec<i64>,
+    #[serde( default )]
+    temperature_2m: Vec<Option<f32>>,
+    #[serde( default )]
+    relative_humidity_2m: Vec<Option<f32>>,
+    #[serde( default )]
+    apparent_temperature: Vec<Option<f32>>,
+    #[serde( default )]
+    dew_point_2m: Vec<Option<f32>>,
+    #[serde( default )]
+    precipitation: Vec<Option<f32>>,
+    #[serde( default )]
+    precipitation_probability: Vec<Option<f32>>,
+    #[serde( default )]
+    weather_code: Vec<Option<f32>>,
+    #[serde( default )]
+    cloud_cover: Vec<Option<f32>>,
+    #[serde( default )]
+    pressure_msl: Vec<Option<f32>>,
+    #[serde( default )]
+    wind_speed_10m: Vec<Option<f32>>,
+    #[serde( default )]
+    wind_direction_10m: Vec<Option<f32>>,
+    #[serde( default )]
+    wind_gusts_10m: Vec<Option<f32>>,
+    #[serde( default )]
+    uv_index: Vec<Option<f32>>,
+    #[serde( default )]
+    visibility: Vec<Option<f32>>,
+    #[serde( default )]
+    is_day: Vec<Option<f32>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenMeteoDaily {
+    time: Vec<i64>,
+    #[serde( default )]
+    weather_code: Vec<Option<f32>>,
+    #[serde( default )]
+    temperature_2m_max: Vec<Option<f32>>,
+    #[serde( default )]
+    temperature_2m_min: Vec<Option<f32>>,
+    #[serde( default )]
+    sunrise: Vec<Option<i64>>,
+    #[serde( default )]
+    sunset: Vec<Option<i64>>,
+    #[serde( default )]
+    uv_index_max: Vec<Option<f32>>,
+    #[serde( default )]
+    precipitation_sum: Vec<Option<f32>>,
+    #[serde( default )]
+    precipitation_probability_max: Vec<Option<f32>>,
+    #[serde( default )]
+    cloud_cover_mean: Vec<Option<f32>>,
+}
+
+impl OpenMeteoResponse {
+    fn to_wx(&self, units: &OpenMeteoUnits) -> Wx {
+        let current = &self.current;
+        let is_day = current.is_day.is_none_or( |is_day| is_day > 0.0 );
+        let (summary, icon, precip_type) = wmo_weather(current.weather_code, is_day);
+        // precipitation is the amount over interval seconds.  Intensity is per hour.
+        let precip_intensity = current.precipitation
+            .map( |precipitation| precipitation * 3600.0 / current.interval.filter( |interval| *interval > 0 ).unwrap_or(3600) as f32 );
+        let currently = CurrentWx {
+            time: unix_time(current.time),
+            summary,
+            icon,
+            precip_intensity,
+            precip_type: precip_type.filter( |_| precip_intensity.is_some_and( |intensity| intensity > 0.0 )
+                || current.snowfall.is_some_and( |snowfall| snowfall > 0.0 ) ),
+            temperature: current.temperature_2m,
+            apparent_temperature: current.apparent_temperature,
+            dew_point: current.dew_point_2m,
+            humidity: current.relative_humidity_2m.map(percent),
+            pressure: current.pressure_msl,
+            wind_speed: current.wind_speed_10m,
+            wind_gust: current.wind_gusts_10m,
+            wind_bearing: current.wind_direction_10m,
+            cloud_cover: current.cloud_cover.map(percent),
+            uv_index: current.uv_index,
+            visibility: current.visibility.map( |meters| meters / units.visibility_meters ),
+            feels_like: current.apparent_temperature,
+            ..Default::default()
+        };
+
+        let daily_data = self.daily.as_ref().map( |daily| daily.time.iter().enumerate().map( |(index, time)| {
+            let (summary, icon, _) = wmo_weather( at(&daily.weather_code, index), true );
+            DailyWxData {
+                time: unix_time(*time),
+                summary,
+                icon,
+                sunrise_time: at(&daily.sunrise, index).map(unix_time),
+                sunset_time: at(&daily.sunset, index).map(unix_time),
+                precip_accumulation: at(&daily.precipitation_sum, index).map( |sum| sum * units.accumulation_scale ),
+                precip_probability: at(&daily.precipitation_probability_max, index).map(percent),
+                temperature_min: at(&daily.temperature_2m_min, index),
+                temperature_max: at(&daily.temperature_2m_max, index),
+                cloud_cover: at(&daily.cloud_cover_mean, index).map(percent),
+                uv_index: at(&daily.uv_index_max, index),
+                ..Default::default()
+            }
+        }).collect() ).unwrap_or_default();
+
+        let hourly = self.hourly.as_ref().map( |hourly| HourlyWx { data: hourly.time.iter().enumerate().map( |(index, time)| {
+            let (summary, icon, precip_type) = wmo_weather( at(&hourly.weather_code, index), at(&hourly.is_day, index).is_none_or( |is_day| is_day > 0.0 ) );
+            let precipitation = at(&hourly.precipitation, index);
+            HourlyWxData {
+                time: unix_time(*time),
+                summary,
+                icon,
+                // hourly precipitation is the amount over the hour, so also the intensity
+                precip_intensity: precipitation,
+                precip_probability: at(&hourly.precipitation_probability, index).map(percent),
+                precip_accumulation: precipitation.map( |amount| amount * units.accumulation_scale ),
+                precip_type: precip_type.filter( |_| precipitation.is_some_and( |precipitation| precipitation > 0.0 ) ),
+                temperature: at(&hourly.temperature_2m, index),
+                apparent_temperature: at(&hourly.apparent_temperature, index),
+                dew_point: at(&hourly.dew_point_2m, index),
+                humidity: at(&hourly.relative_humidity_2m, index).map(percent),
+                pressure: at(&hourly.pressure_msl, index),
+                wind_speed: at(&hourly.wind_speed_10m, index),
+                wind_gust: at(&hourly.wind_gusts_10m, index),
+                wind_bearing: at(&hourly.wind_direction_10m, index),
+                cloud_cover: at(&hourly.cloud_cover, index).map(percent),
+                uv_index: at(&hourly.uv_index, index),
+                visibility: at(&hourly.visibility, index).map( |meters| meters / units.visibility_meters ),
+                ..Default::default()
+            }
+        }).collect() });
+
+        Wx {
+            latitude: self.latitude,
+            longitude: self.longitude,
+            timezone: self.timezone.clone(),
+            offset: self.utc_offset_seconds.map( |seconds| seconds as f32 / 3600.0 ),
+            elevation: self.elevation,
+            currently,
+            daily: DailyWx { data: daily_data },
+            hourly,
+            minutely: None,
+            alerts: Vec::new(),
+        }
+    }
+}
+
+pub struct WxStation {
+    conf: WxStationConf,
+}
+impl WxStation {
+    pub fn new(conf: WxStationConf) -> Self {
+        Self { conf }
+    }
+
+    fn value(&self, station: &serde_json::Value, field: &str) -> Option<f32> {
+        // value at the JSON pointer configured for field
+        json_number( station, self.conf.fields.get(field)? ).map( |value| value as f32 )
+    }
+}
+impl WeatherProvider for WxStation {
+    fn name(&self) -> &'static str {
+        "wx_station"
+    }
+
+    async fn fetch(&self) -> Option<Wx> {
+        let station = get_station_json( &self.conf.url, "Weather station" ).await?;
+        let missing: Vec<&String> = self.conf.fields.iter().filter( |(field, _)| self.value(&station, field).is_none() )
+            .map( |(field, _)| field ).collect();
+        if !missing.is_empty() {
+            debug!("Weather station response has no number for {:?}", missing);
+        }
+        let time = self.value(&station, "time").map( |time| unix_time(time as i64) ).unwrap_or( Utc::now() );
+        let currently = CurrentWx {
+            time,
+            temperature: self.value(&station, "temperature"),
+            apparent_temperature: self.value(&station, "apparent_temperature"),
+            dew_point: self.value(&station, "dew_point"),
+            humidity: self.value(&station, "humidity").map(percent),
+            pressure: self.value(&station, "pressure"),
+            wind_speed: self.value(&station, "wind_speed"),
+            wind_gust: self.value(&station, "wind_gust"),
+            wind_bearing: self.value(&station, "wind_bearing"),
+            precip_intensity: self.value(&station, "precip_intensity"),
+            uv_index: self.value(&station, "uv_index"),
+            visibility: self.value(&station, "visibility"),
+            current_day_liquid: self.value(&station, "current_day_liquid"),
+            ..Default::default()
+        };
+        info!("Weather station json retrieved.");
+        Some( Wx {
+            latitude: self.conf.lat,
+            longitude: self.conf.long,
+            timezone: None,
+            offset: None,
+            elevation: None,
+            currently,
+            daily: DailyWx { data: Vec::new() },
+            hourly: None,
+            minutely: None,
+            alerts: Vec::new(),
+        })
+    }
+}
+
+// FUNCTIONS
+
+pub async fn weather_to_mysql<P: WeatherProvider>(solar_pool: Option<sqlx::Pool<sqlx::MySql>>, provider: P, mut get_wx_job: Job,
+    latest: Latest) {
+    info!("Weather provider {} started.", provider.name());
+    while get_wx_job.tick().await.is_some() {
+        let wx_opt = provider.fetch().await;
+
+        if let Some(wx) = wx_opt {
+            latest.set_wx(&wx.currently);
+            crate::insert_pirate_wx_forecast_to_mysql(&wx, &solar_pool, provider.hourly_forecast_hours() ).await;
+            let new_alerts = crate::insert_pirate_wx_alerts_to_mysql(&wx, &solar_pool ).await;
+            provider.notify_alerts(&new_alerts).await;
+            crate::insert_pirate_wx_to_mysql(wx, &solar_pool ).await;
+        }
+    }
+}
+
+pub async fn get_station_json(url: &str, source: &str) -> Option<serde_json::Value> {
+    // JSON response of a local station.  None (after logging why) if it couldn't be retrieved
+    let response = match reqwest::Client::new().get(url).send().await {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => {
+            error!("{} returned error code: {}", source, response.status());
+            return None
+        },
+        Err(response_eff) => {
+            error!("Error in initial response from {}. Err: {}", source.to_lowercase(), response_eff);
+            return None
+        },
+    };
+    match response.json::<serde_json::Value>().await {
+        Ok(station) => Some(station),
+        Err(json_eff) => {
+            error!("{} response is not json. Err: {}", source, json_eff);
+            None
+        },
+    }
+}
+
+pub fn json_number(json: &serde_json::Value, pointer: &str) -> Option<f64> {
+    // number at JSON pointer.  Numbers may be strings with units, eg "21.5 C"
+    match json.pointer(pointer)? {
+        serde_json::Value::Number(number) => number.as_f64(),
+        serde_json::Value::String(text) => {
+            let text = text.trim();
+            let end = text.find( |c: char| !( c.is_ascii_digit() || matches!(c, '.' | '-' | '+') ) ).unwrap_or(text.len());
+            text[..end].parse::<f64>().ok()
+        },
+        _ => None,
+    }
+}
+
+fn wmo_weather(code: Option<f32>, is_day: bool) -> (Option<String>, Option<String>, Option<String>) {
+    // WMO weather interpretation code (Open-Meteo weather_code) to summary, Pirate Weather icon and precip type
+    let day_night = |day: &str, night: &str| if is_day { day.to_string() } else { night.to_string() };
+    let (summary, icon, precip_type) = match code.map( |code| code.round() as i32 ) {
+        Some(0) => ( "Clear", day_night("clear-day", "clear-night"), None ),
+        Some(1) => ( "Mostly Clear", day_night("clear-day", "clear-night"), None ),
+        Some(2) => ( "Partly Cloudy", day_night("partly-cloudy-day", "partly-cloudy-night"), None ),
+        Some(3) => ( "Cloudy", "cloudy".to_string(), None ),
+        Some(45 | 48) => ( "Fog", "fog".to_string(), None ),
+        Some(51 | 53 | 55) => ( "Drizzle", "rain".to_string(), Some("rain") ),
+        Some(56 | 57) => ( "Freezing Drizzle", "sleet".to_string(), Some("sleet") ),
+        Some(61) => ( "Light Rain", "rain".to_string(), Some("rain") ),
+        Some(63) => ( "Rain", "rain".to_string(), Some("rain") ),
+        Some(65) => ( "Heavy Rain", "rain".to_string(), Some("rain") ),
+        Some(66 | 67) => ( "Freezing Rain", "sleet".to_string(), Some("sleet") ),
+        Some(71) => ( "Light Snow", "snow".to_string(), Some("snow") ),
+        Some(73 | 77) => ( "Snow", "snow".to_string(), Some("snow") ),
+        Some(75) => ( "Heavy Snow", "snow".to_string(), Some("snow") ),
+        Some(80..=82) => ( "Rain Showers", "rain".to_string(), Some("rain") ),
+        Some(85 | 86) => ( "Snow Showers", "snow".to_string(), Some("snow") ),
+        Some(95) => ( "Thunderstorm", "thunderstorm".to_string(), Some("rain") ),
+        Some(96 | 99) => ( "Thunderstorm with Hail", "hail".to_string(), Some("hail") ),
+        _ => return ( None, None, None ),
+    };
+    ( Some( summary.to_string() ), Some(icon), precip_type.map( |precip_type| precip_type.to_string() ) )
+}
+
+fn at<T: Copy>(values: &[Option<T>], index: usize) -> Option<T> {
+    values.get(index).copied().flatten()
+}
+
+fn percent(value: f32) -> f32 {
+    value / 100.0
+}
+
+fn unix_time(seconds: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(seconds, 0).unwrap_or_default()
+}
+
+pub fn verify_open_meteo_conf(open_meteo_conf: &OpenMeteoConf, errors: &mut Vec<ConfigError>) {
+    // verifies location, units, url, forecast lengths and schedule.  Adds a ConfigError to errors for each problem
+    verify_location("open_meteo", open_meteo_conf.lat, open_meteo_conf.long, errors);
+    if !WX_UNITS.contains( &open_meteo_conf.units.as_str() ) {
+        errors.push( ConfigError::invalid("open_meteo.units", &open_meteo_conf.units, "Must be 'us', 'ca', 'uk' or 'si'.") );
+    }
+    if reqwest::Url::parse(&open_meteo_conf.url).is_err() {
+        errors.push( ConfigError::invalid("open_meteo.url", &open_meteo_conf.url, "Must be a url.") );
+    }
+    if !( 1..=16 ).contains(&open_meteo_conf.forecast_days) {
+        errors.push( ConfigError::invalid("open_meteo.forecast_days", open_meteo_conf.forecast_days, "Must be 1 to 16.") );
+    }
+    if !( 0..=384 ).contains(&open_meteo_conf.hourly_forecast_hours) {
+        errors.push( ConfigError::invalid("open_meteo.hourly_forecast_hours", open_meteo_conf.hourly_forecast_hours, "Must be 0 to 384.") );
+    }
+    verify_schedule("open_meteo", &open_meteo_conf.schedule, open_meteo_conf.interval, open_meteo_conf.interval_unit,
+        open_meteo_conf.offset, errors);
+}
+
+pub fn verify_wx_station_conf(wx_station_conf: &WxStationConf, errors: &mut Vec<ConfigError>) {
+    // verifies url, location, fields and schedule.  Adds a ConfigError to errors for each problem
+    if !reqwest::Url::parse(&wx_station_conf.url).is_ok_and( |url| matches!( url.scheme(), "http" | "https" ) ) {
+        errors.push( ConfigError::invalid("wx_station.url", &wx_station_conf.url, "Must be an http:// url of the station's JSON.") );
+    }
+    verify_location("wx_station", wx_station_conf.lat, wx_station_conf.long, errors);
+    if wx_station_conf.fields.is_empty() {
+        errors.push( ConfigError::missing("wx_station.fields", "Map at least one value, eg temperature: \"/outdoor/temperature\".") );
+    }
+    for (field, pointer) in &wx_station_conf.fields {
+        let key = format!("wx_station.fields.{}", field);
+        if !STATION_FIELDS.contains( &field.as_str() ) {
+            errors.push( ConfigError::invalid( &key, field, &format!("Must be one of: {}", STATION_FIELDS.join(", ")) ) );
+        } else if !pointer.starts_with('/') {
+            errors.push( ConfigError::invalid( &key, pointer, "Must be a JSON pointer, eg \"/outdoor/temperature\"." ) );
+        }
+    }
+    verify_schedule("wx_station", &wx_station_conf.schedule, wx_station_conf.interval, wx_station_conf.interval_unit,
+        wx_station_conf.offset, errors);
+}
+
+fn verify_location(section: &str, lat: f64, long: f64, errors: &mut Vec<ConfigError>) {
+    if !( -90.0..=90.0 ).contains(&lat) {
+        errors.push( ConfigError::invalid( &format!("{}.lat", section), lat, "Must be from -90 to 90 degrees." ) );
+    }
+    if !( -180.0..=180.0 ).contains(&long) {
+        errors.push( ConfigError::invalid( &format!("{}.long", section), long, "Must be from -180 to 180 degrees." ) );
+    }
+}
+
+pub fn verify_schedule(section: &str, schedule: &str, interval: u64, interval_unit: char, offset: TimeDelta, errors: &mut Vec<ConfigError>) {
+    if !matches!( interval_unit, 'd' | 'h' | 'm' | 's') {
+        errors.push( ConfigError::invalid( &format!("{}.interval_unit", section), interval_unit, "Must be 'd', 'h', 'm', or 's'." ) );
+    } else if let Err(schedule_eff) = Schedule::from_conf(schedule, interval, interval_unit, offset) {
+        errors.push( ConfigError::invalid( &format!("{}.schedule", section), schedule, &schedule_eff.to_string() ) );
+    }
+}